@@ -0,0 +1,145 @@
+use crate::evaluator::RuntimeError;
+use crate::types::Expr;
+
+/// Checks that every `recur` in `expr` is in tail position of an enclosing `loop` or `fn*`,
+/// so that misplaced `recur`s are reported before anything is evaluated.
+///
+/// `expr` itself is treated as being in tail position of a recur target when `in_tail_position`
+/// is true, which is the case for `loop` and `fn*` bodies.
+pub fn check_recur_positions(expr: &Expr, in_tail_position: bool) -> Result<(), RuntimeError> {
+    match expr {
+        Expr::List(elems) => {
+            let mut elems_iter = elems.iter();
+            let head = match elems_iter.next() {
+                Some(head) => head,
+                None => return Ok(()),
+            };
+            let args = elems_iter.collect::<Vec<_>>();
+
+            match head {
+                Expr::Symbol(s) => match s.as_str() {
+                    "recur" => {
+                        if !in_tail_position {
+                            return Err(RuntimeError::RecurNotInTailPosition);
+                        }
+                        check_all(args, false)
+                    }
+                    "quote" => Ok(()),
+                    "if" => {
+                        if let Some((guard, branches)) = args.split_first() {
+                            check_recur_positions(guard, false)?;
+                            check_all(branches.iter().copied(), in_tail_position)?;
+                        }
+                        Ok(())
+                    }
+                    "do" => check_body(&args, in_tail_position),
+                    "let*" => {
+                        if let Some((bindings, body)) = args.split_first() {
+                            check_recur_positions(bindings, false)?;
+                            check_body(body, in_tail_position)?;
+                        }
+                        Ok(())
+                    }
+                    // `loop` starts a new recur target, so its body is always in tail position
+                    "loop" => {
+                        if let Some((bindings, body)) = args.split_first() {
+                            check_recur_positions(bindings, false)?;
+                            check_body(body, true)?;
+                        }
+                        Ok(())
+                    }
+                    "fn*" => check_fn(&args),
+                    // Methods are function bodies after the name and dispatch value
+                    "defmethod" => check_fn(args.get(2..).unwrap_or_default()),
+                    // Each method is a list starting with its name, which `check_fn` skips like a `fn*` name
+                    "extend-type" | "defrecord" => {
+                        for method in args.iter().skip(1) {
+                            if let Expr::List(method_exprs) = method {
+                                check_fn(&method_exprs.iter().collect::<Vec<_>>())?;
+                            }
+                        }
+                        Ok(())
+                    }
+                    _ => check_all(args, false),
+                },
+                head => {
+                    check_recur_positions(head, false)?;
+                    check_all(args, false)
+                }
+            }
+        }
+        Expr::Vector(elems) | Expr::Set(elems) => check_all(elems, false),
+        Expr::HashMap(pairs) => check_all(pairs.iter().flat_map(|(key, value)| [key, value]), false),
+        Expr::Quote(_) | Expr::Tagged(..) => Ok(()),
+        Expr::Quasiquote(inner) | Expr::Unquote(inner) | Expr::SpliceUnquote(inner) => check_recur_positions(inner, false),
+        Expr::Integer(_) | Expr::Float(_) | Expr::Char(_) | Expr::String(_) | Expr::Symbol(_) | Expr::Keyword(_) | Expr::Nil | Expr::Boolean(_) => Ok(()),
+    }
+}
+
+fn check_all<'a, T>(exprs: T, in_tail_position: bool) -> Result<(), RuntimeError>
+    where T: IntoIterator<Item=&'a Expr>
+{
+    for expr in exprs {
+        check_recur_positions(expr, in_tail_position)?;
+    }
+    Ok(())
+}
+
+/// Only the last expression of a body is in tail position.
+fn check_body(body: &[&Expr], in_tail_position: bool) -> Result<(), RuntimeError> {
+    if let Some((last, init)) = body.split_last() {
+        check_all(init.iter().copied(), false)?;
+        check_recur_positions(last, in_tail_position)?;
+    }
+    Ok(())
+}
+
+/// Checks every arity of a `fn*`, whose bodies are recur targets.
+fn check_fn(args: &[&Expr]) -> Result<(), RuntimeError> {
+    let args = match args.split_first() {
+        Some((Expr::Symbol(_), rest)) => rest,
+        _ => args,
+    };
+
+    match args.first() {
+        Some(Expr::List(clause)) if matches!(clause.front(), Some(Expr::Vector(_))) => {
+            for clause in args {
+                if let Expr::List(clause_exprs) = clause {
+                    check_body(&clause_exprs.iter().skip(1).collect::<Vec<_>>(), true)?;
+                }
+            }
+            Ok(())
+        }
+        Some(_) => check_body(&args[1..], true),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_text_to_expression;
+
+    use super::*;
+
+    fn check(src: &str) -> Result<(), RuntimeError> {
+        check_recur_positions(&parse_text_to_expression(src).expect("src to parse"), false)
+    }
+
+    #[test]
+    fn test_tail_positions() {
+        assert_eq!(Ok(()), check("(loop [i 0] (if (< i 10) (recur (+ i 1)) i))"));
+        assert_eq!(Ok(()), check("(fn* [n acc] (if (= n 0) acc (do (prn n) (recur (- n 1) (* acc n)))))"));
+        assert_eq!(Ok(()), check("(fn* f ([x] (recur x 1)) ([x y] (let* [z 1] (recur z))))"));
+        assert_eq!(Ok(()), check("(defmethod f :a [n] (if (= n 0) 0 (recur (- n 1))))"));
+        assert_eq!(Ok(()), check("(extend-type Integer P (f [n] (recur n)) (g ([n] (recur n))))"));
+    }
+
+    #[test]
+    fn test_non_tail_positions() {
+        assert_eq!(Err(RuntimeError::RecurNotInTailPosition), check("(recur 1)"));
+        assert_eq!(Err(RuntimeError::RecurNotInTailPosition), check("(loop [i 0] (+ 1 (recur i)))"));
+        assert_eq!(Err(RuntimeError::RecurNotInTailPosition), check("(loop [i 0] (do (recur i) i))"));
+        assert_eq!(Err(RuntimeError::RecurNotInTailPosition), check("(fn* [x] (if (recur x) 1 2))"));
+        assert_eq!(Err(RuntimeError::RecurNotInTailPosition), check("(loop [i (recur 1)] i)"));
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+
+use crate::env::Env;
+use crate::evaluator::{apply_function_to_values, RuntimeError};
+use crate::sync::{Lock, Shared};
+use crate::types::{FunctionBody, Value};
+
+/// A mutable reference that can be shared, including between threads with the `sync` feature.
+///
+/// Every change goes through compare-and-set, so `swap!` can call its function without holding a
+/// lock and retry if another thread changed the atom in the meantime. A validator can reject new
+/// values, and watches are called after each change with the old and new values.
+pub struct Atom {
+    /// The current value, along with how many times it has changed, which is how `swap` tells
+    /// whether another update happened while it was computing the new value
+    state: Lock<(u64, Value)>,
+    validator: Lock<Option<FunctionBody>>,
+    watches: Lock<Vec<(Value, FunctionBody)>>,
+}
+
+impl Atom {
+    pub fn new(value: Value) -> Self {
+        Atom { state: Lock::new((0, value)), validator: Lock::new(None), watches: Lock::new(vec![]) }
+    }
+
+    pub fn get(&self) -> Value {
+        self.state.borrow().1.clone()
+    }
+
+    /// Sets the value without checking what it was, returning the old value.
+    pub fn reset(self: &Shared<Self>, env: &Env, value: Value) -> Result<Value, RuntimeError> {
+        self.validate(env, &value)?;
+        let old = {
+            let mut state = self.state.borrow_mut();
+            state.0 += 1;
+            std::mem::replace(&mut state.1, value.clone())
+        };
+        self.notify_watches(env, &old, &value)?;
+        Ok(old)
+    }
+
+    /// Sets the value only if it's currently equal to `expected`, returning whether it was set.
+    pub fn compare_and_set(self: &Shared<Self>, env: &Env, expected: &Value, value: Value) -> Result<bool, RuntimeError> {
+        self.validate(env, &value)?;
+        let old = {
+            let mut state = self.state.borrow_mut();
+            if state.1 != *expected {
+                return Ok(false);
+            }
+            state.0 += 1;
+            std::mem::replace(&mut state.1, value.clone())
+        };
+        self.notify_watches(env, &old, &value)?;
+        Ok(true)
+    }
+
+    /// Replaces the value with `update(value)`, retrying with the latest value if the atom changed
+    /// while `update` was running. Returns the old and new values.
+    pub fn swap<F>(self: &Shared<Self>, env: &Env, mut update: F) -> Result<(Value, Value), RuntimeError>
+        where F: FnMut(Value) -> Result<Value, RuntimeError>
+    {
+        loop {
+            let (version, old) = self.state.borrow().clone();
+            let new = update(old.clone())?;
+            self.validate(env, &new)?;
+            {
+                let mut state = self.state.borrow_mut();
+                if state.0 != version {
+                    continue;
+                }
+                *state = (version + 1, new.clone());
+            }
+            self.notify_watches(env, &old, &new)?;
+            return Ok((old, new));
+        }
+    }
+
+    /// Sets the function that every new value must pass, or removes it. The current value must pass too.
+    pub fn set_validator(self: &Shared<Self>, env: &Env, validator: Option<FunctionBody>) -> Result<(), RuntimeError> {
+        if let Some(validator) = &validator {
+            check_valid(validator, env, &self.get())?;
+        }
+        *self.validator.borrow_mut() = validator;
+        Ok(())
+    }
+
+    /// Adds a function that's called as `(watch key atom old new)` after each change, replacing any
+    /// watch with the same key.
+    pub fn add_watch(&self, key: Value, watch: FunctionBody) {
+        let mut watches = self.watches.borrow_mut();
+        watches.retain(|(existing, _)| *existing != key);
+        watches.push((key, watch));
+    }
+
+    pub fn remove_watch(&self, key: &Value) {
+        self.watches.borrow_mut().retain(|(existing, _)| existing != key);
+    }
+
+    fn validate(&self, env: &Env, value: &Value) -> Result<(), RuntimeError> {
+        // Cloned so the validator can use the atom without deadlocking
+        let validator = self.validator.borrow().clone();
+        match validator {
+            Some(validator) => check_valid(&validator, env, value),
+            None => Ok(()),
+        }
+    }
+
+    fn notify_watches(self: &Shared<Self>, env: &Env, old: &Value, new: &Value) -> Result<(), RuntimeError> {
+        let watches = self.watches.borrow().clone();
+        for (key, watch) in watches {
+            let args = VecDeque::from([key, Value::Atom(self.clone()), old.clone(), new.clone()]);
+            apply_function_to_values(&watch, args, env)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_valid(validator: &FunctionBody, env: &Env, value: &Value) -> Result<(), RuntimeError> {
+    match apply_function_to_values(validator, VecDeque::from([value.clone()]), env)?.is_truthy() {
+        true => Ok(()),
+        false => Err(RuntimeError::InvalidAtomState(value.clone())),
+    }
+}
+
+impl Debug for Atom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // The value isn't printed, since it can contain the atom itself
+        f.debug_struct("Atom").finish_non_exhaustive()
+    }
+}
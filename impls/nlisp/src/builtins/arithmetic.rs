@@ -7,19 +7,30 @@ use crate::types::{FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("+".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(add)
+        FunctionBody::BuiltinValues("+", add)
     ));
     env.insert("-".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(sub)
+        FunctionBody::BuiltinValues("-", sub)
     ));
     env.insert("*".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(mul)
+        FunctionBody::BuiltinValues("*", mul)
     ));
     env.insert("/".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(div)
+        FunctionBody::BuiltinValues("/", div)
     ));
 }
 
+/// Both operands as floats, when either is a float and the other is a float or an integer. An
+/// operation on two integers gives an integer, and any other mix of numbers gives a float.
+pub(super) fn float_operands(val_a: &Value, val_b: &Value) -> Option<(f64, f64)> {
+    match (val_a, val_b) {
+        (Value::Float(num_a), Value::Float(num_b)) => Some((*num_a, *num_b)),
+        (Value::Float(num_a), Value::Integer(num_b)) => Some((*num_a, *num_b as f64)),
+        (Value::Integer(num_a), Value::Float(num_b)) => Some((*num_a as f64, *num_b)),
+        _ => None,
+    }
+}
+
 fn add(_env: &Env, mut arg_values: VecDeque<Value>) -> Result<Value, RuntimeError> {
     assert_args_length(&arg_values, 2)?;
 
@@ -30,7 +41,10 @@ fn add(_env: &Env, mut arg_values: VecDeque<Value>) -> Result<Value, RuntimeErro
         (Value::Integer(num_a), Value::Integer(num_b)) => {
             Ok(Value::Integer(num_a + num_b))
         }
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (val_a, val_b) => match float_operands(&val_a, &val_b) {
+            Some((num_a, num_b)) => Ok(Value::Float(num_a + num_b)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -44,7 +58,10 @@ fn sub(_env: &Env, mut arg_values: VecDeque<Value>) -> Result<Value, RuntimeErro
         (Value::Integer(num_a), Value::Integer(num_b)) => {
             Ok(Value::Integer(num_a - num_b))
         }
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (val_a, val_b) => match float_operands(&val_a, &val_b) {
+            Some((num_a, num_b)) => Ok(Value::Float(num_a - num_b)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -58,7 +75,10 @@ fn mul(_env: &Env, mut arg_values: VecDeque<Value>) -> Result<Value, RuntimeErro
         (Value::Integer(num_a), Value::Integer(num_b)) => {
             Ok(Value::Integer(num_a * num_b))
         }
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (val_a, val_b) => match float_operands(&val_a, &val_b) {
+            Some((num_a, num_b)) => Ok(Value::Float(num_a * num_b)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -72,12 +92,17 @@ fn div(_env: &Env, mut arg_values: VecDeque<Value>) -> Result<Value, RuntimeErro
         (Value::Integer(num_a), Value::Integer(num_b)) => {
             Ok(Value::Integer(num_a / num_b))
         }
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (val_a, val_b) => match float_operands(&val_a, &val_b) {
+            Some((num_a, num_b)) => Ok(Value::Float(num_a / num_b)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::rep;
+
     use super::*;
 
     mod test_add {
@@ -117,5 +142,25 @@ mod tests {
             ]);
             assert_eq!(Err(RuntimeError::IncorrectType(TypeError::Misc)), add(&env, values));
         }
+
+        #[test]
+        fn test_floats() {
+            let env = Env::default();
+            let values = VecDeque::from([
+                Value::Float(1.5),
+                Value::Integer(2)
+            ]);
+            assert_eq!(Ok(Value::Float(3.5)), add(&env, values));
+        }
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let env = Env::default();
+        assert_eq!("0.5\n", rep("(- 2.5 2)", &env).unwrap());
+        assert_eq!("7.5\n", rep("(* 3 2.5)", &env).unwrap());
+        assert_eq!("2.5\n", rep("(/ 5 2.0)", &env).unwrap());
+        // Dividing two integers still gives an integer
+        assert_eq!("2\n", rep("(/ 5 2)", &env).unwrap());
     }
 }
\ No newline at end of file
@@ -1,18 +1,39 @@
-use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::rc::Rc;
 
-use crate::builtins::assert_args_length;
+use crate::atom::Atom;
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
 use crate::Env;
-use crate::evaluator::{RuntimeError, TypeError};
+use crate::evaluator::{apply_function_to_values, RuntimeError, TypeError};
+use crate::sync::{self, Shared};
 use crate::types::{FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("atom".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(atom)
+        FunctionBody::BuiltinValues("atom", atom)
     ));
     env.insert("deref".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(deref)
+        FunctionBody::BuiltinValues("deref", deref)
+    ));
+    env.insert("reset!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("reset!", reset)
+    ));
+    env.insert("swap!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("swap!", swap)
+    ));
+    env.insert("swap-vals!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("swap-vals!", swap_vals)
+    ));
+    env.insert("compare-and-set!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("compare-and-set!", compare_and_set)
+    ));
+    env.insert("add-watch".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("add-watch", add_watch)
+    ));
+    env.insert("remove-watch".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("remove-watch", remove_watch)
+    ));
+    env.insert("set-validator!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("set-validator!", set_validator)
     ));
 }
 
@@ -20,17 +41,198 @@ fn atom(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
     assert_args_length(&args, 1)?;
 
     let val = args.pop_front().expect("atom to have one argument");
-    Ok(Value::Atom(Rc::new(RefCell::new(val))))
+    Ok(Value::Atom(Shared::new(Atom::new(val))))
 }
 
+/// The builtin definition for `deref`. With the `sync` feature, futures and promises can be
+/// dereferenced too, optionally with a timeout, `(deref p timeout-ms timeout-val)`.
 fn deref(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    #[cfg(feature = "sync")]
+    if let Some(Value::Promise(_)) = args.front() {
+        return crate::builtins::concurrency::deref_promise(args);
+    }
     assert_args_length(&args, 1)?;
 
     let arg = args.pop_front().expect("deref to have one argument");
     match arg {
         Value::Atom(val) => {
-            Ok(val.borrow().clone())
+            Ok(val.get())
         }
         _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
     }
-}
\ No newline at end of file
+}
+
+fn pop_atom(args: &mut VecDeque<Value>) -> Result<Shared<Atom>, RuntimeError> {
+    match args.pop_front().expect("an atom to be present") {
+        Value::Atom(atom) => Ok(atom),
+        value => Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "an atom", value })),
+    }
+}
+
+/// The builtin definition for `reset!`, which sets an atom's value and returns the new value.
+fn reset(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+
+    let atom = pop_atom(&mut args)?;
+    let value = args.pop_front().expect("reset! to have a value");
+    atom.reset(env, value.clone())?;
+    Ok(value)
+}
+
+fn pop_function(args: &mut VecDeque<Value>) -> Result<FunctionBody, RuntimeError> {
+    match args.pop_front().expect("a function to be present") {
+        Value::Function(function_body) => Ok(function_body),
+        _ => Err(RuntimeError::CannotApplyNonFunction),
+    }
+}
+
+/// Swaps an atom's value for the result of calling the function in `args` with it and any extra
+/// arguments, returning the old and new values.
+fn swap_values(env: &Env, mut args: VecDeque<Value>) -> Result<(Value, Value), RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+
+    let atom = pop_atom(&mut args)?;
+    let function_body = pop_function(&mut args)?;
+    atom.swap(env, |old| {
+        let mut call_args = args.clone();
+        call_args.push_front(old);
+        apply_function_to_values(&function_body, call_args, env)
+    })
+}
+
+/// The builtin definition for `swap!`, e.g. `(swap! counter + 1)`, which sets an atom to the
+/// result of calling the function with its current value and any extra arguments. The function
+/// can be called more than once if another thread changes the atom while it's running.
+fn swap(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    let (_, new) = swap_values(env, args)?;
+    Ok(new)
+}
+
+/// The builtin definition for `swap-vals!`, which is like `swap!` but returns `[old new]`.
+fn swap_vals(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    let (old, new) = swap_values(env, args)?;
+    Ok(Value::Vector(sync::Vector::from_iter([old, new])))
+}
+
+/// The builtin definition for `compare-and-set!`, which sets an atom's value only if its current
+/// value equals the expected one, returning whether it did.
+fn compare_and_set(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 3)?;
+
+    let atom = pop_atom(&mut args)?;
+    let expected = args.pop_front().expect("compare-and-set! to have an expected value");
+    let value = args.pop_front().expect("compare-and-set! to have a new value");
+    Ok(Value::Boolean(atom.compare_and_set(env, &expected, value)?))
+}
+
+/// The builtin definition for `add-watch`, e.g. `(add-watch config :reload (fn* [key atom old new] ...))`.
+/// The function is called after every change to the atom. Adding a watch with the same key again
+/// replaces it.
+fn add_watch(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 3)?;
+
+    let atom = pop_atom(&mut args)?;
+    let key = args.pop_front().expect("add-watch to have a key");
+    atom.add_watch(key, pop_function(&mut args)?);
+    Ok(Value::Atom(atom))
+}
+
+fn remove_watch(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+
+    let atom = pop_atom(&mut args)?;
+    atom.remove_watch(&args.pop_front().expect("remove-watch to have a key"));
+    Ok(Value::Atom(atom))
+}
+
+/// The builtin definition for `set-validator!`. Once set, a change that the validator returns a
+/// falsy value for fails with an error and leaves the atom unchanged. nil removes the validator.
+fn set_validator(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+
+    let atom = pop_atom(&mut args)?;
+    let validator = match args.front() {
+        Some(Value::Nil) => None,
+        _ => Some(pop_function(&mut args)?),
+    };
+    atom.set_validator(env, validator)?;
+    Ok(Value::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_reset_and_swap() {
+        let env = Env::default();
+        rep("(def! a (atom 1))", &env).unwrap();
+        assert_eq!("5\n", rep("(reset! a 5)", &env).unwrap());
+        assert_eq!("8\n", rep("(swap! a + 3)", &env).unwrap());
+        assert_eq!("8\n", rep("@a", &env).unwrap());
+        assert_eq!(
+            Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "an atom", value: Value::Integer(1) })),
+            rep("(swap! 1 +)", &env)
+        );
+    }
+
+    #[test]
+    fn test_atom_equality() {
+        let env = Env::default();
+        rep("(def! a (atom 1))", &env).unwrap();
+        assert_eq!("true\n", rep("(= a a)", &env).unwrap());
+        assert_eq!("false\n", rep("(= a (atom 1))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_compare_and_set() {
+        let env = Env::default();
+        rep("(def! a (atom [1 2]))", &env).unwrap();
+        assert_eq!("false\n", rep("(compare-and-set! a [1] :x)", &env).unwrap());
+        assert_eq!("true\n", rep("(compare-and-set! a (list 1 2) :x)", &env).unwrap());
+        assert_eq!(":x\n", rep("@a", &env).unwrap());
+    }
+
+    #[test]
+    fn test_swap_vals() {
+        let env = Env::default();
+        rep("(def! a (atom 1))", &env).unwrap();
+        assert_eq!("[1 3]\n", rep("(swap-vals! a + 2)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_watches() {
+        let env = Env::default();
+        rep("(def! a (atom 1))", &env).unwrap();
+        rep("(def! last-change (atom nil))", &env).unwrap();
+        rep("(def! changes (atom 0))", &env).unwrap();
+        rep("(add-watch a :log (fn* [key _ old new] (do (reset! last-change (list key old new)) (swap! changes + 1))))", &env).unwrap();
+        rep("(swap! a + 1)", &env).unwrap();
+        assert_eq!("(:log 1 2)\n", rep("@last-change", &env).unwrap());
+        rep("(reset! a 10)", &env).unwrap();
+        rep("(compare-and-set! a 0 20)", &env).unwrap();
+        assert_eq!("(:log 2 10)\n", rep("@last-change", &env).unwrap());
+        assert_eq!("2\n", rep("@changes", &env).unwrap());
+
+        rep("(remove-watch a :log)", &env).unwrap();
+        rep("(reset! a 11)", &env).unwrap();
+        assert_eq!("2\n", rep("@changes", &env).unwrap());
+    }
+
+    #[test]
+    fn test_validator() {
+        let env = Env::default();
+        rep("(def! a (atom 1))", &env).unwrap();
+        rep("(set-validator! a (fn* [x] (> x 0)))", &env).unwrap();
+        assert_eq!(Err(RuntimeError::InvalidAtomState(Value::Integer(-1))), rep("(reset! a -1)", &env));
+        assert_eq!("1\n", rep("@a", &env).unwrap());
+        assert_eq!(":rejected\n", rep("(try* (swap! a - 5) (catch* e :rejected))", &env).unwrap());
+        // The current value has to pass a new validator
+        assert_eq!(Err(RuntimeError::InvalidAtomState(Value::Integer(1))), rep("(set-validator! a (fn* [x] (> x 1)))", &env));
+
+        rep("(set-validator! a nil)", &env).unwrap();
+        assert_eq!("-1\n", rep("(reset! a -1)", &env).unwrap());
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use itertools::Itertools;
+
+use crate::builtins::{assert_args_length, assert_args_length_between};
+use crate::builtins::concurrency::{catch_panic, STACK_SIZE};
+use crate::builtins::special_forms::implicit_do;
+use crate::channel::{Channel, Selector, TryTake};
+use crate::Env;
+use crate::evaluator::{evaluate_expr, RuntimeError, TypeError};
+use crate::port::{current_port, ERR_VAR};
+use crate::sync::{self, Shared};
+use crate::types::{Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("chan".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("chan", chan)
+    ));
+    env.insert(">!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues(">!", put)
+    ));
+    env.insert("<!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("<!", take)
+    ));
+    env.insert("close!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("close!", close)
+    ));
+    env.insert("timeout".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("timeout", timeout)
+    ));
+    env.insert("alts!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("alts!", alts)
+    ));
+    env.insert("go".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("go", go)
+    ));
+}
+
+/// The builtin definition for `chan`, with an optional buffer size. Without one, the channel is
+/// unbuffered, so each `>!` waits until its value is taken.
+fn chan(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 0, 1)?;
+    let capacity = match args.pop_front() {
+        Some(Value::Integer(size)) if size >= 0 => size as usize,
+        Some(value) => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a buffer size", value })),
+        None => 0,
+    };
+    Ok(Value::Channel(Shared::new(Channel::new(capacity))))
+}
+
+fn expect_channel(value: Value) -> Result<Shared<Channel>, RuntimeError> {
+    match value {
+        Value::Channel(channel) => Ok(channel),
+        value => Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a channel", value })),
+    }
+}
+
+/// nil can't be put on a channel, because taking nil means the channel is closed
+fn expect_non_nil(value: Value) -> Result<Value, RuntimeError> {
+    match value {
+        Value::Nil => Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a value other than nil", value })),
+        value => Ok(value),
+    }
+}
+
+/// The builtin definition for `>!`, which puts a value on a channel, waiting while it's full.
+/// Returns false if the channel is closed.
+fn put(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let channel = expect_channel(args.pop_front().expect(">! to have a channel"))?;
+    let value = expect_non_nil(args.pop_front().expect(">! to have a value"))?;
+    Ok(Value::Boolean(channel.put(value)))
+}
+
+/// The builtin definition for `<!`, which takes a value from a channel, waiting while it's empty.
+/// Returns nil once the channel is closed and empty.
+fn take(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let channel = expect_channel(args.pop_front().expect("<! to have a channel"))?;
+    Ok(channel.take().unwrap_or(Value::Nil))
+}
+
+fn close(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    expect_channel(args.pop_front().expect("close! to have a channel"))?.close();
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `timeout`, which makes a channel that closes after the given number
+/// of milliseconds.
+fn timeout(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("timeout to have a duration") {
+        Value::Integer(millis) if millis >= 0 => Ok(Value::Channel(Shared::new(Channel::timeout(Duration::from_millis(millis as u64))))),
+        value => Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a timeout in milliseconds", value })),
+    }
+}
+
+enum AltOp {
+    Take(Shared<Channel>),
+    Put(Shared<Channel>, Value),
+}
+
+/// The builtin definition for `alts!`, e.g. `(alts! [in (timeout 100) [out v]])`, which waits
+/// until one of several operations can complete and completes only that one. A channel is taken
+/// from and a `[channel value]` pair is put to. Returns `[value channel]`, where the value is
+/// true or false for a put. When several are ready at once, the first one listed wins.
+fn alts(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let ops = args.pop_front().expect("alts! to have operations").to_seq()?.iter()
+        .map(|op| match op {
+            Value::Vector(pair) if pair.len() == 2 => Ok(AltOp::Put(
+                expect_channel(pair[0].clone())?,
+                expect_non_nil(pair[1].clone())?,
+            )),
+            op => Ok(AltOp::Take(expect_channel(op.clone())?)),
+        })
+        .collect::<Result<Vec<_>, RuntimeError>>()?;
+    if ops.is_empty() {
+        return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "at least one channel", value: Value::Vector(sync::Vector::default()) }));
+    }
+
+    let channels = ops.iter().map(|op| match op {
+        AltOp::Take(channel) | AltOp::Put(channel, _) => channel.clone(),
+    }).collect_vec();
+    let deadline = channels.iter().filter_map(|channel| channel.closes_at()).min();
+    let selector = Arc::new(Selector::default());
+    for channel in &channels {
+        channel.add_selector(&selector);
+    }
+    let result = loop {
+        let ready = ops.iter().find_map(|op| match op {
+            AltOp::Take(channel) => match channel.try_take() {
+                TryTake::Value(value) => Some((value, channel)),
+                TryTake::Closed => Some((Value::Nil, channel)),
+                TryTake::Empty => None,
+            },
+            AltOp::Put(channel, value) => channel.try_put(value.clone()).map(|put| (Value::Boolean(put), channel)),
+        });
+        if let Some((value, channel)) = ready {
+            break Value::Vector(sync::Vector::from_iter([value, Value::Channel(channel.clone())]));
+        }
+        selector.wait(deadline);
+    };
+    for channel in &channels {
+        channel.remove_selector(&selector);
+    }
+    Ok(result)
+}
+
+/// The builtin definition for `go`, which evaluates its body on a pooled thread and returns a
+/// channel that gets the result, then closes. Errors are printed to `*err*`, closing the channel
+/// without a result. Go blocks are threads rather than green threads, so `<!` and `>!` work in
+/// them, and anywhere else, by blocking.
+fn go(env: &Env, arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    let body_expr = implicit_do(arg_exprs.into_iter().collect());
+    let channel = Shared::new(Channel::new(1));
+
+    let go_env = env.clone();
+    let go_channel = channel.clone();
+    spawn_go(Box::new(move || {
+        // A panic is reported like any other error, so that the channel still gets closed
+        match catch_panic(|| evaluate_expr(body_expr, &go_env)) {
+            Ok(Value::Nil) => {}
+            Ok(value) => {
+                go_channel.put(value);
+            }
+            Err(e) => {
+                if let Ok(port) = current_port(&go_env, ERR_VAR) {
+                    let _ = port.write_str(&format!("error in go block: {}\n", e));
+                }
+            }
+        }
+        go_channel.close();
+    })).map_err(|e| RuntimeError::Io { action: "start a thread for", path: "go".to_string(), message: e.to_string() })?;
+    Ok(Value::Channel(channel))
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How long a pooled thread waits for another go block before exiting
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The pooled threads waiting for a go block, each with its own way of receiving one. A go block
+/// is given to an idle thread if there is one, and otherwise gets a new thread. Threads are never
+/// shared between running go blocks, so a block waiting on a channel can't hold up another.
+static IDLE_WORKERS: Mutex<Vec<(u64, Sender<Job>)>> = Mutex::new(Vec::new());
+static NEXT_WORKER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Runs `job` on an idle pooled thread, or on a new one. Fails if a new thread can't be started.
+fn spawn_go(job: Job) -> io::Result<()> {
+    let mut idle_workers = IDLE_WORKERS.lock().unwrap_or_else(PoisonError::into_inner);
+    let job = match idle_workers.pop() {
+        // The worker only stops receiving after removing itself from the idle list, which can't
+        // happen while the list is locked here
+        Some((_, sender)) => match sender.send(job) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::SendError(job)) => job,
+        },
+        None => job,
+    };
+    drop(idle_workers);
+
+    thread::Builder::new()
+        .name("nlisp-go".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(move || run_worker(job))?;
+    Ok(())
+}
+
+fn run_worker(mut job: Job) {
+    let id = NEXT_WORKER_ID.fetch_add(1, Ordering::Relaxed);
+    loop {
+        job();
+
+        let (sender, receiver) = mpsc::channel();
+        IDLE_WORKERS.lock().unwrap_or_else(PoisonError::into_inner).push((id, sender));
+        job = match receiver.recv_timeout(IDLE_TIMEOUT) {
+            Ok(next) => next,
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                let mut idle_workers = IDLE_WORKERS.lock().unwrap_or_else(PoisonError::into_inner);
+                match idle_workers.iter().position(|(worker_id, _)| *worker_id == id) {
+                    Some(index) => {
+                        idle_workers.remove(index);
+                        return;
+                    }
+                    // A go block was handed over just as the wait timed out
+                    None => match receiver.recv() {
+                        Ok(next) => next,
+                        Err(_) => return,
+                    },
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_buffered_channel() {
+        let env = Env::default();
+        rep("(def! c (chan 2))", &env).unwrap();
+        assert_eq!("true\n", rep("(>! c 1)", &env).unwrap());
+        rep("(>! c 2)", &env).unwrap();
+        rep("(close! c)", &env).unwrap();
+        assert_eq!("false\n", rep("(>! c 3)", &env).unwrap());
+        assert_eq!("(1 2 nil)\n", rep("(list (<! c) (<! c) (<! c))", &env).unwrap());
+        assert!(rep("(>! (chan) nil)", &env).is_err());
+    }
+
+    #[test]
+    fn test_go_pipeline() {
+        let env = Env::default();
+        rep("(def! in (chan))", &env).unwrap();
+        rep("(def! out (chan))", &env).unwrap();
+        // Unbuffered channels make the producer and the stage hand each value over in turn
+        rep("(go (loop [i 1] (if (<= i 3) (do (>! in i) (recur (+ i 1))) (close! in))))", &env).unwrap();
+        rep("(go (loop [] (let* [x (<! in)] (if (= x nil) (close! out) (do (>! out (* x 10)) (recur))))))", &env).unwrap();
+        assert_eq!("(10 20 30 nil)\n", rep("(list (<! out) (<! out) (<! out) (<! out))", &env).unwrap());
+        assert_eq!("3\n", rep("(<! (go (+ 1 2)))", &env).unwrap());
+        // A panic in the body still closes the channel
+        rep("(def! g (go (/ 1 0)))", &env).unwrap();
+        assert_eq!("[nil true]\n", rep("(let* [[v ch] (alts! [g (timeout 5000)])] [v (= ch g)])", &env).unwrap());
+    }
+
+    #[test]
+    fn test_alts() {
+        let env = Env::default();
+        rep("(def! a (chan 1))", &env).unwrap();
+        rep("(def! b (chan 1))", &env).unwrap();
+        rep("(>! b :from-b)", &env).unwrap();
+        assert_eq!("[:from-b true]\n", rep("(let* [[v ch] (alts! [a b])] [v (= ch b)])", &env).unwrap());
+
+        // Nothing is ready, so the timeout wins
+        assert_eq!("[nil true]\n", rep("(let* [t (timeout 20) [v ch] (alts! [a t])] [v (= ch t)])", &env).unwrap());
+
+        // A put completes when there's room
+        assert_eq!("[true :x]\n", rep("(let* [[ok ch] (alts! [[a :x]])] [ok (<! ch)])", &env).unwrap());
+
+        // A value put by another thread wakes the waiting alts!
+        rep("(go (<! (timeout 10)) (>! b :later))", &env).unwrap();
+        assert_eq!(":later\n", rep("(let* [[v _] (alts! [b (timeout 5000)])] v)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_alts_unbuffered_put() {
+        let env = Env::default();
+        rep("(def! c (chan))", &env).unwrap();
+        // No one is taking, so the put isn't ready and the timeout wins
+        assert_eq!("[nil true]\n", rep("(let* [t (timeout 50) [v ch] (alts! [[c :x] t])] [v (= ch t)])", &env).unwrap());
+
+        // Once a taker is waiting, the put completes and hands it the value
+        rep("(def! taken (go (<! c)))", &env).unwrap();
+        assert_eq!("[true true]\n", rep("(let* [[ok ch] (alts! [[c :y] (timeout 5000)])] [ok (= ch c)])", &env).unwrap());
+        assert_eq!(":y\n", rep("(<! taken)", &env).unwrap());
+    }
+}
@@ -1,25 +1,26 @@
 use std::collections::VecDeque;
 
 use crate::builtins::{assert_args_length, run_to_closure};
+use crate::builtins::arithmetic::float_operands;
 use crate::Env;
 use crate::evaluator::{RuntimeError, TypeError};
 use crate::types::{FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("=".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(eq)
+        FunctionBody::BuiltinValues("=", eq)
     ));
     env.insert("<".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(lt)
+        FunctionBody::BuiltinValues("<", lt)
     ));
     env.insert("<=".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(lte)
+        FunctionBody::BuiltinValues("<=", lte)
     ));
     env.insert(">".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(gt)
+        FunctionBody::BuiltinValues(">", gt)
     ));
     env.insert(">=".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(gte)
+        FunctionBody::BuiltinValues(">=", gte)
     ));
 }
 
@@ -42,7 +43,10 @@ fn lt(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
 
     match (lhs, rhs) {
         (Value::Integer(lhs), Value::Integer(rhs)) => Ok(Value::Boolean(lhs < rhs)),
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (lhs, rhs) => match float_operands(&lhs, &rhs) {
+            Some((lhs, rhs)) => Ok(Value::Boolean(lhs < rhs)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -53,7 +57,10 @@ fn lte(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
 
     match (lhs, rhs) {
         (Value::Integer(lhs), Value::Integer(rhs)) => Ok(Value::Boolean(lhs <= rhs)),
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (lhs, rhs) => match float_operands(&lhs, &rhs) {
+            Some((lhs, rhs)) => Ok(Value::Boolean(lhs <= rhs)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -64,7 +71,10 @@ fn gt(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
 
     match (lhs, rhs) {
         (Value::Integer(lhs), Value::Integer(rhs)) => Ok(Value::Boolean(lhs > rhs)),
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (lhs, rhs) => match float_operands(&lhs, &rhs) {
+            Some((lhs, rhs)) => Ok(Value::Boolean(lhs > rhs)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
@@ -75,12 +85,18 @@ fn gte(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
 
     match (lhs, rhs) {
         (Value::Integer(lhs), Value::Integer(rhs)) => Ok(Value::Boolean(lhs >= rhs)),
-        _ => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        (lhs, rhs) => match float_operands(&lhs, &rhs) {
+            Some((lhs, rhs)) => Ok(Value::Boolean(lhs >= rhs)),
+            None => Err(RuntimeError::IncorrectType(TypeError::Misc))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::rep;
+    use crate::sync;
+
     use super::*;
 
     mod test_eq {
@@ -108,8 +124,8 @@ mod tests {
         fn test_seq_coercion() {
             let env = Env::default();
             let args = VecDeque::from([
-                Value::List(rpds::List::from_iter([Value::Integer(1), Value::Symbol("foo".to_string())])),
-                Value::Vector(rpds::Vector::from_iter([Value::Integer(1), Value::Symbol("foo".to_string())]))
+                Value::List(sync::List::from_iter([Value::Integer(1), Value::Symbol("foo".to_string())])),
+                Value::Vector(sync::Vector::from_iter([Value::Integer(1), Value::Symbol("foo".to_string())]))
             ]);
             assert_eq!(Ok(Value::Boolean(true)), eq(&env, args));
         }
@@ -123,4 +139,12 @@ mod tests {
             assert_eq!(Ok(Value::Boolean(false)), eq(&env, args));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_float_comparison() {
+        let env = Env::default();
+        assert_eq!("(true true false true)\n", rep("(list (< 1 1.5) (<= 1.5 1.5) (> 1.5 2) (>= 2.5 2))", &env).unwrap());
+        // Like Clojure, an integer never equals a float
+        assert_eq!("(true false)\n", rep("(list (= 1.5 1.5) (= 1 1.0))", &env).unwrap());
+    }
+}
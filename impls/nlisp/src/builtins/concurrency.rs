@@ -0,0 +1,250 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+use crate::builtins::{apply_value, assert_args_length, assert_args_length_at_least};
+use crate::builtins::seq::{seq_to_vec, vec_to_list};
+use crate::builtins::special_forms::implicit_do;
+use crate::Env;
+use crate::evaluator::{evaluate_expr, RuntimeError, TypeError};
+use crate::promise::Promise;
+use crate::sync::Shared;
+use crate::types::{Expr, FunctionBody, Value};
+
+/// Threads started for futures and `pmap` get the same stack size as the main thread usually has,
+/// since evaluation recurses for every nested form
+pub(super) const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+pub fn insert_functions(env: &Env) {
+    env.insert("future".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("future", future)
+    ));
+    env.insert("future?".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("future?", is_future)
+    ));
+    env.insert("promise".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("promise", promise)
+    ));
+    env.insert("deliver".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("deliver", deliver)
+    ));
+    env.insert("realized?".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("realized?", is_realized)
+    ));
+    env.insert("pmap".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("pmap", pmap)
+    ));
+}
+
+/// The builtin definition for `future`, which evaluates its body on a new thread. Dereferencing the
+/// future waits for the result, and rethrows any error from the body.
+fn future(env: &Env, arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    let body_expr = implicit_do(arg_exprs.into_iter().collect());
+    let promise = Shared::new(Promise::new("future"));
+
+    let thread_env = env.clone();
+    let thread_promise = promise.clone();
+    thread::Builder::new()
+        .name("nlisp-future".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            // A panic would otherwise leave the future undelivered and `deref` waiting forever
+            thread_promise.deliver(catch_panic(|| evaluate_expr(body_expr, &thread_env)))
+        })
+        .map_err(|e| RuntimeError::Io { action: "start a thread for", path: "future".to_string(), message: e.to_string() })?;
+    Ok(Value::Promise(promise))
+}
+
+/// Runs `f`, turning a panic into `RuntimeError::Panicked` so that it doesn't take down the
+/// thread, or the host program, without a result.
+pub(super) fn catch_panic<T, F>(f: F) -> Result<T, RuntimeError>
+    where F: FnOnce() -> Result<T, RuntimeError>
+{
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(RuntimeError::Panicked(panic_message(payload.as_ref()))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown cause".to_string(),
+    }
+}
+
+fn is_future(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let arg = args.pop_front().expect("future? to have one argument");
+    Ok(Value::Boolean(matches!(arg, Value::Promise(promise) if promise.kind == "future")))
+}
+
+fn promise(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 0)?;
+    Ok(Value::Promise(Shared::new(Promise::new("promise"))))
+}
+
+fn pop_promise(args: &mut VecDeque<Value>) -> Result<Shared<Promise>, RuntimeError> {
+    match args.pop_front().expect("a promise to be present") {
+        Value::Promise(promise) => Ok(promise),
+        value => Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a promise or future", value })),
+    }
+}
+
+/// The builtin definition for `deliver`, which gives a promise its value. It returns the promise,
+/// or nil if the promise already had a value, which is left unchanged.
+fn deliver(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let promise = pop_promise(&mut args)?;
+    if promise.kind == "future" {
+        return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a promise", value: Value::Promise(promise) }));
+    }
+    let value = args.pop_front().expect("deliver to have a value");
+    match promise.deliver(Ok(value)) {
+        true => Ok(Value::Promise(promise)),
+        false => Ok(Value::Nil),
+    }
+}
+
+fn is_realized(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    Ok(Value::Boolean(pop_promise(&mut args)?.is_realized()))
+}
+
+/// `deref` for futures and promises, which waits for the value. `(deref p timeout-ms timeout-val)`
+/// gives up after `timeout-ms` milliseconds and returns `timeout-val` instead.
+pub(super) fn deref_promise(mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    if args.len() != 3 {
+        assert_args_length(&args, 1)?;
+    }
+    let promise = pop_promise(&mut args)?;
+    let timeout = match args.pop_front() {
+        Some(Value::Integer(millis)) if millis >= 0 => Some(Duration::from_millis(millis as u64)),
+        Some(value) => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a timeout in milliseconds", value })),
+        None => None,
+    };
+    match promise.wait(timeout) {
+        Some(result) => result,
+        None => Ok(args.pop_front().expect("deref to have a timeout value")),
+    }
+}
+
+/// The builtin definition for `pmap`, which is like `map` but splits the calls between a thread
+/// for each core. It's only worth it when each call does a lot of work.
+fn pmap(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+    let f = args.pop_front().expect("pmap to have a function argument");
+
+    let colls = args.into_iter().map(seq_to_vec).collect::<Result<Vec<_>, _>>()?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
+    let calls = (0..len)
+        .map(|i| colls.iter().map(|coll| coll[i].clone()).collect::<VecDeque<_>>())
+        .collect::<Vec<_>>();
+
+    let num_threads = thread::available_parallelism().map_or(1, |n| n.get()).min(len).max(1);
+    let chunk_size = len.div_ceil(num_threads).max(1);
+    let chunk_results = thread::scope(|scope| {
+        let handles = calls.chunks(chunk_size)
+            .map(|chunk| thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || catch_panic(|| chunk.iter()
+                    .map(|call_args| apply_value(env, &f, call_args.clone()))
+                    .collect::<Result<Vec<_>, _>>())))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RuntimeError::Io { action: "start a thread for", path: "pmap".to_string(), message: e.to_string() })?;
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(vec_to_list(chunk_results.into_iter().flatten().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+    use crate::types::NativeFunction;
+
+    use super::*;
+
+    #[test]
+    fn test_future() {
+        let env = Env::default();
+        rep("(def! f (future (+ 1 2)))", &env).unwrap();
+        assert_eq!("3\n", rep("@f", &env).unwrap());
+        assert_eq!("true\n", rep("(realized? f)", &env).unwrap());
+        assert_eq!("(true false)\n", rep("(list (future? f) (future? (promise)))", &env).unwrap());
+        // Errors in the body are rethrown by every deref
+        rep("(def! g (future (throw :boom)))", &env).unwrap();
+        assert_eq!(Err(RuntimeError::Thrown(Value::Keyword(":boom".to_string()))), rep("@g", &env));
+        assert_eq!(":caught\n", rep("(try* @g (catch* e :caught))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_promise() {
+        let env = Env::default();
+        rep("(def! p (promise))", &env).unwrap();
+        assert_eq!(":timed-out\n", rep("(deref p 10 :timed-out)", &env).unwrap());
+        assert_eq!("false\n", rep("(realized? p)", &env).unwrap());
+
+        // Delivered from another thread while the main thread waits
+        rep("(future (deliver p 42))", &env).unwrap();
+        assert_eq!("42\n", rep("@p", &env).unwrap());
+        assert_eq!("nil\n", rep("(deliver p 43)", &env).unwrap());
+        assert_eq!("42\n", rep("(deref p 10 :timed-out)", &env).unwrap());
+        assert_eq!("[true false]\n", rep("[(= p p) (= p (promise))]", &env).unwrap());
+    }
+
+    #[test]
+    fn test_atoms_across_threads() {
+        let env = Env::default();
+        rep("(def! counter (atom 0))", &env).unwrap();
+        rep("(def! workers (map (fn* [_] (future (loop [i 0] (if (< i 100) (do (swap! counter + 1) (recur (+ i 1))) i)))) [1 2 3 4 5 6 7 8]))", &env).unwrap();
+        assert_eq!("800\n", rep("(do (map deref workers) @counter)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_recursion_across_threads() {
+        let env = Env::default();
+        rep("(def! depth (fn* [n] (if (= n 0) 0 (+ 1 (depth (- n 1))))))", &env).unwrap();
+        rep("(def! workers (map (fn* [_] (future (reduce + 0 (map depth [200 200 200 200])))) [1 2 3 4 5 6 7 8]))", &env).unwrap();
+        assert_eq!("(800 800 800 800 800 800 800 800)\n", rep("(map deref workers)", &env).unwrap());
+        // Every call has returned, so a depth limit still has all of its room
+        env.set_limits(crate::Limits { max_depth: Some(10), ..Default::default() });
+        assert_eq!("3\n", rep("(depth 3)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_depth_is_per_thread() {
+        let env = Env::default();
+        env.set_limits(crate::Limits { max_depth: Some(12), ..Default::default() });
+        rep("(def! depth (fn* [n] (if (= n 0) 0 (+ 1 (depth (- n 1))))))", &env).unwrap();
+        rep("(def! ready (promise))", &env).unwrap();
+        rep("(def! release (promise))", &env).unwrap();
+        rep("(def! nest (fn* [n] (if (= n 0) (do (deliver ready true) @release) (+ 1 (nest (- n 1))))))", &env).unwrap();
+        rep("(def! f (future (nest 8)))", &env).unwrap();
+        rep("@ready", &env).unwrap();
+        // The future's calls don't count against this thread's depth
+        assert_eq!("8\n", rep("(depth 8)", &env).unwrap());
+        rep("(deliver release 0)", &env).unwrap();
+        assert_eq!("8\n", rep("@f", &env).unwrap());
+    }
+
+    #[test]
+    fn test_panicking_future() {
+        let env = Env::default();
+        env.insert("boom".to_string(), Value::Function(FunctionBody::Native(NativeFunction::new("boom", |_: &Env, _| panic!("boom")))));
+        rep("(def! f (future (boom)))", &env).unwrap();
+        assert_eq!(Err(RuntimeError::Panicked("boom".to_string())), rep("(deref f 5000 :timed-out)", &env));
+    }
+
+    #[test]
+    fn test_pmap() {
+        let env = Env::default();
+        assert_eq!("(2 4 6)\n", rep("(pmap (fn* [x] (* x 2)) [1 2 3])", &env).unwrap());
+        assert_eq!("(5 7)\n", rep("(pmap + [1 2 3] [4 5])", &env).unwrap());
+        assert_eq!("()\n", rep("(pmap + [])", &env).unwrap());
+        assert_eq!(Err(RuntimeError::Thrown(Value::Integer(2))), rep("(pmap (fn* [x] (if (= x 2) (throw x) x)) [1 2 3])", &env));
+        assert!(matches!(rep("(pmap (fn* [x] (/ 1 x)) [1 0])", &env), Err(RuntimeError::Panicked(_))));
+    }
+}
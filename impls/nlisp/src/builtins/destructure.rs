@@ -0,0 +1,200 @@
+use crate::Env;
+use crate::evaluator::{evaluate_expr, DestructureError, RuntimeError};
+use crate::sync;
+use crate::types::{Expr, HashableValue, Value};
+
+/// Binds `value` into `env` according to a binding form, which is either a plain symbol,
+/// a sequential form like `[a b & rest :as all]` or an associative form like
+/// `{:keys [x y] :or {y 0} :as m}`. Binding forms can be nested.
+pub fn bind_pattern(env: &Env, pattern: &Expr, value: Value) -> Result<(), RuntimeError> {
+    match pattern {
+        Expr::Symbol(name) if name != "&" => {
+            env.insert(name.to_string(), value);
+            Ok(())
+        }
+        Expr::Vector(elems) => bind_sequential(env, pattern, elems, value),
+        Expr::HashMap(pairs) => bind_associative(env, pattern, pairs, value),
+        _ => Err(RuntimeError::ExpectedToBindSymbol),
+    }
+}
+
+/// Whether a binding form needs destructuring, rather than being a plain symbol.
+pub fn is_destructuring_pattern(pattern: &Expr) -> bool {
+    matches!(pattern, Expr::Vector(_) | Expr::HashMap(_))
+}
+
+fn bind_sequential(env: &Env, pattern: &Expr, elems: &[Expr], value: Value) -> Result<(), RuntimeError> {
+    let values = match value {
+        Value::List(_) | Value::Vector(_) | Value::Nil => value.clone().to_seq()?,
+        _ => return Err(DestructureError::NotSequential { pattern: pattern.to_string(), value }.into()),
+    };
+
+    let mut remaining = values.iter().cloned();
+    let mut rest_values = Some(values.clone());
+    let mut elems_iter = elems.iter();
+    while let Some(elem) = elems_iter.next() {
+        match elem {
+            Expr::Symbol(s) if s == "&" => {
+                let rest_pattern = elems_iter.next()
+                    .ok_or_else(|| DestructureError::InvalidBindingForm(pattern.to_string()))?;
+                let rest = rest_values.take().unwrap_or_default();
+                let rest_value = if rest.is_empty() { Value::Nil } else { Value::List(rest) };
+                bind_pattern(env, rest_pattern, rest_value)?;
+            }
+            Expr::Keyword(k) if k == ":as" => {
+                let as_pattern = elems_iter.next()
+                    .ok_or_else(|| DestructureError::InvalidBindingForm(pattern.to_string()))?;
+                bind_pattern(env, as_pattern, value.clone())?;
+            }
+            elem_pattern => {
+                bind_pattern(env, elem_pattern, remaining.next().unwrap_or(Value::Nil))?;
+                rest_values = rest_values.and_then(|rest| rest.drop_first());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts the value being destructured into a map. A sequence of alternating keys and values
+/// is also accepted, so that `& {:keys [...]}` can be used for keyword arguments.
+fn value_to_map(pattern: &Expr, value: Value) -> Result<sync::HashTrieMap<HashableValue, Value>, RuntimeError> {
+    match value {
+        Value::HashMap(pairs) => Ok(pairs),
+        Value::Record(record) => Ok(record.to_map()),
+        Value::Nil => Ok(sync::HashTrieMap::default()),
+        Value::List(_) | Value::Vector(_) => {
+            let values = value.clone().to_seq()?;
+            if values.len() % 2 != 0 {
+                return Err(DestructureError::NotAssociative { pattern: pattern.to_string(), value }.into());
+            }
+
+            let mut pairs = sync::HashTrieMap::default();
+            let mut values_iter = values.iter().cloned();
+            while let (Some(key), Some(val)) = (values_iter.next(), values_iter.next()) {
+                let key_hash = key.clone().try_into().map_err(|_| RuntimeError::HashError(key))?;
+                pairs.insert_mut(key_hash, val);
+            }
+            Ok(pairs)
+        }
+        _ => Err(DestructureError::NotAssociative { pattern: pattern.to_string(), value }.into()),
+    }
+}
+
+fn bind_associative(env: &Env, pattern: &Expr, pattern_pairs: &[(Expr, Expr)], value: Value) -> Result<(), RuntimeError> {
+    let map = value_to_map(pattern, value.clone())?;
+
+    let defaults = pattern_pairs.iter()
+        .find_map(|(key, default_exprs)| match (key, default_exprs) {
+            (Expr::Keyword(k), Expr::HashMap(default_pairs)) if k == ":or" => Some(default_pairs.as_slice()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    let bind_key = |target: &Expr, key: HashableValue| -> Result<(), RuntimeError> {
+        let found = match map.get(&key) {
+            Some(found) => found.clone(),
+            None => match (target, defaults.iter().find(|(name, _)| name == target)) {
+                (Expr::Symbol(_), Some((_, default_expr))) => evaluate_expr(default_expr.clone(), env)?,
+                _ => Value::Nil,
+            }
+        };
+        bind_pattern(env, target, found)
+    };
+
+    for (key, target) in pattern_pairs {
+        match (key, target) {
+            (Expr::Keyword(k), Expr::Vector(names)) if k == ":keys" || k == ":strs" => {
+                for name in names {
+                    let key = match name {
+                        Expr::Symbol(s) if k == ":keys" => HashableValue::Keyword(format!(":{}", s)),
+                        Expr::Symbol(s) => HashableValue::String(s.to_string()),
+                        _ => return Err(RuntimeError::ExpectedToBindSymbol),
+                    };
+                    bind_key(name, key)?;
+                }
+            }
+            (Expr::Keyword(k), _) if k == ":as" => bind_pattern(env, target, value.clone())?,
+            (Expr::Keyword(k), _) if k == ":or" => {}
+            // An explicit `{binding-form lookup-key}` entry
+            (binding_form, lookup_key) => {
+                let key = match lookup_key {
+                    Expr::Keyword(s) => HashableValue::Keyword(s.to_string()),
+                    Expr::String(s) => HashableValue::String(s.to_string()),
+                    Expr::Integer(num) => HashableValue::Integer(*num),
+                    _ => return Err(DestructureError::InvalidBindingForm(pattern.to_string()).into()),
+                };
+                bind_key(binding_form, key)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_text_to_expression;
+
+    use super::*;
+
+    fn bind(pattern_src: &str, value: Value) -> Result<Env, RuntimeError> {
+        let env = Env::default().create_child_env();
+        let pattern = parse_text_to_expression(pattern_src).expect("pattern to parse");
+        bind_pattern(&env, &pattern, value)?;
+        Ok(env)
+    }
+
+    fn int_vector(nums: &[i64]) -> Value {
+        Value::Vector(nums.iter().map(|num| Value::Integer(*num)).collect())
+    }
+
+    #[test]
+    fn test_sequential() {
+        let env = bind("[a b & rest :as all]", int_vector(&[1, 2, 3, 4])).unwrap();
+        assert_eq!(Some(Value::Integer(1)), env.lookup("a"));
+        assert_eq!(Some(Value::Integer(2)), env.lookup("b"));
+        assert_eq!(Some(Value::List(sync::List::from_iter([Value::Integer(3), Value::Integer(4)]))), env.lookup("rest"));
+        assert_eq!(Some(int_vector(&[1, 2, 3, 4])), env.lookup("all"));
+    }
+
+    #[test]
+    fn test_sequential_missing_values() {
+        let env = bind("[a b & rest]", int_vector(&[1])).unwrap();
+        assert_eq!(Some(Value::Nil), env.lookup("b"));
+        assert_eq!(Some(Value::Nil), env.lookup("rest"));
+    }
+
+    #[test]
+    fn test_associative() {
+        let map = Value::HashMap(sync::HashTrieMap::from_iter([
+            (HashableValue::Keyword(":x".to_string()), Value::Integer(1)),
+            (HashableValue::String("name".to_string()), Value::String("n".to_string())),
+        ]));
+        let env = bind("{:keys [x y] :strs [name] :or {y 0} :as m}", map.clone()).unwrap();
+        assert_eq!(Some(Value::Integer(1)), env.lookup("x"));
+        assert_eq!(Some(Value::Integer(0)), env.lookup("y"));
+        assert_eq!(Some(Value::String("n".to_string())), env.lookup("name"));
+        assert_eq!(Some(map), env.lookup("m"));
+    }
+
+    #[test]
+    fn test_nested() {
+        let map = Value::HashMap(sync::HashTrieMap::from_iter([
+            (HashableValue::Keyword(":point".to_string()), int_vector(&[3, 4])),
+        ]));
+        let env = bind("[{[x y] :point}]", Value::Vector(sync::Vector::from_iter([map]))).unwrap();
+        assert_eq!(Some(Value::Integer(3)), env.lookup("x"));
+        assert_eq!(Some(Value::Integer(4)), env.lookup("y"));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(Err(RuntimeError::Destructure(DestructureError::NotSequential {
+            pattern: "[a b]".to_string(),
+            value: Value::Integer(3),
+        })), bind("[a b]", Value::Integer(3)).map(|_| ()));
+        assert_eq!(Err(RuntimeError::Destructure(DestructureError::NotAssociative {
+            pattern: "{:keys [a]}".to_string(),
+            value: Value::Integer(3),
+        })), bind("{:keys [a]}", Value::Integer(3)).map(|_| ()));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::builtins::special_forms::implicit_do;
+use crate::Env;
+use crate::evaluator::{evaluate_expr, RuntimeError};
+use crate::sync::Shared;
+use crate::types::{DynamicVar, Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("def-dynamic".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("def-dynamic", def_dynamic)
+    ));
+    env.insert("binding".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("binding", binding)
+    ));
+    env.insert("set!".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("set!", set)
+    ));
+}
+
+/// Defines `name` in `env`. Redefining a dynamic var of this namespace changes its root value
+/// rather than replacing it, so that functions which already refer to the var keep seeing it.
+/// Redefining one from an enclosing environment, like `(def! *print-length* 10)`, creates a var
+/// in this namespace that shadows it, so other namespaces are unaffected.
+pub fn define_var(env: &Env, name: String, value: Value, is_dynamic: bool) {
+    if let Some(Value::DynamicVar(var)) = env.lookup_local(&name) {
+        return var.set_root(value);
+    }
+    if is_dynamic || matches!(env.lookup(&name), Some(Value::DynamicVar(_))) {
+        let qualified_name = format!("{}/{}", env.namespace(), name);
+        env.insert(name, Value::DynamicVar(Shared::new(DynamicVar::new(qualified_name, value))));
+    } else {
+        env.insert(name, value);
+    }
+}
+
+/// The builtin definition for `def-dynamic`, which is the same as `(def! ^:dynamic name value)`
+fn def_dynamic(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length(&arg_exprs, 2)?;
+    let name = match arg_exprs.pop_front().expect("def-dynamic to have a name") {
+        Expr::Symbol(name) => name,
+        _ => return Err(RuntimeError::ExpectedToBindSymbol),
+    };
+    let value = evaluate_expr(arg_exprs.pop_front().expect("def-dynamic to have a value"), env)?;
+    define_var(env, name, value.clone(), true);
+    Ok(value)
+}
+
+fn lookup_dynamic_var(env: &Env, name_expr: Expr) -> Result<Shared<DynamicVar>, RuntimeError> {
+    match name_expr {
+        Expr::Symbol(name) => match env.lookup_err(&name)? {
+            Value::DynamicVar(var) => Ok(var),
+            _ => Err(RuntimeError::NotDynamic(name)),
+        },
+        _ => Err(RuntimeError::ExpectedToBindSymbol),
+    }
+}
+
+/// Pops the bindings pushed by `binding` when dropped, so they are undone however the body exits.
+pub struct BindingGuard(pub Vec<Shared<DynamicVar>>);
+
+impl Drop for BindingGuard {
+    fn drop(&mut self) {
+        for var in self.0.iter() {
+            var.pop_binding();
+        }
+    }
+}
+
+/// The builtin definition for `binding`, e.g. `(binding [*out-width* 40] (show))`.
+/// Unlike `let*`, the new values are seen by every function called from the body, not just
+/// code that appears lexically inside it. All values are evaluated before any var is rebound.
+fn binding(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let binding_exprs = match arg_exprs.pop_front().expect("binding to have bindings") {
+        Expr::Vector(binding_exprs) => binding_exprs,
+        _ => return Err(RuntimeError::IncorrectType(crate::evaluator::TypeError::Misc)),
+    };
+    if binding_exprs.len() % 2 != 0 {
+        return Err(RuntimeError::UnmatchedLetBindingID);
+    }
+
+    let new_bindings = binding_exprs.into_iter()
+        .tuples()
+        .map(|(name_expr, value_expr)| {
+            let var = lookup_dynamic_var(env, name_expr)?;
+            Ok((var, evaluate_expr(value_expr, env)?))
+        })
+        .collect::<Result<Vec<_>, RuntimeError>>()?;
+
+    let mut guard = BindingGuard(Vec::with_capacity(new_bindings.len()));
+    for (var, value) in new_bindings {
+        var.push_binding(value);
+        guard.0.push(var);
+    }
+
+    evaluate_expr(implicit_do(arg_exprs.into_iter().collect()), env)
+}
+
+/// The builtin definition for `set!`, which changes the innermost `binding` of a dynamic var
+fn set(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length(&arg_exprs, 2)?;
+    let var = lookup_dynamic_var(env, arg_exprs.pop_front().expect("set! to have a var"))?;
+    let value = evaluate_expr(arg_exprs.pop_front().expect("set! to have a value"), env)?;
+    if var.set_binding(value.clone()) {
+        Ok(value)
+    } else {
+        Err(RuntimeError::DynamicVarNotBound(var.name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_binding_is_seen_by_callees() {
+        let env = Env::default();
+        rep("(def-dynamic *x* 1)", &env).unwrap();
+        rep("(def! get-x (fn* [] *x*))", &env).unwrap();
+        assert_eq!("2\n", rep("(binding [*x* 2] (get-x))", &env).unwrap());
+        assert_eq!("1\n", rep("(get-x)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_binding_restored_after_error() {
+        let env = Env::default();
+        rep("(def! ^:dynamic *x* 1)", &env).unwrap();
+        assert!(rep("(binding [*x* 2] (undefined-fn))", &env).is_err());
+        assert_eq!("1\n", rep("*x*", &env).unwrap());
+    }
+
+    #[test]
+    fn test_set() {
+        let env = Env::default();
+        rep("(def-dynamic *x* 1)", &env).unwrap();
+        assert_eq!("3\n", rep("(binding [*x* 2] (do (set! *x* 3) *x*))", &env).unwrap());
+        assert_eq!(Err(RuntimeError::DynamicVarNotBound("user/*x*".to_string())), rep("(set! *x* 4)", &env));
+    }
+
+    #[test]
+    fn test_binding_non_dynamic() {
+        let env = Env::default();
+        rep("(def! y 1)", &env).unwrap();
+        assert_eq!(Err(RuntimeError::NotDynamic("y".to_string())), rep("(binding [y 2] y)", &env));
+    }
+
+    #[test]
+    fn test_def_shadows_core_var() {
+        let env = Env::default();
+        rep("(def! *print-length* 1)", &env).unwrap();
+        assert_eq!("[1 ...]\n", crate::rep_pprint("[1 2 3]", &env).unwrap());
+        // It's still a dynamic var, so it can be rebound
+        assert_eq!("2\n", rep("(binding [*print-length* 2] *print-length*)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_def_of_core_var_is_namespace_local() {
+        let env = Env::default();
+        rep("(ns other)", &env).unwrap();
+        rep("(def! *print-length* 2)", &env).unwrap();
+        rep("(in-ns 'user)", &env).unwrap();
+        assert_eq!("nil\n", rep("*print-length*", &env).unwrap());
+        assert_eq!("[1 2 3]\n", crate::rep_pprint("[1 2 3]", &env).unwrap());
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::VecDeque;
+
+use crate::builtins::assert_args_length;
+use crate::edn::{read_string, write_string};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::types::{FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("edn/read-string".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("edn/read-string", edn_read_string)
+    ));
+    env.insert("edn/write-string".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("edn/write-string", edn_write_string)
+    ));
+}
+
+/// The builtin definition for `edn/read-string`, which reads the first EDN element in a string as
+/// data, without evaluating it
+fn edn_read_string(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("edn/read-string to have an argument") {
+        Value::String(text) => Ok(read_string(&text)?),
+        _ => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+/// The builtin definition for `edn/write-string`
+fn edn_write_string(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let value = args.pop_front().expect("edn/write-string to have an argument");
+    write_string(&value).map(Value::String).map_err(RuntimeError::NotEdn)
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::builtins::special_forms::implicit_do;
+use crate::Env;
+use crate::evaluator::{evaluate_expr, RuntimeError};
+use crate::types::{Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("throw".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("throw", throw)
+    ));
+    env.insert("try*".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("try*", try_f)
+    ));
+}
+
+/// The builtin definition for `throw`
+fn throw(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    Err(RuntimeError::Thrown(args.pop_front().expect("throw to have an argument")))
+}
+
+/// The builtin definition for `try*`, e.g. `(try* (risky) (catch* e (handle e)))`.
+/// A thrown value is bound as-is, while other runtime errors are bound as their message.
+fn try_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let catch_clause = match arg_exprs.back() {
+        Some(Expr::List(clause)) if clause.front() == Some(&Expr::Symbol("catch*".to_string())) => {
+            let mut clause = clause.iter().skip(1).cloned().collect::<VecDeque<_>>();
+            match clause.pop_front() {
+                Some(Expr::Symbol(name)) => Some((name, implicit_do(clause.into_iter().collect()))),
+                _ => return Err(RuntimeError::ExpectedToBindSymbol),
+            }
+        }
+        _ => None,
+    };
+    if catch_clause.is_some() {
+        arg_exprs.pop_back();
+    }
+
+    let result = evaluate_expr(implicit_do(arg_exprs.into_iter().collect()), env);
+    match (result, catch_clause) {
+        // `recur` isn't an error, so it has to pass through to the enclosing loop
+        (Err(RuntimeError::Recur(values)), _) => Err(RuntimeError::Recur(values)),
+        // Interrupts and exceeded limits have to get back to the host, so untrusted code can't catch them either
+        (Err(err @ (RuntimeError::Interrupted | RuntimeError::LimitExceeded(_))), _) => Err(err),
+        (Err(err), Some((name, catch_body))) => {
+            let caught = match err {
+                RuntimeError::Thrown(value) => value,
+                err => Value::String(err.to_string()),
+            };
+            let catch_env = env.create_child_env();
+            catch_env.insert(name, caught);
+            evaluate_expr(catch_body, &catch_env)
+        }
+        (result, _) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_catch_thrown_value() {
+        let env = Env::default();
+        assert_eq!("{:code 1}\n", rep("(try* (throw {:code 1}) (catch* e e))", &env).unwrap());
+        assert_eq!("2\n", rep("(try* 2 (catch* e 3))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_catch_runtime_error() {
+        let env = Env::default();
+        assert_eq!("\"symbol 'nope' not found\"\n", rep("(try* nope (catch* e e))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_limits_are_not_caught() {
+        let env = Env::default();
+        env.set_limits(crate::Limits { max_depth: Some(50), ..Default::default() });
+        assert_eq!(
+            Err(RuntimeError::LimitExceeded(crate::Limit::Depth(50))),
+            rep("(try* ((fn* f [] (f))) (catch* e e))", &env),
+        );
+        // Nesting catches around the recursion doesn't help either
+        assert_eq!(
+            Err(RuntimeError::LimitExceeded(crate::Limit::Depth(50))),
+            rep("((fn* f [] (try* (f) (catch* e (f)))))", &env),
+        );
+    }
+
+    #[test]
+    fn test_uncaught() {
+        let env = Env::default();
+        assert_eq!(Err(RuntimeError::Thrown(Value::Integer(1))), rep("(try* (throw 1))", &env));
+    }
+}
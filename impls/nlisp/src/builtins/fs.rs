@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least, assert_args_length_between};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::printer::PrintOptions;
+use crate::types::{FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("slurp".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("slurp", slurp)
+    ));
+    env.insert("spit".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("spit", spit)
+    ));
+    env.insert("read-lines".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("read-lines", read_lines)
+    ));
+    env.insert("file-exists?".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("file-exists?", file_exists)
+    ));
+    env.insert("list-dir".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("list-dir", list_dir)
+    ));
+    env.insert("mkdir".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("mkdir", mkdir)
+    ));
+    env.insert("delete-file".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("delete-file", delete_file)
+    ));
+}
+
+fn pop_path(args: &mut VecDeque<Value>) -> Result<String, RuntimeError> {
+    match args.pop_front().expect("to have a path argument") {
+        Value::String(path) => Ok(path),
+        _ => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+fn io_error(action: &'static str, path: &str, error: std::io::Error) -> RuntimeError {
+    RuntimeError::Io { action, path: path.to_string(), message: error.to_string() }
+}
+
+fn slurp(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let path = pop_path(&mut args)?;
+    fs::read_to_string(&path)
+        .map(Value::String)
+        .map_err(|e| io_error("read", &path, e))
+}
+
+/// The builtin definition for `spit`, which writes its content as `str` would, replacing the file
+/// unless given `:append true`
+fn spit(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+    let path = pop_path(&mut args)?;
+    let content = args.pop_front().expect("spit to have content");
+    let append = match (args.pop_front(), args.pop_front()) {
+        (None, None) => false,
+        (Some(Value::Keyword(k)), Some(append)) if k == ":append" && args.is_empty() => append.is_truthy(),
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+
+    let content = match content {
+        Value::String(s) => s,
+        value => value.print_value_with_options(false, &PrintOptions::from_env(env)),
+    };
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(&path)
+        .map_err(|e| io_error("open", &path, e))?;
+    file.write_all(content.as_bytes()).map_err(|e| io_error("write", &path, e))?;
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `read-lines`, which returns a vector of the file's lines without
+/// their line endings
+fn read_lines(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let path = pop_path(&mut args)?;
+    let file = fs::File::open(&path).map_err(|e| io_error("open", &path, e))?;
+    BufReader::new(file).lines()
+        .map(|line| line.map(Value::String).map_err(|e| io_error("read", &path, e)))
+        .collect::<Result<_, _>>()
+        .map(Value::Vector)
+}
+
+fn file_exists(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let path = pop_path(&mut args)?;
+    Ok(Value::Boolean(Path::new(&path).exists()))
+}
+
+/// The builtin definition for `list-dir`, which returns the sorted names of a directory's entries
+fn list_dir(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let path = pop_path(&mut args)?;
+    let mut names = fs::read_dir(&path)
+        .and_then(|entries| entries
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>())
+        .map_err(|e| io_error("list", &path, e))?;
+    names.sort();
+    Ok(Value::Vector(names.into_iter().map(Value::String).collect()))
+}
+
+/// The builtin definition for `mkdir`, which also creates any missing parent directories
+fn mkdir(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let path = pop_path(&mut args)?;
+    fs::create_dir_all(&path).map_err(|e| io_error("create directory", &path, e))?;
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `delete-file`, which deletes a file or empty directory. Failures are
+/// ignored if the second argument is truthy.
+fn delete_file(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 1, 2)?;
+    let path = pop_path(&mut args)?;
+    let silently = args.pop_front().is_some_and(|silently| silently.is_truthy());
+
+    let result = if Path::new(&path).is_dir() {
+        fs::remove_dir(&path)
+    } else {
+        fs::remove_file(&path)
+    };
+    match result {
+        Ok(()) => Ok(Value::Boolean(true)),
+        Err(_) if silently => Ok(Value::Boolean(false)),
+        Err(e) => Err(io_error("delete", &path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync;
+
+    use super::*;
+
+    fn call(f: fn(&Env, VecDeque<Value>) -> Result<Value, RuntimeError>, env: &Env, args: &[Value]) -> Result<Value, RuntimeError> {
+        f(env, args.iter().cloned().collect())
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_files() {
+        let env = Env::default();
+        let dir = std::env::temp_dir().join(format!("nlisp-fs-test-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let file = format!("{}/nested/out.txt", dir);
+
+        call(mkdir, &env, &[string(&format!("{}/nested", dir))]).unwrap();
+        assert_eq!(Ok(Value::Boolean(false)), call(file_exists, &env, &[string(&file)]));
+        call(spit, &env, &[string(&file), string("one\n")]).unwrap();
+        call(spit, &env, &[string(&file), Value::Integer(2), Value::Keyword(":append".to_string()), Value::Boolean(true)]).unwrap();
+        assert_eq!(Ok(string("one\n2")), call(slurp, &env, &[string(&file)]));
+        assert_eq!(Ok(Value::Vector(sync::Vector::from_iter([string("one"), string("2")]))), call(read_lines, &env, &[string(&file)]));
+        assert_eq!(Ok(Value::Vector(sync::Vector::from_iter([string("out.txt")]))), call(list_dir, &env, &[string(&format!("{}/nested", dir))]));
+
+        assert!(matches!(call(delete_file, &env, &[string(&format!("{}/nested", dir))]), Err(RuntimeError::Io { action: "delete", .. })));
+        assert_eq!(Ok(Value::Boolean(true)), call(delete_file, &env, &[string(&file)]));
+        assert_eq!(Ok(Value::Boolean(false)), call(delete_file, &env, &[string(&file), Value::Boolean(true)]));
+        assert!(matches!(call(slurp, &env, &[string(&file)]), Err(RuntimeError::Io { action: "read", .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
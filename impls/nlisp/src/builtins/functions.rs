@@ -0,0 +1,52 @@
+use std::collections::{LinkedList, VecDeque};
+
+use crate::builtins::assert_args_length;
+use crate::Env;
+use crate::evaluator::{quote, RuntimeError, TypeError};
+use crate::types::{Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("source".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("source", source)
+    ));
+}
+
+/// The builtin definition for `source`, which returns the `fn*` form that a closure was created
+/// from, or nil for builtins
+fn source(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("source to have an argument") {
+        Value::Function(FunctionBody::Closure { name, arities, .. }) => {
+            let mut form = LinkedList::from([Expr::Symbol("fn*".to_string())]);
+            if let Some(name) = name {
+                form.push_back(Expr::Symbol(name));
+            }
+            match arities.as_slice() {
+                [arity] => match &arity.source {
+                    Expr::List(clause) => form.extend(clause.iter().cloned()),
+                    clause => form.push_back(clause.clone()),
+                },
+                arities => form.extend(arities.iter().map(|arity| arity.source.clone())),
+            }
+            quote(Expr::List(form))
+        }
+        Value::Function(_) => Ok(Value::Nil),
+        _ => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        let env = Env::default();
+        rep("(def! f (fn* [x {:keys [y]}] (prn x) (+ x y)))", &env).unwrap();
+        assert_eq!("(fn* [x {:keys [y]}] (prn x) (+ x y))\n", rep("(source f)", &env).unwrap());
+        assert_eq!("(fn* g ([] 0) ([x] x))\n", rep("(source (fn* g ([] 0) ([x] x)))", &env).unwrap());
+        assert_eq!("nil\n", rep("(source +)", &env).unwrap());
+    }
+}
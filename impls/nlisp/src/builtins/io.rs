@@ -2,33 +2,102 @@ use std::collections::VecDeque;
 
 use itertools::Itertools;
 
+use crate::builtins::assert_args_length;
+use crate::builtins::dynamic::{BindingGuard, define_var};
+use crate::builtins::special_forms::implicit_do;
 use crate::Env;
-use crate::evaluator::RuntimeError;
-use crate::printer::Printable;
-use crate::types::{FunctionBody, Value};
+use crate::evaluator::{evaluate_expr, RuntimeError};
+use crate::port::{current_port, ERR_VAR, OUT_VAR, OutputPort, SharedBuffer};
+use crate::pprint::pprint_value;
+use crate::printer::{
+    DEFAULT_ATOM_DEPTH, DEFAULT_RIGHT_MARGIN, PRINT_ATOM_DEPTH_VAR, PRINT_ATOMS_OPAQUE_VAR, PRINT_LENGTH_VAR,
+    PRINT_LEVEL_VAR, PRINT_RIGHT_MARGIN_VAR, PrintOptions,
+};
+use crate::types::{Expr, FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("prn".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(prn)
+        FunctionBody::BuiltinValues("prn", prn)
     ));
     env.insert("println".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(println)
+        FunctionBody::BuiltinValues("println", println)
+    ));
+    env.insert("print".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("print", print)
+    ));
+    env.insert("flush".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("flush", flush)
+    ));
+    env.insert("pprint".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("pprint", pprint)
+    ));
+    env.insert("with-out-str".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("with-out-str", with_out_str)
     ));
-}
 
+    define_var(env, PRINT_LENGTH_VAR.to_string(), Value::Nil, true);
+    define_var(env, PRINT_LEVEL_VAR.to_string(), Value::Nil, true);
+    define_var(env, PRINT_RIGHT_MARGIN_VAR.to_string(), Value::Integer(DEFAULT_RIGHT_MARGIN as i64), true);
+    define_var(env, PRINT_ATOM_DEPTH_VAR.to_string(), Value::Integer(DEFAULT_ATOM_DEPTH as i64), true);
+    define_var(env, PRINT_ATOMS_OPAQUE_VAR.to_string(), Value::Boolean(false), true);
+    define_var(env, OUT_VAR.to_string(), Value::OutputPort(OutputPort::new(OUT_VAR, std::io::stdout())), true);
+    define_var(env, ERR_VAR.to_string(), Value::OutputPort(OutputPort::new(ERR_VAR, std::io::stderr())), true);
+}
 
-fn prn(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
-    let output = args.into_iter()
-        .map(|value| value.print_value(true))
+/// Prints each value separated by spaces to `*out*`
+fn write_out(env: &Env, args: VecDeque<Value>, readable: bool, end: &str) -> Result<Value, RuntimeError> {
+    let options = PrintOptions::from_env(env);
+    let mut output = args.into_iter()
+        .map(|value| value.print_value_with_options(readable, &options))
         .join(" ");
-    println!("{}", output);
+    output.push_str(end);
+    current_port(env, OUT_VAR)?.write_str(&output)?;
     Ok(Value::Nil)
 }
 
-fn println(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
-    let output = args.into_iter()
-        .map(|value| value.print_value(false))
-        .join(" ");
-    println!("{}", output);
+fn prn(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    write_out(env, args, true, "\n")
+}
+
+fn println(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    write_out(env, args, false, "\n")
+}
+
+/// The builtin definition for `print`, which is `println` without the trailing newline
+fn print(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    write_out(env, args, false, "")
+}
+
+/// The builtin definition for `flush`, which flushes `*out*`
+fn flush(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 0)?;
+    current_port(env, OUT_VAR)?.flush()?;
     Ok(Value::Nil)
 }
+
+/// The builtin definition for `pprint`, which lays out its argument to fit within
+/// `*print-right-margin*` columns, truncated according to `*print-length*` and `*print-level*`
+fn pprint(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let value = args.pop_front().expect("pprint to have an argument");
+    let output = pprint_value(&value, &PrintOptions::from_env(env));
+    current_port(env, OUT_VAR)?.write_str(&format!("{}\n", output))?;
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `with-out-str`, which evaluates its body with `*out*` bound to a
+/// fresh buffer and returns everything that was printed to it
+fn with_out_str(env: &Env, arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    let out_var = match env.lookup_err(OUT_VAR)? {
+        Value::DynamicVar(var) => var,
+        _ => return Err(RuntimeError::NotDynamic(OUT_VAR.to_string())),
+    };
+    let buffer = SharedBuffer::default();
+    out_var.push_binding(Value::OutputPort(OutputPort::new("with-out-str", buffer.clone())));
+    let guard = BindingGuard(vec![out_var]);
+
+    evaluate_expr(implicit_do(arg_exprs.into_iter().collect()), env)?;
+    drop(guard);
+    let output = String::from_utf8_lossy(&buffer.0.borrow()).into_owned();
+    Ok(Value::String(output))
+}
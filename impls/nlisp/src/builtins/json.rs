@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+
+use crate::builtins::assert_args_length_between;
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::json::{JsonOptions, parse, stringify};
+use crate::types::{FunctionBody, HashableValue, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("json/parse".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("json/parse", json_parse)
+    ));
+    env.insert("json/stringify".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("json/stringify", json_stringify)
+    ));
+}
+
+/// Whether the keyword `option` is set to a truthy value in an optional map of options.
+fn option_enabled(options: Option<&Value>, option: &str) -> Result<bool, RuntimeError> {
+    match options {
+        None | Some(Value::Nil) => Ok(false),
+        Some(Value::HashMap(options)) => Ok(options.get(&HashableValue::Keyword(option.to_string()))
+            .is_some_and(Value::is_truthy)),
+        Some(_) => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+/// The builtin definition for `json/parse`, e.g. `(json/parse "{\"a\": [1]}" {:keywordize-keys true})`
+/// gives `{:a [1]}`
+fn json_parse(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 1, 2)?;
+    let text = match args.pop_front().expect("json/parse to have an argument") {
+        Value::String(text) => text,
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+    let keywordize_keys = option_enabled(args.front(), ":keywordize-keys")?;
+    parse(&text, keywordize_keys).map_err(|e| RuntimeError::InvalidJson(e.to_string()))
+}
+
+/// The builtin definition for `json/stringify`, which accepts `:pretty` and `:sort-keys` options
+fn json_stringify(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 1, 2)?;
+    let value = args.pop_front().expect("json/stringify to have an argument");
+    let options = JsonOptions {
+        pretty: option_enabled(args.front(), ":pretty")?,
+        sort_keys: option_enabled(args.front(), ":sort-keys")?,
+    };
+    stringify(&value, &options).map(Value::String).map_err(RuntimeError::NotJson)
+}
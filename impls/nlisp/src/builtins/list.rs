@@ -3,26 +3,27 @@ use std::collections::VecDeque;
 use crate::builtins::assert_args_length;
 use crate::Env;
 use crate::evaluator::RuntimeError;
-use crate::types::{FunctionBody, Value};
+use crate::sync;
+use crate::types::{FunctionBody, TransientCollection, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("list".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(list_f)
+        FunctionBody::BuiltinValues("list", list_f)
     ));
     env.insert("list?".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(list_p)
+        FunctionBody::BuiltinValues("list?", list_p)
     ));
     env.insert("empty?".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(empty_p)
+        FunctionBody::BuiltinValues("empty?", empty_p)
     ));
     env.insert("count".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(count)
+        FunctionBody::BuiltinValues("count", count)
     ));
 }
 
 /// The builtin definition for `list`
 fn list_f(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
-    let mut ret_list = rpds::List::new();
+    let mut ret_list = sync::List::default();
     for arg in args.into_iter().rev() {
         ret_list.push_front_mut(arg);
     }
@@ -44,13 +45,20 @@ fn empty_p(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError>
     assert_args_length(&args, 1)?;
     let arg = args.pop_front().expect("empty? to have an argument");
     let seq = arg.to_seq()?;
-    Ok(Value::Boolean(seq.len() == 0))
+    Ok(Value::Boolean(seq.is_empty()))
 }
 
 /// The builtin definition for `count`
 fn count(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
     assert_args_length(&args, 1)?;
     let arg = args.pop_front().expect("count to have an argument");
+    if let Value::Transient(transient) = &arg {
+        return match &*transient.borrow() {
+            Some(TransientCollection::Vector(values)) => Ok(Value::Integer(values.len() as i64)),
+            Some(TransientCollection::HashMap(pairs)) => Ok(Value::Integer(pairs.size() as i64)),
+            None => Err(RuntimeError::TransientUsedAfterPersistent),
+        };
+    }
     let seq = arg.to_seq()?;
     Ok(Value::Integer(seq.len() as i64))
 }
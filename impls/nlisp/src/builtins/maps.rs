@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use crate::builtins::{hash_key, assert_args_length, assert_args_length_at_least, assert_args_length_between};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::sync;
+use crate::types::{FunctionBody, HashableValue, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("get".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("get", get)
+    ));
+    env.insert("assoc".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("assoc", assoc)
+    ));
+    env.insert("dissoc".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("dissoc", dissoc)
+    ));
+    env.insert("keys".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("keys", keys)
+    ));
+    env.insert("vals".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("vals", vals)
+    ));
+}
+
+/// The builtin definition for `get`, e.g. `(get m :a)` or `(get m :a default)`, which looks up a key
+/// in a map or record, an index in a vector, or an element of a set. Anything that isn't found
+/// gives the default, or nil.
+fn get(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 2, 3)?;
+
+    let coll = args.pop_front().expect("get to have a collection");
+    let key = args.pop_front().expect("get to have a key");
+    let not_found = args.pop_front().unwrap_or(Value::Nil);
+    let found = match (coll, key) {
+        (Value::Vector(elems), Value::Integer(index)) => usize::try_from(index).ok()
+            .and_then(|index| elems.get(index).cloned()),
+        (coll, key) => match (coll, key.try_into()) {
+            (Value::HashMap(pairs), Ok(key)) => pairs.get(&key).cloned(),
+            (Value::Record(record), Ok(key)) => record.get(&key).cloned(),
+            (Value::Set(elems), Ok(key)) if elems.contains(&key) => Some(key.into()),
+            _ => None,
+        },
+    };
+    Ok(found.unwrap_or(not_found))
+}
+
+/// The builtin definition for `assoc`, e.g. `(assoc m :a 1 :b 2)`, which adds or replaces keys in
+/// a map or record. nil is treated as an empty map.
+fn assoc(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 3)?;
+    if args.len().is_multiple_of(2) {
+        let key = args.pop_back().expect("assoc to have an unpaired key");
+        return Err(RuntimeError::UnpairedKey { name: "assoc", key });
+    }
+
+    let mut coll = args.pop_front().expect("assoc to have a map");
+    while let (Some(key), Some(value)) = (args.pop_front(), args.pop_front()) {
+        let key = hash_key(key)?;
+        coll = match coll {
+            Value::HashMap(pairs) => Value::HashMap(pairs.insert(key, value)),
+            Value::Record(record) => Value::Record(record.assoc(key, value)),
+            Value::Nil => Value::HashMap(sync::HashTrieMap::default().insert(key, value)),
+            value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a map", value })),
+        };
+    }
+    Ok(coll)
+}
+
+/// The builtin definition for `dissoc`, e.g. `(dissoc m :a :b)`. Removing one of a record's fields
+/// gives a plain map.
+fn dissoc(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 1)?;
+
+    let mut coll = args.pop_front().expect("dissoc to have a map");
+    for key in args {
+        let key = hash_key(key)?;
+        coll = match coll {
+            Value::HashMap(pairs) => Value::HashMap(pairs.remove(&key)),
+            Value::Record(record) => record.dissoc(&key),
+            Value::Nil => Value::Nil,
+            value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a map", value })),
+        };
+    }
+    Ok(coll)
+}
+
+/// Collects one part of each entry in a map or record, or returns nil if there are none.
+fn map_entries<F>(map: Value, part: F) -> Result<Value, RuntimeError>
+    where F: Fn(&HashableValue, &Value) -> Value
+{
+    let parts: sync::List<Value> = match &map {
+        Value::HashMap(pairs) => pairs.iter().map(|(key, value)| part(key, value)).collect(),
+        Value::Record(record) => record.entries().map(|(key, value)| part(key, value)).collect(),
+        Value::Nil => sync::List::default(),
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a map", value: map })),
+    };
+    Ok(if parts.is_empty() { Value::Nil } else { Value::List(parts) })
+}
+
+/// The builtin definition for `keys`. A record's fields come first, in the order they were declared.
+fn keys(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    map_entries(args.pop_front().expect("keys to have a map"), |key, _| key.clone().into())
+}
+
+fn vals(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    map_entries(args.pop_front().expect("vals to have a map"), |_, value| value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let env = Env::default();
+        assert_eq!("1\n", rep("(get {:a 1} :a)", &env).unwrap());
+        assert_eq!("nil\n", rep("(get {:a 1} :b)", &env).unwrap());
+        assert_eq!(":none\n", rep("(get {:a 1} :b :none)", &env).unwrap());
+        assert_eq!("3\n", rep("(get [1 2 3] 2)", &env).unwrap());
+        assert_eq!("nil\n", rep("(get [1 2 3] -1)", &env).unwrap());
+        assert_eq!("nil\n", rep("(get nil :a)", &env).unwrap());
+    }
+
+    #[test]
+    fn test_assoc_and_dissoc() {
+        let env = Env::default();
+        assert_eq!("{:a 1}\n", rep("(assoc nil :a 1)", &env).unwrap());
+        assert_eq!("true\n", rep("(= {:a 2 :b 3} (assoc {:a 1} :a 2 :b 3))", &env).unwrap());
+        assert_eq!("{:b 2}\n", rep("(dissoc {:a 1 :b 2 :c 3} :a :c)", &env).unwrap());
+        assert_eq!(
+            Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a map", value: Value::Integer(1) })),
+            rep("(assoc 1 :a 1)", &env),
+        );
+        assert_eq!(
+            Err(RuntimeError::UnpairedKey { name: "assoc", key: Value::Keyword(":b".to_string()) }),
+            rep("(assoc {} :a 1 :b)", &env),
+        );
+    }
+
+    #[test]
+    fn test_keys_and_vals() {
+        let env = Env::default();
+        assert_eq!("(:a)\n", rep("(keys {:a 1})", &env).unwrap());
+        assert_eq!("(1)\n", rep("(vals {:a 1})", &env).unwrap());
+        assert_eq!("nil\n", rep("(keys {})", &env).unwrap());
+    }
+}
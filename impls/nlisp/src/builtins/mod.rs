@@ -1,18 +1,38 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use crate::Env;
-use crate::evaluator::{evaluate_expr, RuntimeError};
+use crate::evaluator::{apply_function_to_values, evaluate_expr, RuntimeError};
 use crate::parser::parse_text_to_expression;
-use crate::types::Value;
+use crate::sandbox::Capabilities;
+use crate::types::{HashableValue, Value};
 
 mod arithmetic;
+mod destructure;
+mod dynamic;
+mod edn;
+mod exceptions;
+mod fs;
+mod functions;
 mod special_forms;
 mod list;
 mod io;
+mod json;
 mod comparison;
+#[cfg(feature = "sync")]
+mod channels;
+#[cfg(feature = "sync")]
+mod concurrency;
 mod sequencing;
 mod string;
 mod atoms;
+mod seq;
+mod maps;
+mod namespaces;
+mod polymorphism;
+mod process;
+mod records;
+pub mod reader_tags;
+mod transients;
 
 pub fn insert_core_functions(env: &Env) {
     arithmetic::insert_functions(env);
@@ -23,6 +43,49 @@ pub fn insert_core_functions(env: &Env) {
     sequencing::insert_functions(env);
     string::insert_functions(env);
     atoms::insert_functions(env);
+    #[cfg(feature = "sync")]
+    concurrency::insert_functions(env);
+    #[cfg(feature = "sync")]
+    channels::insert_functions(env);
+    seq::insert_functions(env);
+    maps::insert_functions(env);
+    transients::insert_functions(env);
+    namespaces::insert_functions(env);
+    dynamic::insert_functions(env);
+    exceptions::insert_functions(env);
+    functions::insert_functions(env);
+    polymorphism::insert_functions(env);
+    records::insert_functions(env);
+    reader_tags::insert_functions(env);
+    edn::insert_functions(env);
+    json::insert_functions(env);
+    fs::insert_functions(env);
+    process::insert_functions(env);
+}
+
+/// Removes the builtins for capabilities that aren't allowed from `core_env`. Each group is found by
+/// inserting it into an empty environment, so the groups can't get out of sync with their modules.
+pub fn remove_disallowed(core_env: &Env, capabilities: Capabilities) {
+    let groups = [
+        (capabilities.io, io::insert_functions as fn(&Env)),
+        (capabilities.fs, fs::insert_functions),
+        (capabilities.process, process::insert_functions),
+    ];
+    for (_, insert_functions) in groups.into_iter().filter(|(allowed, _)| !allowed) {
+        let group_env = Env::with_map(HashMap::new());
+        insert_functions(&group_env);
+        for name in group_env.local_names() {
+            if let Some(Value::Function(_)) = group_env.lookup_local(&name) {
+                core_env.remove(&name);
+            }
+        }
+    }
+
+    // Without file access, `require` can only find namespaces that are already loaded
+    if !capabilities.fs {
+        core_env.remove("add-load-path!");
+        core_env.runtime().namespaces.clear_load_path();
+    }
 }
 
 pub fn insert_core_closures(into_env: &Env, closure_env: &Env) {
@@ -38,11 +101,24 @@ fn run_to_closure(expr_src: &str, env: &Env) -> Value {
     }
 }
 
+/// Calls `function` with already-evaluated arguments, for builtins that take functions as arguments.
+fn apply_value(env: &Env, function: &Value, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    match function {
+        Value::Function(function_body) => apply_function_to_values(function_body, args, env),
+        _ => Err(RuntimeError::CannotApplyNonFunction),
+    }
+}
+
+/// Converts a value to a map key or set element, failing for values that can't be hashed.
+fn hash_key(value: Value) -> Result<HashableValue, RuntimeError> {
+    value.clone().try_into().map_err(|_| RuntimeError::HashError(value))
+}
+
 fn assert_args_length<T>(args: &VecDeque<T>, expected_num_args: usize) -> Result<(), RuntimeError> {
     if args.len() != expected_num_args {
         return Err(RuntimeError::FunctionApplicationWrongNumberOfArgs {
             given: args.len(),
-            expected: 2,
+            expected: expected_num_args,
         });
     }
     Ok(())
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::Env;
+use crate::evaluator::{quote, RuntimeError, TypeError};
+use crate::namespace::{find_or_create_namespace, load_namespace, NamespaceError};
+use crate::types::{Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("ns".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("ns", ns)
+    ));
+    env.insert("in-ns".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("in-ns", in_ns)
+    ));
+    env.insert("require".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("require", require)
+    ));
+    env.insert("load-path".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("load-path", load_path)
+    ));
+    env.insert("add-load-path!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("add-load-path!", add_load_path)
+    ));
+}
+
+/// The builtin definition for `ns`, e.g. `(ns my.app (:require [my.lib :as lib :refer [helper]]))`.
+/// Switches to the namespace, creating it if needed, then processes its `:require` clauses.
+fn ns(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let name = match arg_exprs.pop_front().expect("ns to have a name") {
+        Expr::Symbol(name) => name,
+        _ => return Err(RuntimeError::ExpectedToBindSymbol),
+    };
+    let namespace_env = find_or_create_namespace(env, &name);
+    env.runtime().namespaces.set_current(&name);
+
+    for clause in arg_exprs {
+        match quote(clause)? {
+            Value::List(clause) => match clause.first() {
+                Some(Value::Keyword(k)) if k == ":require" => {
+                    require_all(&namespace_env, clause.iter().skip(1))?;
+                }
+                _ => return Err(NamespaceError::InvalidRequireSpec(Value::List(clause).to_string()).into()),
+            },
+            clause => return Err(NamespaceError::InvalidRequireSpec(clause.to_string()).into()),
+        }
+    }
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `in-ns`
+fn in_ns(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("in-ns to have an argument") {
+        Value::Symbol(name) => {
+            find_or_create_namespace(env, &name);
+            env.runtime().namespaces.set_current(&name);
+            Ok(Value::Nil)
+        }
+        _ => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+/// The builtin definition for `require`, e.g. `(require '[my.lib :as lib])`
+fn require(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    require_all(env, args.iter())?;
+    Ok(Value::Nil)
+}
+
+fn is_reload_flag(value: &Value) -> bool {
+    matches!(value, Value::Keyword(k) if k == ":reload")
+}
+
+/// Requires each spec in `args`. A `:reload` flag among them, e.g.
+/// `(require '[my.lib :as lib] :reload)`, reloads every spec.
+fn require_all<'a>(into_env: &Env, args: impl Iterator<Item=&'a Value>) -> Result<(), RuntimeError> {
+    let (flags, specs): (Vec<_>, Vec<_>) = args.partition(|arg| is_reload_flag(arg));
+    for spec in specs {
+        require_spec(into_env, spec, !flags.is_empty())?;
+    }
+    Ok(())
+}
+
+/// Loads the namespace named by a require spec, then sets up any aliases and refers in the
+/// namespace of `into_env`. A spec is either a symbol or a vector like
+/// `[my.lib :as lib :refer [a b]]`, where `:refer :all` refers every public definition and a
+/// `:reload` flag anywhere after the name reloads the file even if it was already loaded.
+fn require_spec(into_env: &Env, spec: &Value, reload: bool) -> Result<(), RuntimeError> {
+    let invalid_spec = || RuntimeError::from(NamespaceError::InvalidRequireSpec(spec.to_string()));
+
+    let (name, options) = match spec {
+        Value::Symbol(name) => (name.to_string(), vec![]),
+        Value::Vector(elems) => match elems.first() {
+            Some(Value::Symbol(name)) => (name.to_string(), elems.iter().skip(1).cloned().collect()),
+            _ => return Err(invalid_spec()),
+        },
+        _ => return Err(invalid_spec()),
+    };
+
+    let (flags, options): (Vec<_>, Vec<_>) = options.into_iter().partition(is_reload_flag);
+    load_namespace(into_env, &name, reload || !flags.is_empty())?;
+
+    let namespaces = &into_env.runtime().namespaces;
+    let mut options_iter = options.into_iter();
+    while let Some(option) = options_iter.next() {
+        match (option, options_iter.next()) {
+            (Value::Keyword(k), Some(Value::Symbol(alias))) if k == ":as" => {
+                namespaces.add_alias(into_env.namespace(), &alias, &name);
+            }
+            (Value::Keyword(k), Some(Value::Keyword(all))) if k == ":refer" && all == ":all" => {
+                let target = namespaces.get(&name).ok_or_else(invalid_spec)?;
+                for referred in target.env.local_names() {
+                    namespaces.add_refer(into_env.namespace(), &referred, &name);
+                }
+            }
+            (Value::Keyword(k), Some(Value::Vector(referred))) if k == ":refer" => {
+                let target = namespaces.get(&name).ok_or_else(invalid_spec)?;
+                for referred in referred.iter() {
+                    match referred {
+                        Value::Symbol(referred) if target.env.lookup(referred).is_some() => {
+                            namespaces.add_refer(into_env.namespace(), referred, &name);
+                        }
+                        Value::Symbol(referred) => return Err(RuntimeError::UnboundSymbol(format!("{}/{}", name, referred))),
+                        _ => return Err(invalid_spec()),
+                    }
+                }
+            }
+            _ => return Err(invalid_spec()),
+        }
+    }
+    Ok(())
+}
+
+/// The builtin definition for `load-path`, which returns the directories searched by `require`
+fn load_path(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 0)?;
+    let dirs = env.runtime().namespaces.load_path().into_iter()
+        .map(|dir| Value::String(dir.to_string_lossy().to_string()))
+        .collect();
+    Ok(Value::Vector(dirs))
+}
+
+/// The builtin definition for `add-load-path!`
+fn add_load_path(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("add-load-path! to have an argument") {
+        Value::String(dir) => {
+            env.runtime().namespaces.add_load_path(PathBuf::from(dir));
+            Ok(Value::Nil)
+        }
+        _ => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::builtins::dynamic::define_var;
+use crate::builtins::special_forms::fn_f;
+use crate::dispatch::{MultiMethod, Protocol};
+use crate::Env;
+use crate::evaluator::{evaluate_expr, RuntimeError, TypeError};
+use crate::sync::Shared;
+use crate::types::{Expr, FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("defmulti".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("defmulti", defmulti)
+    ));
+    env.insert("defmethod".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("defmethod", defmethod)
+    ));
+    env.insert("defprotocol".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("defprotocol", defprotocol)
+    ));
+    env.insert("extend-type".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("extend-type", extend_type)
+    ));
+    env.insert("satisfies?".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("satisfies?", satisfies)
+    ));
+    env.insert("type".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("type", type_f)
+    ));
+}
+
+fn pop_name(arg_exprs: &mut VecDeque<Expr>) -> Result<String, RuntimeError> {
+    match arg_exprs.pop_front() {
+        Some(Expr::Symbol(name)) => Ok(name),
+        _ => Err(RuntimeError::ExpectedToBindSymbol),
+    }
+}
+
+/// The builtin definition for `defmulti`, e.g. `(defmulti area (fn* [kind size] kind))`,
+/// which defines a function that calls the method registered with `defmethod` for whatever the
+/// dispatch function returns
+fn defmulti(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length(&arg_exprs, 2)?;
+
+    let name = pop_name(&mut arg_exprs)?;
+    let dispatch = match evaluate_expr(arg_exprs.pop_front().expect("defmulti to have a dispatch function"), env)? {
+        Value::Function(dispatch) => dispatch,
+        value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a dispatch function", value })),
+    };
+    let multi = Value::Function(FunctionBody::MultiMethod(Shared::new(MultiMethod::new(name.clone(), dispatch))));
+    define_var(env, name, multi.clone(), false);
+    Ok(multi)
+}
+
+/// The builtin definition for `defmethod`, e.g. `(defmethod area :circle [_ r] ...)`, which adds the
+/// method that a multimethod calls when its dispatch function returns the given value. The method
+/// for `:default` is called when no other method matches. The rest of the form is the same as `fn*`,
+/// so a method can have several arities.
+fn defmethod(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 3)?;
+
+    let name = pop_name(&mut arg_exprs)?;
+    let multi = match env.lookup_err(&name)? {
+        Value::Function(FunctionBody::MultiMethod(multi)) => multi,
+        value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a multimethod", value })),
+    };
+    let dispatch_value = evaluate_expr(arg_exprs.pop_front().expect("defmethod to have a dispatch value"), env)?;
+    let method = match fn_f(env, arg_exprs)? {
+        Value::Function(method) => method,
+        _ => unreachable!("fn* to return a function"),
+    };
+    multi.add_method(dispatch_value, method);
+    Ok(Value::Function(FunctionBody::MultiMethod(multi)))
+}
+
+/// The builtin definition for `defprotocol`, e.g. `(defprotocol Shape (area [this]) (scale [this factor]))`,
+/// which defines the protocol and a function for each method that calls the implementation for
+/// the type of its first argument. A method can list several parameter vectors, and the protocol
+/// and its methods can have docstrings, but these are only for the reader.
+fn defprotocol(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let name = pop_name(&mut arg_exprs)?;
+    let mut method_names = Vec::with_capacity(arg_exprs.len());
+    for signature in arg_exprs {
+        match signature {
+            Expr::String(_) => {}
+            Expr::List(signature) => match signature.front() {
+                Some(Expr::Symbol(method_name)) => method_names.push(method_name.to_string()),
+                _ => return Err(invalid_definition(&name, "expected every method to start with its name")),
+            },
+            _ => return Err(invalid_definition(&name, "expected every method to be a list")),
+        }
+    }
+
+    let protocol = Shared::new(Protocol::new(name.clone(), method_names));
+    for (index, method_name) in protocol.method_names.iter().enumerate() {
+        define_var(env, method_name.to_string(), Value::Function(FunctionBody::ProtocolMethod(protocol.clone(), index)), false);
+    }
+    define_var(env, name, Value::Protocol(protocol.clone()), false);
+    Ok(Value::Protocol(protocol))
+}
+
+fn invalid_definition(name: &str, reason: &str) -> RuntimeError {
+    RuntimeError::InvalidFunctionDefinition(format!("{}: {}", name, reason))
+}
+
+/// The builtin definition for `extend-type`, e.g. `(extend-type String Shape (area [s] (count s)))`,
+/// which implements the methods of one or more protocols for a type. The type is one of the names
+/// returned by `type`, `nil`, or `Object` to implement a method for every type except nil. A record
+/// declared in the current namespace can be given without its namespace. Each method is the method
+/// name followed by the same arguments as `fn*`.
+fn extend_type(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let type_name = match arg_exprs.pop_front().expect("extend-type to have a type") {
+        Expr::Symbol(type_name) if type_name == "nil" => "Nil".to_string(),
+        Expr::Symbol(type_name) if Value::BUILTIN_TYPE_NAMES.contains(&type_name.as_str()) => type_name,
+        // A record type, which is qualified with the current namespace unless it already is
+        Expr::Symbol(type_name) if type_name.contains('/') => type_name,
+        Expr::Symbol(type_name) => format!("{}/{}", env.namespace(), type_name),
+        Expr::Nil => "Nil".to_string(),
+        _ => return Err(RuntimeError::ExpectedToBindSymbol),
+    };
+    extend(env, type_name, arg_exprs)?;
+    Ok(Value::Nil)
+}
+
+/// Implements the protocols and methods in `exprs`, which are written as in `extend-type`, for `type_name`.
+pub(super) fn extend<T>(env: &Env, type_name: String, exprs: T) -> Result<(), RuntimeError>
+    where T: IntoIterator<Item=Expr>
+{
+    let mut protocol_methods: Vec<(Shared<Protocol>, HashMap<String, FunctionBody>)> = vec![];
+    for expr in exprs {
+        match expr {
+            Expr::Symbol(protocol_name) => match env.lookup_err(&protocol_name)? {
+                Value::Protocol(protocol) => protocol_methods.push((protocol, HashMap::new())),
+                value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a protocol", value })),
+            },
+            Expr::List(mut method_exprs) => {
+                let Some((protocol, methods)) = protocol_methods.last_mut() else {
+                    return Err(invalid_definition(&type_name, "expected a protocol before its methods"));
+                };
+                let method_name = match method_exprs.pop_front() {
+                    Some(Expr::Symbol(method_name)) if protocol.method_names.contains(&method_name) => method_name,
+                    Some(Expr::Symbol(method_name)) => {
+                        return Err(invalid_definition(&type_name, &format!("`{}` isn't a method of {}", method_name, protocol.name)));
+                    }
+                    _ => return Err(invalid_definition(&type_name, "expected every method to start with its name")),
+                };
+                match fn_f(env, method_exprs.into_iter().collect())? {
+                    Value::Function(method) => methods.insert(method_name, method),
+                    _ => unreachable!("fn* to return a function"),
+                };
+            }
+            _ => return Err(invalid_definition(&type_name, "expected protocols and method definitions")),
+        }
+    }
+
+    for (protocol, methods) in protocol_methods {
+        protocol.extend(type_name.clone(), methods);
+    }
+    Ok(())
+}
+
+/// The builtin definition for `satisfies?`, e.g. `(satisfies? Shape x)`, which checks whether a
+/// protocol has been extended to the type of a value
+fn satisfies(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+
+    let protocol = match args.pop_front().expect("satisfies? to have a protocol") {
+        Value::Protocol(protocol) => protocol,
+        value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a protocol", value })),
+    };
+    Ok(Value::Boolean(protocol.is_satisfied_by(&args.pop_front().expect("satisfies? to have a value"))))
+}
+
+/// The builtin definition for `type`, which returns the name of a value's type as a symbol, such as
+/// `Integer`, `String`, `Vector` or `HashMap`, or a record's qualified name such as `user/Point`.
+/// These are the names that `extend-type` takes.
+fn type_f(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    Ok(Value::Symbol(args.pop_front().expect("type to have an argument").type_name().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_multimethods() {
+        let env = Env::default();
+        rep("(defmulti describe (fn* [x y] (if (> x y) :bigger :smaller)))", &env).unwrap();
+        rep("(defmethod describe :bigger [x y] (str x \" > \" y))", &env).unwrap();
+        rep("(defmethod describe :smaller [x y] (str x \" <= \" y))", &env).unwrap();
+        assert_eq!("\"3 > 2\"\n", rep("(describe 3 2)", &env).unwrap());
+        assert_eq!("\"1 <= 2\"\n", rep("(describe 1 2)", &env).unwrap());
+
+        // Redefining a method replaces it, and unhashable dispatch values work too
+        rep("(defmethod describe :smaller [x y] :replaced)", &env).unwrap();
+        assert_eq!(":replaced\n", rep("(describe 1 2)", &env).unwrap());
+        rep("(defmulti pair (fn* [x y] [x y]))", &env).unwrap();
+        rep("(defmethod pair [1 2] [x y] :one-two)", &env).unwrap();
+        assert_eq!(":one-two\n", rep("(pair 1 2)", &env).unwrap());
+        assert_eq!(
+            Err(RuntimeError::NoMethod { name: "pair".to_string(), dispatch_value: rep_value("[2 1]", &env) }),
+            rep("(pair 2 1)", &env),
+        );
+        rep("(defmethod pair :default [x y] :other)", &env).unwrap();
+        assert_eq!(":other\n", rep("(pair 2 1)", &env).unwrap());
+        assert_eq!("#<multi pair>\n", rep("pair", &env).unwrap());
+    }
+
+    #[test]
+    fn test_protocols() {
+        let env = Env::default();
+        rep("(defprotocol Describe \"Things that describe themselves\" (describe [this]) (describe-with [this prefix]))", &env).unwrap();
+        rep("(extend-type Integer Describe (describe [n] (str \"the number \" n)) (describe-with [n prefix] (str prefix n)))", &env).unwrap();
+        rep("(extend-type String Describe (describe [s] (str \"the string \" s)))", &env).unwrap();
+        rep("(extend-type nil Describe (describe [_] \"nothing\"))", &env).unwrap();
+        assert_eq!("\"the number 1\"\n", rep("(describe 1)", &env).unwrap());
+        assert_eq!("\"the string a\"\n", rep("(describe \"a\")", &env).unwrap());
+        assert_eq!("\"nothing\"\n", rep("(describe nil)", &env).unwrap());
+        assert_eq!("\"#1\"\n", rep("(describe-with 1 \"#\")", &env).unwrap());
+        assert_eq!(
+            Err(RuntimeError::NoProtocolImpl { protocol: "Describe".to_string(), method: "describe".to_string(), type_name: "Vector".to_string() }),
+            rep("(describe [1])", &env),
+        );
+        assert_eq!("false\n", rep("(satisfies? Describe [1])", &env).unwrap());
+
+        // Object is the fallback for everything but nil, and is found after a cached miss
+        rep("(extend-type Object Describe (describe [x] (str \"a \" (type x))))", &env).unwrap();
+        assert_eq!("\"a Vector\"\n", rep("(describe [1])", &env).unwrap());
+        assert_eq!("\"the number 1\"\n", rep("(describe 1)", &env).unwrap());
+        assert_eq!("true\n", rep("(satisfies? Describe [1])", &env).unwrap());
+
+        assert_eq!("#<protocol Describe>\n", rep("Describe", &env).unwrap());
+        assert_eq!("#<protocol-fn Describe/describe>\n", rep("describe", &env).unwrap());
+        assert!(matches!(rep("(extend-type Integer Describe (other [n] n))", &env), Err(RuntimeError::InvalidFunctionDefinition(_))));
+    }
+
+    #[test]
+    fn test_type() {
+        let env = Env::default();
+        assert_eq!("(Integer String Keyword HashMap Nil Function)\n", rep("(list (type 1) (type \"a\") (type :a) (type {}) (type nil) (type +))", &env).unwrap());
+    }
+
+    fn rep_value(src: &str, env: &Env) -> Value {
+        crate::evaluator::evaluate_text(src, env).unwrap().pop().unwrap()
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::builtins::{assert_args_length_at_least, assert_args_length_between};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::sync;
+use crate::types::{FunctionBody, HashableValue, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("getenv".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("getenv", getenv)
+    ));
+    env.insert("exit".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("exit", exit)
+    ));
+    env.insert("sh".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("sh", sh)
+    ));
+}
+
+/// The builtin definition for `getenv`, which returns the value of an environment variable, or nil
+/// if it isn't set. With no arguments, returns a map of every variable. Names and values that
+/// aren't valid UTF-8 have the invalid parts replaced.
+fn getenv(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 0, 1)?;
+    match args.pop_front() {
+        None => Ok(Value::HashMap(std::env::vars_os()
+            .map(|(name, value)| (
+                HashableValue::String(name.to_string_lossy().into_owned()),
+                Value::String(value.to_string_lossy().into_owned()),
+            ))
+            .collect())),
+        Some(Value::String(name)) => Ok(std::env::var_os(name)
+            .map(|value| Value::String(value.to_string_lossy().into_owned()))
+            .unwrap_or(Value::Nil)),
+        Some(_) => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    }
+}
+
+/// The builtin definition for `exit`, which ends the process with the given status, or 0
+fn exit(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 0, 1)?;
+    let code = match args.pop_front() {
+        None => 0,
+        Some(Value::Integer(code)) => code as i32,
+        Some(_) => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+    std::process::exit(code)
+}
+
+/// The builtin definition for `sh`, e.g. `(sh "git" "status" :dir "repo")`. Runs a program with
+/// the given arguments and returns `{:exit code :out stdout :err stderr}`. `:in` passes a string to
+/// its stdin and `:dir` sets its working directory.
+fn sh(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 1)?;
+    let mut args = args.into_iter();
+    let mut program = None;
+    let mut program_args = vec![];
+    let mut stdin = None;
+    let mut dir = None;
+    while let Some(arg) = args.next() {
+        match (arg, program.is_some()) {
+            (Value::Keyword(option), true) => match (option.as_str(), args.next()) {
+                (":in", Some(Value::String(input))) => stdin = Some(input),
+                (":dir", Some(Value::String(path))) => dir = Some(path),
+                _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+            },
+            (Value::String(arg), false) => program = Some(arg),
+            (Value::String(arg), true) => program_args.push(arg),
+            _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+        }
+    }
+    let program = program.ok_or(RuntimeError::IncorrectType(TypeError::Misc))?;
+    let io_error = |e: std::io::Error| RuntimeError::Io { action: "run", path: program.clone(), message: e.to_string() };
+
+    let mut command = Command::new(&program);
+    command.args(&program_args)
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let mut child = command.spawn().map_err(io_error)?;
+    let child_stdin = child.stdin.take();
+    // The input is written from another thread while the output is read, since a child can fill
+    // its output pipe and stop reading before it has been given all of its input
+    let (output, write_result) = thread::scope(|scope| {
+        let writer = scope.spawn(move || match (child_stdin, stdin) {
+            (Some(mut child_stdin), Some(input)) => child_stdin.write_all(input.as_bytes()),
+            _ => Ok(()),
+        });
+        let output = child.wait_with_output();
+        (output, writer.join().expect("stdin writer not to panic"))
+    });
+    let output = output.map_err(io_error)?;
+    match write_result {
+        // The child exited without reading all of its input, which is up to it
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+        result => result.map_err(io_error)?,
+    }
+
+    // A process killed by a signal has no exit code
+    let exit_code = output.status.code().map(|code| Value::Integer(code as i64)).unwrap_or(Value::Nil);
+    Ok(Value::HashMap(sync::HashTrieMap::from_iter([
+        (HashableValue::Keyword(":exit".to_string()), exit_code),
+        (HashableValue::Keyword(":out".to_string()), Value::String(String::from_utf8_lossy(&output.stdout).into_owned())),
+        (HashableValue::Keyword(":err".to_string()), Value::String(String::from_utf8_lossy(&output.stderr).into_owned())),
+    ])))
+}
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use crate::builtins::{apply_value, assert_args_length};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::types::{FunctionBody, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("add-reader-tag!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("add-reader-tag!", add_reader_tag)
+    ));
+
+    let mut reader_tags = env.runtime().reader_tags.borrow_mut();
+    reader_tags.insert("inst".to_string(), Value::Function(FunctionBody::BuiltinValues("inst", read_inst)));
+    reader_tags.insert("uuid".to_string(), Value::Function(FunctionBody::BuiltinValues("uuid", read_uuid)));
+}
+
+/// Reads a tagged literal by passing its unevaluated form to the reader function for `tag`.
+pub fn read_tagged(env: &Env, tag: &str, form: Value) -> Result<Value, RuntimeError> {
+    let reader = env.runtime().reader_tags.borrow().get(tag).cloned()
+        .ok_or_else(|| RuntimeError::UnknownReaderTag(tag.to_string()))?;
+    apply_value(env, &reader, VecDeque::from([form]))
+}
+
+/// The builtin definition for `add-reader-tag!`, e.g. `(add-reader-tag! 'point (fn* [[x y]] {:x x :y y}))`
+/// makes `#point [1 2]` read as `{:x 1 :y 2}`
+fn add_reader_tag(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let tag = match args.pop_front().expect("add-reader-tag! to have a tag") {
+        Value::Symbol(tag) => tag,
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+    let reader = args.pop_front().expect("add-reader-tag! to have a reader function");
+    if !matches!(reader, Value::Function(_)) {
+        return Err(RuntimeError::IncorrectType(TypeError::Misc));
+    }
+
+    env.runtime().reader_tags.borrow_mut().insert(tag, reader);
+    Ok(Value::Nil)
+}
+
+fn invalid_literal(tag: &str, form: &Value, reason: &str) -> RuntimeError {
+    RuntimeError::InvalidTaggedLiteral { tag: tag.to_string(), form: form.to_string(), reason: reason.to_string() }
+}
+
+/// The reader for `#inst`, which accepts an RFC 3339 date like `"2024-01-31"` or timestamp like
+/// `"2024-01-31T12:30:00.5+01:00"`
+fn read_inst(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let form = args.pop_front().expect("#inst to have a form");
+    match &form {
+        Value::String(timestamp) => match validate_timestamp(timestamp) {
+            Ok(()) => Ok(Value::Tagged("inst".to_string(), Box::new(form))),
+            Err(reason) => Err(invalid_literal("inst", &form, reason)),
+        },
+        _ => Err(invalid_literal("inst", &form, "expected a string")),
+    }
+}
+
+/// Checks that `digits` is exactly `len` ASCII digits within `range`.
+fn parse_field(digits: Option<&str>, len: usize, range: std::ops::RangeInclusive<u32>) -> Option<u32> {
+    let digits = digits.filter(|digits| digits.len() == len && digits.bytes().all(|b| b.is_ascii_digit()))?;
+    digits.parse().ok().filter(|value| range.contains(value))
+}
+
+fn validate_timestamp(timestamp: &str) -> Result<(), &'static str> {
+    let (date, time) = match timestamp.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (timestamp, None),
+    };
+
+    let mut date_fields = date.split('-');
+    parse_field(date_fields.next(), 4, 0..=9999).ok_or("expected a four digit year")?;
+    parse_field(date_fields.next(), 2, 1..=12).ok_or("expected a month from 01 to 12")?;
+    parse_field(date_fields.next(), 2, 1..=31).ok_or("expected a day from 01 to 31")?;
+    if date_fields.next().is_some() {
+        return Err("expected a date like 2024-01-31");
+    }
+
+    let time = match time {
+        Some(time) => time,
+        None => return Ok(()),
+    };
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(offset_start) => time.split_at(offset_start),
+        None => return Err("expected a UTC offset like Z or +01:00"),
+    };
+
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let mut time_fields = time.split(':');
+    parse_field(time_fields.next(), 2, 0..=23).ok_or("expected an hour from 00 to 23")?;
+    parse_field(time_fields.next(), 2, 0..=59).ok_or("expected minutes from 00 to 59")?;
+    // Leap seconds are allowed
+    parse_field(time_fields.next(), 2, 0..=60).ok_or("expected seconds from 00 to 60")?;
+    if time_fields.next().is_some() {
+        return Err("expected a time like 12:30:00");
+    }
+    if fraction.is_some_and(|fraction| fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit())) {
+        return Err("expected digits after the decimal point");
+    }
+
+    if offset != "Z" {
+        let mut offset_fields = offset[1..].split(':');
+        parse_field(offset_fields.next(), 2, 0..=23).ok_or("expected a UTC offset like Z or +01:00")?;
+        parse_field(offset_fields.next(), 2, 0..=59).ok_or("expected a UTC offset like Z or +01:00")?;
+        if offset_fields.next().is_some() {
+            return Err("expected a UTC offset like Z or +01:00");
+        }
+    }
+    Ok(())
+}
+
+/// The reader for `#uuid`, which accepts the canonical `8-4-4-4-12` hex form
+fn read_uuid(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let form = args.pop_front().expect("#uuid to have a form");
+    match &form {
+        Value::String(uuid) => {
+            let groups = uuid.split('-').map(str::len).collect::<Vec<_>>();
+            let is_hex = uuid.chars().all(|c| c == '-' || c.is_ascii_hexdigit());
+            if groups == [8, 4, 4, 4, 12] && is_hex {
+                Ok(Value::Tagged("uuid".to_string(), Box::new(Value::String(uuid.to_ascii_lowercase()))))
+            } else {
+                Err(invalid_literal("uuid", &form, "expected 32 hex digits grouped like 8-4-4-4-12"))
+            }
+        }
+        _ => Err(invalid_literal("uuid", &form, "expected a string")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_timestamp() {
+        assert_eq!(Ok(()), validate_timestamp("2024-01-31"));
+        assert_eq!(Ok(()), validate_timestamp("2024-01-31T12:30:00Z"));
+        assert_eq!(Ok(()), validate_timestamp("2024-01-31T12:30:00.125-05:30"));
+        assert!(validate_timestamp("2024-13-01").is_err());
+        assert!(validate_timestamp("2024-01-31T25:00:00Z").is_err());
+        assert!(validate_timestamp("2024-01-31T12:30:00").is_err());
+        assert!(validate_timestamp("24-01-31").is_err());
+    }
+
+    #[test]
+    fn test_read_uuid() {
+        let env = Env::default();
+        let uuid = "F81D4FAE-7DEC-11D0-A765-00A0C91E6BF6";
+        assert_eq!(
+            Ok(Value::Tagged("uuid".to_string(), Box::new(Value::String(uuid.to_ascii_lowercase())))),
+            read_tagged(&env, "uuid", Value::String(uuid.to_string()))
+        );
+        assert!(read_tagged(&env, "uuid", Value::String("f81d4fae".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_unknown_tag() {
+        let env = Env::default();
+        assert_eq!(Err(RuntimeError::UnknownReaderTag("point".to_string())), read_tagged(&env, "point", Value::Nil));
+    }
+}
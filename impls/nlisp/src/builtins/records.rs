@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::builtins::dynamic::define_var;
+use crate::builtins::polymorphism::extend;
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::record::{Record, RecordType};
+use crate::sync::{self, Shared};
+use crate::types::{Expr, FunctionBody, HashableValue, NativeFn, NativeFunction, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("defrecord".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("defrecord", defrecord)
+    ));
+}
+
+/// The builtin definition for `defrecord`, e.g. `(defrecord Point [x y])`, which declares a record
+/// type and defines:
+///
+/// * `->Point`, which takes the fields in order
+/// * `map->Point`, which takes a map of field keywords to values, leaving out fields as nil
+/// * `Point?`, which checks whether a value is a `Point`
+/// * `Point-x` and `Point-y`, which get the fields
+///
+/// Protocols and their methods can follow the fields, as in `extend-type`. The methods can get at
+/// the fields with the accessors, `get`, or by destructuring `this`.
+fn defrecord(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 2)?;
+
+    let name = match arg_exprs.pop_front().expect("defrecord to have a name") {
+        Expr::Symbol(name) => name,
+        _ => return Err(RuntimeError::ExpectedToBindSymbol),
+    };
+    if Value::BUILTIN_TYPE_NAMES.contains(&name.as_str()) || name.contains('/') {
+        return Err(RuntimeError::InvalidFunctionDefinition(format!("{}: records can't have the name of a built-in type or a namespace", name)));
+    }
+    let field_names = match arg_exprs.pop_front().expect("defrecord to have fields") {
+        Expr::Vector(fields) => fields.into_iter()
+            .map(|field| match field {
+                Expr::Symbol(field) => Ok(field),
+                _ => Err(RuntimeError::ExpectedToBindSymbol),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(RuntimeError::InvalidFunctionDefinition(format!("{}: expected a vector of fields", name))),
+    };
+    let fields = field_names.iter().map(|field| HashableValue::Keyword(format!(":{}", field))).collect();
+    let qualified_name = format!("{}/{}", env.namespace(), name);
+    let record_type = Shared::new(RecordType { name: name.clone(), qualified_name: qualified_name.clone(), fields });
+
+    let constructor_type = record_type.clone();
+    define_native(env, format!("->{}", name), move |_env, args| {
+        if args.len() != constructor_type.fields.len() {
+            return Err(RuntimeError::FunctionApplicationWrongNumberOfArgs { given: args.len(), expected: constructor_type.fields.len() });
+        }
+        let entries = constructor_type.fields.iter().cloned().zip(args).collect();
+        Ok(Value::Record(Record::new(constructor_type.clone(), entries)))
+    });
+
+    let map_constructor_type = record_type.clone();
+    define_native(env, format!("map->{}", name), move |_env, mut args| {
+        assert_args_length(&args, 1)?;
+        let entries = match args.pop_front().expect("map constructor to have a map") {
+            Value::HashMap(pairs) => pairs,
+            Value::Record(record) => record.to_map(),
+            Value::Nil => sync::HashTrieMap::default(),
+            value => return Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "a map", value })),
+        };
+        Ok(Value::Record(Record::new(map_constructor_type.clone(), entries)))
+    });
+
+    let predicate_type = record_type.clone();
+    define_native(env, format!("{}?", name), move |_env, mut args| {
+        assert_args_length(&args, 1)?;
+        let value = args.pop_front().expect("record predicate to have an argument");
+        Ok(Value::Boolean(matches!(value, Value::Record(record) if record.record_type == predicate_type)))
+    });
+
+    for (field_name, field) in field_names.iter().zip(&record_type.fields) {
+        let accessor_type = record_type.clone();
+        let field = field.clone();
+        define_native(env, format!("{}-{}", name, field_name), move |_env, mut args| {
+            assert_args_length(&args, 1)?;
+            match args.pop_front().expect("record accessor to have an argument") {
+                Value::Record(record) if record.record_type == accessor_type => {
+                    Ok(record.get(&field).cloned().unwrap_or(Value::Nil))
+                }
+                value => Err(RuntimeError::IncorrectType(TypeError::ExpectedRecord { name: accessor_type.qualified_name.clone(), value })),
+            }
+        });
+    }
+
+    extend(env, qualified_name.clone(), arg_exprs)?;
+    Ok(Value::Symbol(qualified_name))
+}
+
+fn define_native(env: &Env, name: String, func: impl NativeFn + 'static) {
+    let function = Value::Function(FunctionBody::Native(NativeFunction::new(name.clone(), func)));
+    define_var(env, name, function, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_defrecord() {
+        let env = Env::default();
+        assert_eq!("user/Point\n", rep("(defrecord Point [x y])", &env).unwrap());
+        rep("(def! p (->Point 1 2))", &env).unwrap();
+        assert_eq!("#Point{:x 1 :y 2}\n", rep("p", &env).unwrap());
+        assert_eq!("(1 2)\n", rep("(list (Point-x p) (Point-y p))", &env).unwrap());
+        assert_eq!("(true false)\n", rep("(list (Point? p) (Point? {:x 1 :y 2}))", &env).unwrap());
+        assert_eq!("#Point{:x 1 :y nil}\n", rep("(map->Point {:x 1})", &env).unwrap());
+        assert_eq!(
+            Err(RuntimeError::FunctionApplicationWrongNumberOfArgs { given: 1, expected: 2 }),
+            rep("(->Point 1)", &env),
+        );
+        assert!(matches!(rep("(Point-x {:x 1})", &env), Err(RuntimeError::IncorrectType(TypeError::ExpectedRecord { .. }))));
+    }
+
+    #[test]
+    fn test_records_as_maps() {
+        let env = Env::default();
+        rep("(defrecord Point [x y])", &env).unwrap();
+        rep("(def! p (->Point 1 2))", &env).unwrap();
+        assert_eq!("1\n", rep("(get p :x)", &env).unwrap());
+        assert_eq!("#Point{:x 3 :y 2}\n", rep("(assoc p :x 3)", &env).unwrap());
+        assert_eq!("#Point{:x 1 :y 2 :z 3}\n", rep("(assoc p :z 3)", &env).unwrap());
+        assert_eq!("{:y 2}\n", rep("(dissoc p :x)", &env).unwrap());
+        assert_eq!("(:x :y)\n", rep("(keys p)", &env).unwrap());
+        assert_eq!("3\n", rep("(let* [{:keys [x y]} p] (+ x y))", &env).unwrap());
+        assert_eq!("2\n", rep("(count p)", &env).unwrap());
+
+        // Records are equal when their types and fields are, and never equal to plain maps
+        assert_eq!("true\n", rep("(= p (->Point 1 2))", &env).unwrap());
+        assert_eq!("false\n", rep("(= p (->Point 1 3))", &env).unwrap());
+        assert_eq!("false\n", rep("(= p {:x 1 :y 2})", &env).unwrap());
+        rep("(defrecord Other [x y])", &env).unwrap();
+        assert_eq!("false\n", rep("(= p (->Other 1 2))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_records_with_protocols() {
+        let env = Env::default();
+        rep("(defprotocol Shape (area [this]))", &env).unwrap();
+        rep("(defrecord Square [side] Shape (area [this] (* (Square-side this) (Square-side this))))", &env).unwrap();
+        rep("(defrecord Rect [w h])", &env).unwrap();
+        rep("(extend-type Rect Shape (area [{:keys [w h]}] (* w h)))", &env).unwrap();
+        assert_eq!("(9 6)\n", rep("(list (area (->Square 3)) (area (->Rect 2 3)))", &env).unwrap());
+        assert_eq!("user/Square\n", rep("(type (->Square 3))", &env).unwrap());
+    }
+
+    #[test]
+    fn test_record_names_are_namespaced() {
+        let env = Env::default();
+        assert!(matches!(rep("(defrecord Integer [n])", &env), Err(RuntimeError::InvalidFunctionDefinition(_))));
+
+        rep("(defprotocol P (f [this]))", &env).unwrap();
+        rep("(extend-type Integer P (f [this] :integer))", &env).unwrap();
+        rep("(defrecord Point [x y] P (f [this] :user-point))", &env).unwrap();
+        rep("(def! p (->Point 1 2))", &env).unwrap();
+        rep("(in-ns 'other)", &env).unwrap();
+        rep("(defrecord Point [x y] user/P (f [this] :other-point))", &env).unwrap();
+        assert_eq!("(:integer :user-point :other-point)\n", rep("(list (user/f 1) (user/f user/p) (user/f (->Point 1 2)))", &env).unwrap());
+        // Records from different namespaces are different types, even with the same name and fields
+        assert_eq!("(false false)\n", rep("(list (= user/p (->Point 1 2)) (Point? user/p))", &env).unwrap());
+    }
+}
@@ -0,0 +1,539 @@
+use std::cmp::Ordering;
+use std::collections::{HashSet, VecDeque};
+
+use crate::builtins::{hash_key, apply_value, assert_args_length, assert_args_length_at_least, assert_args_length_between};
+use crate::builtins::arithmetic::float_operands;
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::sync;
+use crate::types::{FunctionBody, HashableValue, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("map".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("map", map)
+    ));
+    env.insert("mapcat".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("mapcat", mapcat)
+    ));
+    env.insert("apply".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("apply", apply)
+    ));
+    env.insert("reduce".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("reduce", reduce)
+    ));
+    env.insert("filter".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("filter", filter)
+    ));
+    env.insert("remove".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("remove", remove)
+    ));
+    env.insert("some".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("some", some)
+    ));
+    env.insert("every?".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("every?", every_p)
+    ));
+    env.insert("sort".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("sort", sort)
+    ));
+    env.insert("sort-by".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("sort-by", sort_by)
+    ));
+    env.insert("group-by".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("group-by", group_by)
+    ));
+    env.insert("frequencies".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("frequencies", frequencies)
+    ));
+    env.insert("partition".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("partition", partition)
+    ));
+    env.insert("interleave".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("interleave", interleave)
+    ));
+    env.insert("zipmap".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("zipmap", zipmap)
+    ));
+    env.insert("distinct".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("distinct", distinct)
+    ));
+    env.insert("reverse".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("reverse", reverse)
+    ));
+    env.insert("last".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("last", last)
+    ));
+}
+
+/// Converts a sequence argument into a `Vec` so that it can be indexed and iterated repeatedly.
+pub(super) fn seq_to_vec(value: Value) -> Result<Vec<Value>, RuntimeError> {
+    Ok(value.to_seq()?.iter().cloned().collect())
+}
+
+pub(super) fn vec_to_list(values: Vec<Value>) -> Value {
+    Value::List(values.into_iter().collect())
+}
+
+/// Applies `f` to the elements of one or more collections in lockstep, stopping at the shortest.
+fn map_values(env: &Env, f: &Value, colls: VecDeque<Value>) -> Result<Vec<Value>, RuntimeError> {
+    let colls = colls.into_iter()
+        .map(seq_to_vec)
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
+
+    let mut results = Vec::with_capacity(len);
+    for i in 0..len {
+        let args = colls.iter().map(|coll| coll[i].clone()).collect();
+        results.push(apply_value(env, f, args)?);
+    }
+    Ok(results)
+}
+
+/// The builtin definition for `map`
+fn map(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+    let f = args.pop_front().expect("map to have a function argument");
+    Ok(vec_to_list(map_values(env, &f, args)?))
+}
+
+/// The builtin definition for `mapcat`
+fn mapcat(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+    let f = args.pop_front().expect("mapcat to have a function argument");
+
+    let mut results = vec![];
+    for mapped in map_values(env, &f, args)? {
+        results.extend(mapped.to_seq()?.iter().cloned());
+    }
+    Ok(vec_to_list(results))
+}
+
+/// The builtin definition for `apply`. The last argument is spread into the argument list.
+fn apply(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 2)?;
+    let f = args.pop_front().expect("apply to have a function argument");
+    let spread_args = args.pop_back().expect("apply to have a sequence argument");
+    args.extend(spread_args.to_seq()?.iter().cloned());
+    apply_value(env, &f, args)
+}
+
+/// The builtin definition for `reduce`, either `(reduce f coll)` or `(reduce f init coll)`
+fn reduce(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 2, 3)?;
+    let f = args.pop_front().expect("reduce to have a function argument");
+    let coll = args.pop_back().expect("reduce to have a sequence argument");
+
+    let mut elems = coll.to_seq()?.iter().cloned().collect::<VecDeque<_>>();
+    let mut acc = match args.pop_front() {
+        Some(init) => init,
+        None => match elems.pop_front() {
+            Some(first) => first,
+            None => return apply_value(env, &f, VecDeque::new()),
+        }
+    };
+
+    for elem in elems {
+        acc = apply_value(env, &f, VecDeque::from([acc, elem]))?;
+    }
+    Ok(acc)
+}
+
+fn filter_by(env: &Env, mut args: VecDeque<Value>, keep: bool) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let pred = args.pop_front().expect("filter to have a predicate argument");
+    let coll = args.pop_front().expect("filter to have a sequence argument");
+
+    let mut results = vec![];
+    for elem in coll.to_seq()?.iter() {
+        if apply_value(env, &pred, VecDeque::from([elem.clone()]))?.is_truthy() == keep {
+            results.push(elem.clone());
+        }
+    }
+    Ok(vec_to_list(results))
+}
+
+/// The builtin definition for `filter`
+fn filter(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    filter_by(env, args, true)
+}
+
+/// The builtin definition for `remove`
+fn remove(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    filter_by(env, args, false)
+}
+
+/// The builtin definition for `some`. Returns the first truthy result of `pred`, or nil.
+fn some(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let pred = args.pop_front().expect("some to have a predicate argument");
+    let coll = args.pop_front().expect("some to have a sequence argument");
+
+    for elem in coll.to_seq()?.iter() {
+        let result = apply_value(env, &pred, VecDeque::from([elem.clone()]))?;
+        if result.is_truthy() {
+            return Ok(result);
+        }
+    }
+    Ok(Value::Nil)
+}
+
+/// The builtin definition for `every?`
+fn every_p(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let pred = args.pop_front().expect("every? to have a predicate argument");
+    let coll = args.pop_front().expect("every? to have a sequence argument");
+
+    for elem in coll.to_seq()?.iter() {
+        if !apply_value(env, &pred, VecDeque::from([elem.clone()]))?.is_truthy() {
+            return Ok(Value::Boolean(false));
+        }
+    }
+    Ok(Value::Boolean(true))
+}
+
+/// The natural ordering used by `sort` when no comparator is given.
+fn compare_values(lhs: &Value, rhs: &Value) -> Result<Ordering, RuntimeError> {
+    match (lhs, rhs) {
+        (Value::Nil, Value::Nil) => Ok(Ordering::Equal),
+        (Value::Nil, _) => Ok(Ordering::Less),
+        (_, Value::Nil) => Ok(Ordering::Greater),
+        (Value::Integer(l), Value::Integer(r)) => Ok(l.cmp(r)),
+        (Value::String(l), Value::String(r))
+        | (Value::Symbol(l), Value::Symbol(r))
+        | (Value::Keyword(l), Value::Keyword(r)) => Ok(l.cmp(r)),
+        (Value::Char(l), Value::Char(r)) => Ok(l.cmp(r)),
+        (Value::Boolean(l), Value::Boolean(r)) => Ok(l.cmp(r)),
+        (Value::Vector(l), Value::Vector(r)) => {
+            // Like Clojure, shorter vectors sort first, then compare element-wise
+            if l.len() != r.len() {
+                return Ok(l.len().cmp(&r.len()));
+            }
+            for (l_elem, r_elem) in l.iter().zip(r.iter()) {
+                match compare_values(l_elem, r_elem)? {
+                    Ordering::Equal => {}
+                    ordering => return Ok(ordering),
+                }
+            }
+            Ok(Ordering::Equal)
+        }
+        (lhs, rhs) => match float_operands(lhs, rhs) {
+            // NaN sorts after every other number and equal to itself, so the ordering stays total
+            Some((l, r)) => Ok(l.partial_cmp(&r).unwrap_or_else(|| l.is_nan().cmp(&r.is_nan()))),
+            None => Err(RuntimeError::IncorrectType(TypeError::NotComparable)),
+        },
+    }
+}
+
+/// Orders two values with a user comparator, which may either return an integer like `compare`
+/// or a boolean like `<`.
+fn compare_with(env: &Env, comparator: &Value, lhs: &Value, rhs: &Value) -> Result<Ordering, RuntimeError> {
+    match apply_value(env, comparator, VecDeque::from([lhs.clone(), rhs.clone()]))? {
+        Value::Integer(num) => Ok(num.cmp(&0)),
+        Value::Boolean(true) => Ok(Ordering::Less),
+        Value::Boolean(false) | Value::Nil => {
+            if apply_value(env, comparator, VecDeque::from([rhs.clone(), lhs.clone()]))?.is_truthy() {
+                Ok(Ordering::Greater)
+            } else {
+                Ok(Ordering::Equal)
+            }
+        }
+        _ => Err(RuntimeError::IncorrectType(TypeError::NotComparable)),
+    }
+}
+
+/// Stable-sorts `elems` by `keys`, stopping at the first comparison error.
+fn sort_by_keys<F>(elems: Vec<Value>, keys: Vec<Value>, mut compare: F) -> Result<Value, RuntimeError>
+    where F: FnMut(&Value, &Value) -> Result<Ordering, RuntimeError>
+{
+    let keyed = keys.into_iter().zip(elems).collect::<Vec<_>>();
+    let sorted = merge_sort(keyed, &mut |(l, _), (r, _)| compare(l, r))?;
+    Ok(vec_to_list(sorted.into_iter().map(|(_, elem)| elem).collect()))
+}
+
+/// A stable merge sort that returns the first comparison error. `slice::sort_by` may panic when the
+/// comparator isn't a total order, but here every comparison consumes an element, so an
+/// inconsistent user comparator just gives some order.
+fn merge_sort<T, F>(mut items: Vec<T>, compare: &mut F) -> Result<Vec<T>, RuntimeError>
+    where F: FnMut(&T, &T) -> Result<Ordering, RuntimeError>
+{
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = merge_sort(items.split_off(items.len() / 2), compare)?;
+    let left = merge_sort(items, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Ties take from the left so equal elements keep their order
+        let next = if compare(r, l)? == Ordering::Less { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// The builtin definition for `sort`, either `(sort coll)` or `(sort comparator coll)`
+fn sort(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 1, 2)?;
+    let coll = args.pop_back().expect("sort to have a sequence argument");
+    let comparator = args.pop_front();
+
+    let elems = seq_to_vec(coll)?;
+    let keys = elems.clone();
+    match comparator {
+        Some(comparator) => sort_by_keys(elems, keys, |l, r| compare_with(env, &comparator, l, r)),
+        None => sort_by_keys(elems, keys, compare_values),
+    }
+}
+
+/// The builtin definition for `sort-by`, either `(sort-by keyfn coll)` or `(sort-by keyfn comparator coll)`
+fn sort_by(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 2, 3)?;
+    let key_fn = args.pop_front().expect("sort-by to have a key function argument");
+    let coll = args.pop_back().expect("sort-by to have a sequence argument");
+    let comparator = args.pop_front();
+
+    let elems = seq_to_vec(coll)?;
+    let keys = elems.iter()
+        .map(|elem| apply_value(env, &key_fn, VecDeque::from([elem.clone()])))
+        .collect::<Result<Vec<_>, _>>()?;
+    match comparator {
+        Some(comparator) => sort_by_keys(elems, keys, |l, r| compare_with(env, &comparator, l, r)),
+        None => sort_by_keys(elems, keys, compare_values),
+    }
+}
+
+/// The builtin definition for `group-by`. Returns a map from each key to a vector of the
+/// elements that produced it, in their original order.
+fn group_by(env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let key_fn = args.pop_front().expect("group-by to have a key function argument");
+    let coll = args.pop_front().expect("group-by to have a sequence argument");
+
+    let mut groups: sync::HashTrieMap<HashableValue, Value> = sync::HashTrieMap::default();
+    for elem in coll.to_seq()?.iter() {
+        let key = hash_key(apply_value(env, &key_fn, VecDeque::from([elem.clone()]))?)?;
+        let group = match groups.get(&key) {
+            Some(Value::Vector(group)) => group.push_back(elem.clone()),
+            _ => sync::Vector::from_iter([elem.clone()]),
+        };
+        groups.insert_mut(key, Value::Vector(group));
+    }
+    Ok(Value::HashMap(groups))
+}
+
+/// The builtin definition for `frequencies`
+fn frequencies(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let coll = args.pop_front().expect("frequencies to have a sequence argument");
+
+    let mut counts = sync::HashTrieMap::default();
+    for elem in coll.to_seq()?.iter() {
+        let key = hash_key(elem.clone())?;
+        let count = match counts.get(&key) {
+            Some(Value::Integer(count)) => count + 1,
+            _ => 1,
+        };
+        counts.insert_mut(key, Value::Integer(count));
+    }
+    Ok(Value::HashMap(counts))
+}
+
+/// The builtin definition for `partition`, either `(partition n coll)` or `(partition n step coll)`.
+/// A trailing partition with fewer than `n` elements is dropped.
+fn partition(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_between(&args, 2, 3)?;
+    let coll = args.pop_back().expect("partition to have a sequence argument");
+    let size = args.pop_front().expect("partition to have a size argument");
+    let step = args.pop_front().unwrap_or(size.clone());
+
+    let (size, step) = match (size, step) {
+        (Value::Integer(size), Value::Integer(step)) if size > 0 && step > 0 => (size as usize, step as usize),
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+
+    let elems = seq_to_vec(coll)?;
+    let mut partitions = vec![];
+    let mut start = 0;
+    while start + size <= elems.len() {
+        partitions.push(vec_to_list(elems[start..start + size].to_vec()));
+        start += step;
+    }
+    Ok(vec_to_list(partitions))
+}
+
+/// The builtin definition for `interleave`
+fn interleave(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    let colls = args.into_iter()
+        .map(seq_to_vec)
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = colls.iter().map(Vec::len).min().unwrap_or(0);
+
+    let mut results = Vec::with_capacity(len * colls.len());
+    for i in 0..len {
+        results.extend(colls.iter().map(|coll| coll[i].clone()));
+    }
+    Ok(vec_to_list(results))
+}
+
+/// The builtin definition for `zipmap`
+fn zipmap(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 2)?;
+    let keys = args.pop_front().expect("zipmap to have a keys argument");
+    let values = args.pop_front().expect("zipmap to have a values argument");
+
+    let mut map = sync::HashTrieMap::default();
+    for (key, value) in keys.to_seq()?.iter().zip(values.to_seq()?.iter()) {
+        map.insert_mut(hash_key(key.clone())?, value.clone());
+    }
+    Ok(Value::HashMap(map))
+}
+
+/// The builtin definition for `distinct`. Keeps the first occurrence of each element.
+fn distinct(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let coll = args.pop_front().expect("distinct to have a sequence argument");
+
+    let mut seen_hashable = HashSet::new();
+    let mut seen_other: Vec<Value> = vec![];
+    let mut results = vec![];
+    for elem in coll.to_seq()?.iter() {
+        let is_new = match TryInto::<HashableValue>::try_into(elem.clone()) {
+            Ok(key) => seen_hashable.insert(key),
+            // Unhashable values fall back to a linear scan using structural equality
+            Err(_) if seen_other.contains(elem) => false,
+            Err(_) => {
+                seen_other.push(elem.clone());
+                true
+            }
+        };
+        if is_new {
+            results.push(elem.clone());
+        }
+    }
+    Ok(vec_to_list(results))
+}
+
+/// The builtin definition for `reverse`
+fn reverse(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let coll = args.pop_front().expect("reverse to have a sequence argument");
+    Ok(Value::List(coll.to_seq()?.reverse()))
+}
+
+/// The builtin definition for `last`
+fn last(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let coll = args.pop_front().expect("last to have a sequence argument");
+    match coll {
+        Value::Vector(values) => Ok(values.last().cloned().unwrap_or(Value::Nil)),
+        coll => Ok(coll.to_seq()?.iter().last().cloned().unwrap_or(Value::Nil)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_list(nums: &[i64]) -> Value {
+        Value::List(nums.iter().map(|num| Value::Integer(*num)).collect())
+    }
+
+    fn int_vector(nums: &[i64]) -> Value {
+        Value::Vector(nums.iter().map(|num| Value::Integer(*num)).collect())
+    }
+
+    fn lookup(env: &Env, name: &str) -> Value {
+        env.lookup(name).expect("builtin to be defined")
+    }
+
+    #[test]
+    fn test_map_multiple_colls() {
+        let env = Env::default();
+        let args = VecDeque::from([lookup(&env, "+"), int_list(&[1, 2, 3]), int_vector(&[10, 20])]);
+        assert_eq!(Ok(int_list(&[11, 22])), map(&env, args));
+    }
+
+    #[test]
+    fn test_reduce() {
+        let env = Env::default();
+        let args = VecDeque::from([lookup(&env, "+"), int_vector(&[1, 2, 3, 4])]);
+        assert_eq!(Ok(Value::Integer(10)), reduce(&env, args));
+
+        let args = VecDeque::from([lookup(&env, "+"), Value::Integer(5), Value::Nil]);
+        assert_eq!(Ok(Value::Integer(5)), reduce(&env, args));
+    }
+
+    #[test]
+    fn test_sort() {
+        let env = Env::default();
+        assert_eq!(Ok(int_list(&[1, 2, 3])), sort(&env, VecDeque::from([int_vector(&[3, 1, 2])])));
+
+        let args = VecDeque::from([lookup(&env, ">"), int_vector(&[3, 1, 2])]);
+        assert_eq!(Ok(int_list(&[3, 2, 1])), sort(&env, args));
+    }
+
+    #[test]
+    fn test_sort_numbers_and_chars() {
+        assert_eq!(Ok("(0.5 1.5)\n".to_string()), crate::rep("(sort [1.5 0.5])", &Env::default()));
+        assert_eq!(Ok("(1.5 2)\n".to_string()), crate::rep("(sort [2 1.5])", &Env::default()));
+        assert_eq!(Ok("(1 2.5 ##NaN ##NaN)\n".to_string()), crate::rep("(sort [##NaN 2.5 ##NaN 1])", &Env::default()));
+        assert_eq!(Ok("({:p 1.0} {:p 2.5})\n".to_string()), crate::rep("(sort-by (fn* [m] (get m :p)) [{:p 2.5} {:p 1.0}])", &Env::default()));
+        assert_eq!(Ok("(\\a \\b \\c)\n".to_string()), crate::rep("(sort [\\c \\a \\b])", &Env::default()));
+    }
+
+    #[test]
+    fn test_sort_with_inconsistent_comparator() {
+        let env = Env::default();
+        let result = crate::rep("(count (sort (fn* [x y] true) [5 3 8 1 9 2 7 4 6 0 5 3 8 1 9 2 7 4 6 0 11 12]))", &env);
+        assert_eq!(Ok("22\n".to_string()), result);
+        assert_eq!(Ok("(1 1 2)\n".to_string()), crate::rep("(sort-by (fn* [x] 0) [1 1 2])", &env));
+    }
+
+    #[test]
+    fn test_sort_incomparable() {
+        let env = Env::default();
+        let args = VecDeque::from([Value::List(sync::List::from_iter([Value::Integer(1), Value::String("a".to_string())]))]);
+        assert_eq!(Err(RuntimeError::IncorrectType(TypeError::NotComparable)), sort(&env, args));
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let env = Env::default();
+        let expected = Value::HashMap(sync::HashTrieMap::from_iter([
+            (HashableValue::Integer(1), Value::Integer(2)),
+            (HashableValue::Integer(2), Value::Integer(1)),
+        ]));
+        assert_eq!(Ok(expected), frequencies(&env, VecDeque::from([int_list(&[1, 2, 1])])));
+    }
+
+    #[test]
+    fn test_partition() {
+        let env = Env::default();
+        let expected = Value::List(sync::List::from_iter([int_list(&[1, 2]), int_list(&[3, 4])]));
+        assert_eq!(Ok(expected), partition(&env, VecDeque::from([Value::Integer(2), int_list(&[1, 2, 3, 4, 5])])));
+
+        let expected = Value::List(sync::List::from_iter([int_list(&[1, 2]), int_list(&[2, 3])]));
+        assert_eq!(Ok(expected), partition(&env, VecDeque::from([Value::Integer(2), Value::Integer(1), int_list(&[1, 2, 3])])));
+    }
+
+    #[test]
+    fn test_distinct() {
+        let env = Env::default();
+        let args = VecDeque::from([Value::List(sync::List::from_iter([
+            Value::Integer(1), int_vector(&[1]), Value::Integer(1), int_list(&[1]), Value::Integer(2),
+        ]))]);
+        let expected = Value::List(sync::List::from_iter([Value::Integer(1), int_vector(&[1]), Value::Integer(2)]));
+        assert_eq!(Ok(expected), distinct(&env, args));
+    }
+
+    #[test]
+    fn test_last() {
+        let env = Env::default();
+        assert_eq!(Ok(Value::Integer(3)), last(&env, VecDeque::from([int_vector(&[1, 2, 3])])));
+        assert_eq!(Ok(Value::Nil), last(&env, VecDeque::from([Value::Nil])));
+    }
+}
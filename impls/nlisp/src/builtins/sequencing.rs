@@ -7,10 +7,10 @@ use crate::types::{Expr, FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("if".to_string(), Value::Function(
-        FunctionBody::BuiltinExpressions(if_f)
+        FunctionBody::BuiltinExpressions("if", if_f)
     ));
     env.insert("do".to_string(), Value::Function(
-        FunctionBody::BuiltinExpressions(do_f)
+        FunctionBody::BuiltinExpressions("do", do_f)
     ));
 }
 
@@ -21,13 +21,7 @@ fn if_f(env: &Env, mut args: VecDeque<Expr>) -> Result<Value, RuntimeError> {
     let then_expr = args.pop_front().expect("if to have a then expression");
     let else_expr = args.pop_front();
 
-    let guard_value = match evaluate_expr(guard_expr, env)? {
-        Value::Boolean(bool) => bool,
-        Value::Nil => false,
-        _ => true,
-    };
-
-    if guard_value {
+    if evaluate_expr(guard_expr, env)?.is_truthy() {
         evaluate_expr(then_expr, env)
     } else if let Some(else_expr) = else_expr {
         evaluate_expr(else_expr, env)
@@ -2,20 +2,33 @@ use std::collections::{LinkedList, VecDeque};
 
 use itertools::Itertools;
 
-use crate::builtins::assert_args_length;
+use crate::analyzer::check_recur_positions;
+use crate::builtins::{assert_args_length, assert_args_length_at_least};
+use crate::builtins::destructure::{bind_pattern, is_destructuring_pattern};
+use crate::builtins::dynamic::define_var;
 use crate::Env;
-use crate::evaluator::{evaluate_expr, RuntimeError, TypeError};
-use crate::types::{Expr, FunctionBody, Value};
+use crate::evaluator::{evaluate_expr, quote, RuntimeError, TypeError};
+use crate::sync::Shared;
+use crate::types::{ClosureArity, Expr, FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("def!".to_string(), Value::Function(
-        FunctionBody::BuiltinExpressions(def)
+        FunctionBody::BuiltinExpressions("def!", def)
     ));
     env.insert("let*".to_string(), Value::Function(
-        FunctionBody::BuiltinExpressions(let_f)
+        FunctionBody::BuiltinExpressions("let*", let_f)
     ));
     env.insert("fn*".to_string(), Value::Function(
-        FunctionBody::BuiltinExpressions(fn_f)
+        FunctionBody::BuiltinExpressions("fn*", fn_f)
+    ));
+    env.insert("quote".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("quote", quote_f)
+    ));
+    env.insert("loop".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("loop", loop_f)
+    ));
+    env.insert("recur".to_string(), Value::Function(
+        FunctionBody::BuiltinExpressions("recur", recur)
     ));
 }
 
@@ -24,14 +37,41 @@ fn def(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError>
 
     let expr_a = arg_exprs.pop_front().expect("id to be present");
 
-    match expr_a {
-        Expr::Symbol(id) => {
-            let assignment_expr = arg_exprs.pop_front().expect("assignment to be present");
-            let assignment_val = evaluate_expr(assignment_expr, env)?;
-            env.insert(id, assignment_val.clone());
-            Ok(assignment_val)
+    // `^:dynamic name` is read as `(with-meta name :dynamic)`
+    let (id, is_dynamic) = match expr_a {
+        Expr::Symbol(id) => (id, false),
+        Expr::List(elems) => match elems.into_iter().collect_tuple() {
+            Some((Expr::Symbol(with_meta), Expr::Symbol(id), metadata)) if with_meta == "with-meta" => {
+                (id, is_dynamic_metadata(&metadata))
+            }
+            _ => return Err(RuntimeError::ExpectedToBindSymbol),
+        },
+        _ => return Err(RuntimeError::ExpectedToBindSymbol)
+    };
+
+    let assignment_expr = arg_exprs.pop_front().expect("assignment to be present");
+    let assignment_val = name_closure(evaluate_expr(assignment_expr, env)?, &id);
+    define_var(env, id, assignment_val.clone(), is_dynamic);
+    Ok(assignment_val)
+}
+
+/// Records the name that a closure is first defined as, so that it prints as `#<fn name ...>`.
+/// Binding an existing function to another name keeps its original name.
+fn name_closure(value: Value, id: &str) -> Value {
+    match value {
+        Value::Function(FunctionBody::Closure { closed_env, name, defined_as: None, arities }) => {
+            Value::Function(FunctionBody::Closure { closed_env, name, defined_as: Some(id.to_string()), arities })
         }
-        _ => Err(RuntimeError::ExpectedToBindSymbol)
+        value => value,
+    }
+}
+
+fn is_dynamic_metadata(metadata: &Expr) -> bool {
+    match metadata {
+        Expr::Keyword(k) => k == ":dynamic",
+        Expr::HashMap(pairs) => pairs.iter()
+            .any(|pair| pair == &(Expr::Keyword(":dynamic".to_string()), Expr::Boolean(true))),
+        _ => false,
     }
 }
 
@@ -42,13 +82,7 @@ fn create_environment_for_bindings<T>(base_env: &Env, binding_exprs: T) -> Resul
         let binding_expr = chunk_exprs.next().expect("let binding chunk to have a binding");
         let assignment_expr = chunk_exprs.next().expect("let binding chunk to have an assignment");
         let assignment_val = evaluate_expr(assignment_expr.clone(), &new_env)?;
-
-        match binding_expr {
-            Expr::Symbol(s) => {
-                new_env.insert(s.to_string(), assignment_val);
-            }
-            _ => return Err(RuntimeError::ExpectedToBindSymbol)
-        }
+        bind_pattern(&new_env, &binding_expr, assignment_val)?;
     }
     Ok(new_env)
 }
@@ -80,12 +114,128 @@ fn let_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError
     evaluate_expr(body_expr, &new_env)
 }
 
-fn fn_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
-    assert_args_length(&arg_exprs, 2)?;
+/// The builtin definition for `quote`, which returns its argument unevaluated
+fn quote_f(_env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length(&arg_exprs, 1)?;
+    quote(arg_exprs.pop_front().expect("quote to have an argument"))
+}
+
+/// Multiple body expressions are wrapped in an implicit `do`.
+pub(crate) fn implicit_do(mut body_exprs: Vec<Expr>) -> Expr {
+    match body_exprs.len() {
+        0 => Expr::Nil,
+        1 => body_exprs.pop().expect("body to have one expression"),
+        _ => Expr::List(std::iter::once(Expr::Symbol("do".to_string())).chain(body_exprs).collect()),
+    }
+}
+
+/// The builtin definition for `loop`. The bindings are set up like `let*`, and `recur` in
+/// tail position of the body rebinds them and runs the body again.
+fn loop_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&arg_exprs, 1)?;
+
+    let binding_exprs = match arg_exprs.pop_front().expect("bindings to be present") {
+        Expr::Vector(binding_exprs) => binding_exprs,
+        Expr::List(binding_exprs) => binding_exprs.into_iter().collect(),
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+    if binding_exprs.len() % 2 != 0 {
+        return Err(RuntimeError::UnmatchedLetBindingID);
+    }
+
+    let body_expr = implicit_do(arg_exprs.into_iter().collect());
+    check_recur_positions(&body_expr, true)?;
+
+    let binding_patterns = binding_exprs.iter().step_by(2).cloned().collect_vec();
+    let mut loop_env = create_environment_for_bindings(env, binding_exprs.into_iter())?;
+    loop {
+        env.runtime().budget.check_interrupt()?;
+        match evaluate_expr(body_expr.clone(), &loop_env) {
+            Err(RuntimeError::Recur(recur_values)) => {
+                if recur_values.len() != binding_patterns.len() {
+                    return Err(RuntimeError::RecurWrongNumberOfArgs { given: recur_values.len(), expected: binding_patterns.len() });
+                }
+
+                loop_env = env.create_child_env();
+                for (pattern, value) in binding_patterns.iter().zip(recur_values) {
+                    bind_pattern(&loop_env, pattern, value)?;
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The builtin definition for `recur`. It evaluates its arguments and hands them back to the
+/// enclosing `loop` or function, which is guaranteed to be waiting for them because the
+/// tail positions were checked up front.
+fn recur(env: &Env, arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    let mut recur_values = Vec::with_capacity(arg_exprs.len());
+    for arg_expr in arg_exprs {
+        recur_values.push(evaluate_expr(arg_expr, env)?);
+    }
+    Err(RuntimeError::Recur(recur_values))
+}
 
-    let param_list_expr = arg_exprs.pop_front().expect("parameter list to be present");
-    let body_expr = arg_exprs.pop_front().expect("function body to be present");
+/// The builtin definition for `fn*`, which accepts either a single arity, `(fn* name? [params] body...)`,
+/// or several, `(fn* name? ([params] body...) ([params] body...))`.
+pub(super) fn fn_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError> {
+    let name = match arg_exprs.front() {
+        Some(Expr::Symbol(name)) => {
+            let name = name.to_string();
+            arg_exprs.pop_front();
+            Some(name)
+        }
+        _ => None,
+    };
 
+    let is_multi_arity = matches!(arg_exprs.front(), Some(Expr::List(clause)) if matches!(clause.front(), Some(Expr::Vector(_))));
+    let arities = if is_multi_arity {
+        let mut arities = Vec::with_capacity(arg_exprs.len());
+        for clause in arg_exprs {
+            let mut clause_exprs = match clause {
+                Expr::List(clause_exprs) if matches!(clause_exprs.front(), Some(Expr::Vector(_))) => clause_exprs,
+                _ => return Err(RuntimeError::InvalidFunctionDefinition("expected every arity to be a list starting with a parameter vector".to_string())),
+            };
+            let param_list_expr = clause_exprs.pop_front().expect("arity clause to have a parameter list");
+            arities.push(parse_arity(param_list_expr, clause_exprs.into_iter().collect())?);
+        }
+        validate_arities(&arities)?;
+        arities
+    } else {
+        assert_args_length_at_least(&arg_exprs, 2)?;
+        let param_list_expr = arg_exprs.pop_front().expect("parameter list to be present");
+        vec![parse_arity(param_list_expr, arg_exprs.into_iter().collect())?]
+    };
+
+    Ok(Value::Function(FunctionBody::Closure {
+        closed_env: env.clone(),
+        name,
+        defined_as: None,
+        arities: Shared::new(arities),
+    }))
+}
+
+/// Makes sure that a call could never match more than one arity.
+fn validate_arities(arities: &[ClosureArity]) -> Result<(), RuntimeError> {
+    let variadic_arities = arities.iter().filter(|arity| arity.variadic_param.is_some()).collect_vec();
+    if variadic_arities.len() > 1 {
+        return Err(RuntimeError::InvalidFunctionDefinition("can't have more than one variadic arity".to_string()));
+    }
+    let fixed_arities = arities.iter().filter(|arity| arity.variadic_param.is_none());
+    if !fixed_arities.map(|arity| arity.params.len()).all_unique() {
+        return Err(RuntimeError::InvalidFunctionDefinition("can't have two arities with the same number of params".to_string()));
+    }
+    if let Some(variadic_arity) = variadic_arities.first() {
+        if arities.iter().any(|arity| arity.variadic_param.is_none() && arity.params.len() > variadic_arity.params.len()) {
+            return Err(RuntimeError::InvalidFunctionDefinition("can't have a fixed arity with more params than the variadic arity".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn parse_arity(param_list_expr: Expr, body_exprs: Vec<Expr>) -> Result<ClosureArity, RuntimeError> {
+    let source = Expr::List(std::iter::once(param_list_expr.clone()).chain(body_exprs.iter().cloned()).collect());
     let param_name_exprs = {
         match param_list_expr {
             Expr::List(elems) => elems,
@@ -97,37 +247,60 @@ fn fn_f(env: &Env, mut arg_exprs: VecDeque<Expr>) -> Result<Value, RuntimeError>
     let mut param_names = Vec::with_capacity(param_name_exprs.len());
     let mut expecting_variadic = false;
     let mut variadic_param = None;
+    // Destructured parameters are bound to a generated name, then unpacked with a `let*` around the body
+    let mut destructured_bindings = vec![];
     for elem in param_name_exprs {
-        match elem {
-            Expr::Symbol(param_name) => {
-                if param_name == "&" {
-                    if expecting_variadic {
-                        return Err(RuntimeError::Misc); // Can't have duplicate &
-                    }
-
-                    // Consume the next param as variadic
-                    expecting_variadic = true;
-                } else if expecting_variadic && variadic_param.is_some() {
-                    return Err(RuntimeError::Misc); // Can only have one variadic param
-                } else if expecting_variadic && variadic_param.is_none() {
-                    variadic_param = Some(param_name);
-                } else {
-                    param_names.push(param_name)
+        let param_name = match elem {
+            Expr::Symbol(param_name) if param_name == "&" => {
+                if expecting_variadic {
+                    return Err(RuntimeError::Misc); // Can't have duplicate &
                 }
+
+                // Consume the next param as variadic
+                expecting_variadic = true;
+                continue;
+            }
+            Expr::Symbol(param_name) => param_name,
+            pattern if is_destructuring_pattern(&pattern) => {
+                let generated_name = format!("__destructure{}", destructured_bindings.len() / 2);
+                destructured_bindings.push(pattern);
+                destructured_bindings.push(Expr::Symbol(generated_name.clone()));
+                generated_name
             }
             _ => return Err(RuntimeError::ExpectedToBindSymbol),
+        };
+
+        if expecting_variadic && variadic_param.is_some() {
+            return Err(RuntimeError::Misc); // Can only have one variadic param
+        } else if expecting_variadic && variadic_param.is_none() {
+            variadic_param = Some(param_name);
+        } else {
+            param_names.push(param_name)
         }
     }
     if expecting_variadic && variadic_param.is_none() {
         return Err(RuntimeError::Misc); // If you use &, you need to give a name to the variadic params
     }
 
-    Ok(Value::Function(FunctionBody::Closure {
-        closed_env: env.clone(),
+    let body_expr = implicit_do(body_exprs);
+    check_recur_positions(&body_expr, true)?;
+
+    let body = if destructured_bindings.is_empty() {
+        body_expr
+    } else {
+        Expr::List(LinkedList::from([
+            Expr::Symbol("let*".to_string()),
+            Expr::Vector(destructured_bindings),
+            body_expr,
+        ]))
+    };
+
+    Ok(ClosureArity {
         params: param_names,
         variadic_param,
-        body: body_expr,
-    }))
+        body,
+        source,
+    })
 }
 
 #[cfg(test)]
@@ -242,7 +415,7 @@ mod tests {
 
         use crate::Env;
         use crate::evaluator::RuntimeError;
-        use crate::types::{Expr, FunctionBody, Value};
+        use crate::types::{ClosureArity, Expr, FunctionBody, Value};
 
         use super::*;
 
@@ -262,13 +435,18 @@ mod tests {
             ]);
             let expected_value = Value::Function(FunctionBody::Closure {
                 closed_env: env.clone(),
-                params: vec!["x".to_string(), "y".to_string()],
-                variadic_param: None,
-                body: Expr::List(LinkedList::from([
-                    Expr::Symbol("+".to_string()),
-                    Expr::Symbol("x".to_string()),
-                    Expr::Symbol("y".to_string()),
-                ])),
+                name: None,
+                defined_as: None,
+                arities: Shared::new(vec![ClosureArity {
+                    params: vec!["x".to_string(), "y".to_string()],
+                    variadic_param: None,
+                    body: Expr::List(LinkedList::from([
+                        Expr::Symbol("+".to_string()),
+                        Expr::Symbol("x".to_string()),
+                        Expr::Symbol("y".to_string()),
+                    ])),
+                    source: Expr::List(exprs.iter().cloned().collect()),
+                }]),
             });
 
             assert_eq!(Ok(expected_value), fn_f(&env, exprs));
@@ -328,9 +506,62 @@ mod tests {
             ]);
             let expected_value = Value::Function(FunctionBody::Closure {
                 closed_env: env.clone(),
-                params: vec!["x".to_string()],
-                variadic_param: Some("rest".to_string()),
-                body: Expr::Symbol("rest".to_string()),
+                name: None,
+                defined_as: None,
+                arities: Shared::new(vec![ClosureArity {
+                    params: vec!["x".to_string()],
+                    variadic_param: Some("rest".to_string()),
+                    body: Expr::Symbol("rest".to_string()),
+                    source: Expr::List(exprs.iter().cloned().collect()),
+                }]),
+            });
+
+            assert_eq!(Ok(expected_value), fn_f(&env, exprs));
+        }
+
+        #[test]
+        fn test_multi_arity_invalid() {
+            let env = Env::default();
+            let clause = |params: Vec<Expr>| Expr::List(LinkedList::from([Expr::Vector(params), Expr::Nil]));
+            let exprs = VecDeque::from([
+                clause(vec![Expr::Symbol("x".to_string())]),
+                clause(vec![Expr::Symbol("y".to_string())]),
+            ]);
+
+            assert_eq!(Err(RuntimeError::InvalidFunctionDefinition("can't have two arities with the same number of params".to_string())),
+                       fn_f(&env, exprs));
+
+            // Every clause is checked, not just the first
+            let malformed = RuntimeError::InvalidFunctionDefinition("expected every arity to be a list starting with a parameter vector".to_string());
+            assert_eq!(Err(malformed.clone()), crate::rep("(fn* ([x] x) ())", &env));
+            assert_eq!(Err(malformed), crate::rep("(fn* ([x] x) (y))", &env));
+        }
+
+        #[test]
+        fn test_destructured_parameters() {
+            let env = Env::default();
+            let pattern = Expr::Vector(vec![
+                Expr::Symbol("a".to_string()),
+                Expr::Symbol("b".to_string()),
+            ]);
+            let exprs = VecDeque::from([
+                Expr::Vector(vec![pattern.clone()]),
+                Expr::Symbol("a".to_string()),
+            ]);
+            let expected_value = Value::Function(FunctionBody::Closure {
+                closed_env: env.clone(),
+                name: None,
+                defined_as: None,
+                arities: Shared::new(vec![ClosureArity {
+                    params: vec!["__destructure0".to_string()],
+                    variadic_param: None,
+                    body: Expr::List(LinkedList::from([
+                        Expr::Symbol("let*".to_string()),
+                        Expr::Vector(vec![pattern, Expr::Symbol("__destructure0".to_string())]),
+                        Expr::Symbol("a".to_string()),
+                    ])),
+                    source: Expr::List(exprs.iter().cloned().collect()),
+                }]),
             });
 
             assert_eq!(Ok(expected_value), fn_f(&env, exprs));
@@ -4,28 +4,30 @@ use itertools::Itertools;
 
 use crate::Env;
 use crate::evaluator::RuntimeError;
-use crate::printer::Printable;
+use crate::printer::PrintOptions;
 use crate::types::{FunctionBody, Value};
 
 pub fn insert_functions(env: &Env) {
     env.insert("pr-str".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(pr_str)
+        FunctionBody::BuiltinValues("pr-str", pr_str)
     ));
     env.insert("str".to_string(), Value::Function(
-        FunctionBody::BuiltinValues(str)
+        FunctionBody::BuiltinValues("str", str)
     ));
 }
 
-fn pr_str(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+fn pr_str(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    let options = PrintOptions::from_env(env);
     let result = args.into_iter()
-        .map(|value| value.print_value(true))
+        .map(|value| value.print_value_with_options(true, &options))
         .join(" ");
     Ok(Value::String(result))
 }
 
-fn str(_env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+fn str(env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    let options = PrintOptions::from_env(env);
     let result = args.into_iter()
-        .map(|value| value.print_value(false))
+        .map(|value| value.print_value_with_options(false, &options))
         .join("");
     Ok(Value::String(result))
 }
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+
+use crate::builtins::{hash_key, assert_args_length, assert_args_length_at_least};
+use crate::Env;
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::sync::{Lock, Shared};
+use crate::types::{FunctionBody, TransientCollection, Value};
+
+pub fn insert_functions(env: &Env) {
+    env.insert("transient".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("transient", transient)
+    ));
+    env.insert("persistent!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("persistent!", persistent)
+    ));
+    env.insert("conj!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("conj!", conj)
+    ));
+    env.insert("assoc!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("assoc!", assoc)
+    ));
+    env.insert("dissoc!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("dissoc!", dissoc)
+    ));
+    env.insert("pop!".to_string(), Value::Function(
+        FunctionBody::BuiltinValues("pop!", pop)
+    ));
+}
+
+/// Mutates the collection inside a transient in place and returns the same transient.
+fn update_transient<F>(transient: Value, update: F) -> Result<Value, RuntimeError>
+    where F: FnOnce(&mut TransientCollection) -> Result<(), RuntimeError>
+{
+    match &transient {
+        Value::Transient(cell) => {
+            match cell.borrow_mut().as_mut() {
+                Some(collection) => update(collection)?,
+                None => return Err(RuntimeError::TransientUsedAfterPersistent),
+            }
+            Ok(transient)
+        }
+        _ => Err(RuntimeError::IncorrectType(TypeError::NotATransient)),
+    }
+}
+
+/// The builtin definition for `transient`
+fn transient(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let collection = match args.pop_front().expect("transient to have an argument") {
+        Value::Vector(values) => TransientCollection::Vector(values),
+        Value::HashMap(pairs) => TransientCollection::HashMap(pairs),
+        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+    };
+    Ok(Value::Transient(Shared::new(Lock::new(Some(collection)))))
+}
+
+/// The builtin definition for `persistent!`. The transient cannot be used afterwards.
+fn persistent(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    match args.pop_front().expect("persistent! to have an argument") {
+        Value::Transient(cell) => match cell.borrow_mut().take() {
+            Some(TransientCollection::Vector(values)) => Ok(Value::Vector(values)),
+            Some(TransientCollection::HashMap(pairs)) => Ok(Value::HashMap(pairs)),
+            None => Err(RuntimeError::TransientUsedAfterPersistent),
+        },
+        _ => Err(RuntimeError::IncorrectType(TypeError::NotATransient)),
+    }
+}
+
+/// The builtin definition for `conj!`. Maps accept `[key value]` vectors.
+fn conj(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 1)?;
+    let transient = args.pop_front().expect("conj! to have a transient argument");
+
+    update_transient(transient, |collection| {
+        for arg in args {
+            match collection {
+                TransientCollection::Vector(values) => values.push_back_mut(arg),
+                TransientCollection::HashMap(pairs) => {
+                    let (key, value) = match arg {
+                        Value::Vector(entry) if entry.len() == 2 => (entry[0].clone(), entry[1].clone()),
+                        _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+                    };
+                    pairs.insert_mut(hash_key(key)?, value);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The builtin definition for `assoc!`. Vectors accept any index up to and including their length.
+fn assoc(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 3)?;
+    let transient = args.pop_front().expect("assoc! to have a transient argument");
+    if !args.len().is_multiple_of(2) {
+        let key = args.pop_back().expect("assoc! to have an unpaired key");
+        return Err(RuntimeError::UnpairedKey { name: "assoc!", key });
+    }
+
+    update_transient(transient, |collection| {
+        for (key, value) in args.into_iter().tuples() {
+            match collection {
+                TransientCollection::Vector(values) => match key {
+                    Value::Integer(index) if index >= 0 && (index as usize) < values.len() => {
+                        values.set_mut(index as usize, value);
+                    }
+                    Value::Integer(index) if index as usize == values.len() => values.push_back_mut(value),
+                    _ => return Err(RuntimeError::IncorrectType(TypeError::Misc)),
+                },
+                TransientCollection::HashMap(pairs) => pairs.insert_mut(hash_key(key)?, value),
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The builtin definition for `dissoc!`
+fn dissoc(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length_at_least(&args, 1)?;
+    let transient = args.pop_front().expect("dissoc! to have a transient argument");
+
+    update_transient(transient, |collection| {
+        match collection {
+            TransientCollection::HashMap(pairs) => {
+                for key in args {
+                    pairs.remove_mut(&hash_key(key)?);
+                }
+                Ok(())
+            }
+            TransientCollection::Vector(_) => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+        }
+    })
+}
+
+/// The builtin definition for `pop!`
+fn pop(_env: &Env, mut args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+    assert_args_length(&args, 1)?;
+    let transient = args.pop_front().expect("pop! to have a transient argument");
+
+    update_transient(transient, |collection| {
+        match collection {
+            TransientCollection::Vector(values) => {
+                if values.drop_last_mut() {
+                    Ok(())
+                } else {
+                    Err(RuntimeError::CannotPopEmpty)
+                }
+            }
+            TransientCollection::HashMap(_) => Err(RuntimeError::IncorrectType(TypeError::Misc)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync;
+    use crate::types::HashableValue;
+
+    use super::*;
+
+    #[test]
+    fn test_build_vector() {
+        let env = Env::default();
+        let mut t = transient(&env, VecDeque::from([Value::Vector(sync::Vector::default())])).unwrap();
+        for i in 0..1000 {
+            t = conj(&env, VecDeque::from([t, Value::Integer(i)])).unwrap();
+        }
+        t = pop(&env, VecDeque::from([t])).unwrap();
+        t = assoc(&env, VecDeque::from([t, Value::Integer(0), Value::Integer(-1)])).unwrap();
+
+        let expected = Value::Vector((0..999).map(|i| Value::Integer(if i == 0 { -1 } else { i })).collect());
+        assert_eq!(Ok(expected), persistent(&env, VecDeque::from([t])));
+    }
+
+    #[test]
+    fn test_assoc_unpaired_key() {
+        let env = Env::default();
+        let t = transient(&env, VecDeque::from([Value::HashMap(sync::HashTrieMap::default())])).unwrap();
+        assert_eq!(
+            Err(RuntimeError::UnpairedKey { name: "assoc!", key: Value::Keyword(":b".to_string()) }),
+            assoc(&env, VecDeque::from([t, Value::Keyword(":a".to_string()), Value::Integer(1), Value::Keyword(":b".to_string())])),
+        );
+    }
+
+    #[test]
+    fn test_does_not_modify_original() {
+        let env = Env::default();
+        let original = Value::Vector(sync::Vector::from_iter([Value::Integer(1)]));
+        let t = transient(&env, VecDeque::from([original.clone()])).unwrap();
+        conj(&env, VecDeque::from([t, Value::Integer(2)])).unwrap();
+        assert_eq!(Value::Vector(sync::Vector::from_iter([Value::Integer(1)])), original);
+    }
+
+    #[test]
+    fn test_map_operations() {
+        let env = Env::default();
+        let t = transient(&env, VecDeque::from([Value::HashMap(sync::HashTrieMap::default())])).unwrap();
+        let t = assoc(&env, VecDeque::from([t, Value::Keyword(":a".to_string()), Value::Integer(1),
+                                            Value::Keyword(":b".to_string()), Value::Integer(2)])).unwrap();
+        let t = dissoc(&env, VecDeque::from([t, Value::Keyword(":a".to_string())])).unwrap();
+
+        let expected = Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Keyword(":b".to_string()), Value::Integer(2))]));
+        assert_eq!(Ok(expected), persistent(&env, VecDeque::from([t])));
+    }
+
+    #[test]
+    fn test_use_after_persistent() {
+        let env = Env::default();
+        let t = transient(&env, VecDeque::from([Value::Vector(sync::Vector::default())])).unwrap();
+        persistent(&env, VecDeque::from([t.clone()])).unwrap();
+        assert_eq!(Err(RuntimeError::TransientUsedAfterPersistent), conj(&env, VecDeque::from([t.clone(), Value::Nil])));
+        assert_eq!(Err(RuntimeError::TransientUsedAfterPersistent), persistent(&env, VecDeque::from([t])));
+    }
+
+    #[test]
+    fn test_pop_empty() {
+        let env = Env::default();
+        let t = transient(&env, VecDeque::from([Value::Vector(sync::Vector::default())])).unwrap();
+        assert_eq!(Err(RuntimeError::CannotPopEmpty), pop(&env, VecDeque::from([t])));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::types::Value;
+
+/// A queue that threads use to hand values to each other, as in core.async. Taking from an empty
+/// channel waits for a value, and putting to a full one waits for room. An unbuffered channel,
+/// with a buffer size of 0, makes each put wait until its value has been taken.
+pub struct Channel {
+    state: Mutex<ChannelState>,
+    changed: Condvar,
+    /// A channel made by `timeout` closes by itself at this time
+    closes_at: Option<Instant>,
+}
+
+struct ChannelState {
+    buffer: VecDeque<Value>,
+    capacity: usize,
+    closed: bool,
+    /// How many values have been put and taken, so that a put to an unbuffered channel can tell
+    /// when its value has been taken
+    puts: u64,
+    takes: u64,
+    /// How many `take` calls are waiting for a value, so that `alts!` only puts to an unbuffered
+    /// channel when someone is there to take the value
+    waiting_takers: usize,
+    /// The `alts!` calls currently waiting on this channel, which are woken on every change
+    selectors: Vec<Arc<Selector>>,
+}
+
+/// What happened when trying to take from a channel without waiting
+pub enum TryTake {
+    Value(Value),
+    Closed,
+    Empty,
+}
+
+impl Channel {
+    pub fn new(capacity: usize) -> Self {
+        Channel::with_deadline(capacity, None)
+    }
+
+    /// A channel that closes after `duration`, for use with `alts!`.
+    pub fn timeout(duration: Duration) -> Self {
+        Channel::with_deadline(0, Some(Instant::now() + duration))
+    }
+
+    fn with_deadline(capacity: usize, closes_at: Option<Instant>) -> Self {
+        let state = ChannelState { buffer: VecDeque::new(), capacity, closed: false, puts: 0, takes: 0, waiting_takers: 0, selectors: vec![] };
+        Channel { state: Mutex::new(state), changed: Condvar::new(), closes_at }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.closes_at.is_some_and(|closes_at| Instant::now() >= closes_at) {
+            state.closed = true;
+        }
+        state
+    }
+
+    /// Waits until the state changes, or until the channel's deadline passes.
+    fn wait<'a>(&'a self, state: MutexGuard<'a, ChannelState>) -> MutexGuard<'a, ChannelState> {
+        let state = match self.closes_at {
+            Some(closes_at) => {
+                let remaining = closes_at.saturating_duration_since(Instant::now());
+                self.changed.wait_timeout(state, remaining).unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => self.changed.wait(state).unwrap_or_else(PoisonError::into_inner),
+        };
+        drop(state);
+        self.lock()
+    }
+
+    fn notify(&self, state: &ChannelState) {
+        self.changed.notify_all();
+        for selector in &state.selectors {
+            selector.wake();
+        }
+    }
+
+    /// Puts a value, waiting for room. Returns false if the channel is closed.
+    pub fn put(&self, value: Value) -> bool {
+        let mut state = self.lock();
+        while !state.closed && !state.has_room() {
+            state = self.wait(state);
+        }
+        let Some(put_number) = state.push(value) else {
+            return false;
+        };
+        self.notify(&state);
+        // An unbuffered put isn't finished until its value has been taken
+        while state.capacity == 0 && state.takes < put_number && !state.closed {
+            state = self.wait(state);
+        }
+        true
+    }
+
+    /// Puts a value if that can finish without waiting. Returns `None` if it can't, otherwise
+    /// whether the channel was still open. A put to an unbuffered channel can only finish when a
+    /// taker is already waiting for the value.
+    pub fn try_put(&self, value: Value) -> Option<bool> {
+        let mut state = self.lock();
+        if state.closed {
+            return Some(false);
+        }
+        let ready = match state.capacity {
+            0 => state.buffer.len() < state.waiting_takers,
+            _ => state.has_room(),
+        };
+        if !ready {
+            return None;
+        }
+        state.push(value);
+        self.notify(&state);
+        Some(true)
+    }
+
+    /// Takes a value, waiting for one. Returns `None` once the channel is closed and empty.
+    pub fn take(&self) -> Option<Value> {
+        let mut state = self.lock();
+        let mut waiting = false;
+        let taken = loop {
+            match self.try_take_locked(&mut state) {
+                TryTake::Value(value) => break Some(value),
+                TryTake::Closed => break None,
+                TryTake::Empty if !waiting => {
+                    // Lets any `alts!` trying to put to this channel know there's now a taker
+                    waiting = true;
+                    state.waiting_takers += 1;
+                    for selector in &state.selectors {
+                        selector.wake();
+                    }
+                    state = self.wait(state);
+                }
+                TryTake::Empty => state = self.wait(state),
+            }
+        };
+        if waiting {
+            state.waiting_takers -= 1;
+        }
+        taken
+    }
+
+    pub fn try_take(&self) -> TryTake {
+        let mut state = self.lock();
+        self.try_take_locked(&mut state)
+    }
+
+    fn try_take_locked(&self, state: &mut ChannelState) -> TryTake {
+        match state.buffer.pop_front() {
+            Some(value) => {
+                state.takes += 1;
+                self.notify(state);
+                TryTake::Value(value)
+            }
+            None if state.closed => TryTake::Closed,
+            None => TryTake::Empty,
+        }
+    }
+
+    /// Closes the channel. Values already in it can still be taken, but no more can be put.
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        self.notify(&state);
+    }
+
+    pub fn closes_at(&self) -> Option<Instant> {
+        self.closes_at
+    }
+
+    pub fn add_selector(&self, selector: &Arc<Selector>) {
+        self.lock().selectors.push(selector.clone());
+    }
+
+    pub fn remove_selector(&self, selector: &Arc<Selector>) {
+        self.lock().selectors.retain(|existing| !Arc::ptr_eq(existing, selector));
+    }
+}
+
+impl ChannelState {
+    /// An unbuffered channel has room for the one value that's being handed over
+    fn has_room(&self) -> bool {
+        self.buffer.len() < self.capacity.max(1)
+    }
+
+    /// Adds a value, returning which put it was, or `None` if the channel is closed.
+    fn push(&mut self, value: Value) -> Option<u64> {
+        if self.closed {
+            return None;
+        }
+        self.buffer.push_back(value);
+        self.puts += 1;
+        Some(self.puts)
+    }
+}
+
+impl Debug for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Channel")
+            .field("buffered", &state.buffer.len())
+            .field("capacity", &state.capacity)
+            .field("closed", &state.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Lets `alts!` wait for any of several channels to change.
+#[derive(Default)]
+pub struct Selector {
+    woken: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl Selector {
+    fn wake(&self) {
+        *self.woken.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.changed.notify_all();
+    }
+
+    /// Waits until one of the channels this was added to changes, or until `deadline`.
+    pub fn wait(&self, deadline: Option<Instant>) {
+        let woken = self.woken.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut woken = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                self.changed.wait_timeout_while(woken, remaining, |woken| !*woken)
+                    .unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => self.changed.wait_while(woken, |woken| !*woken).unwrap_or_else(PoisonError::into_inner),
+        };
+        *woken = false;
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use crate::evaluator::{RuntimeError, TypeError};
+use crate::types::{HashableValue, Value};
+
+/// Converts a Rust value into an nlisp value, e.g. to define it with `Interpreter::define`.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// Converts an nlisp value into a Rust value, failing if it's the wrong type.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, RuntimeError>;
+}
+
+fn expected(expected: &'static str, value: Value) -> RuntimeError {
+    RuntimeError::IncorrectType(TypeError::Expected { expected, value })
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(()),
+            value => Err(expected("nil", value)),
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Integer(num) => Ok(num),
+            value => Err(expected("an integer", value)),
+        }
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Integer(self as i64)
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Integer(num) => i32::try_from(num).map_err(|_| expected("a 32-bit integer", value)),
+            value => Err(expected("a 32-bit integer", value)),
+        }
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+/// Integers are also accepted, since nlisp arithmetic on whole numbers gives integers
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Float(num) => Ok(num),
+            Value::Integer(num) => Ok(num as f64),
+            value => Err(expected("a number", value)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            value => Err(expected("a boolean", value)),
+        }
+    }
+}
+
+impl IntoValue for char {
+    fn into_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Char(c) => Ok(c),
+            value => Err(expected("a character", value)),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(s) => Ok(s),
+            value => Err(expected("a string", value)),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nil => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Vector(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+/// Accepts any sequence, such as a list, vector or set
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        let seq = match value {
+            Value::List(_) | Value::Vector(_) | Value::Set(_) | Value::Nil => value.to_seq()?,
+            value => return Err(expected("a sequence", value)),
+        };
+        seq.iter().cloned().map(T::from_value).collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::HashMap(self.into_iter()
+            .map(|(key, value)| (HashableValue::String(key), value.into_value()))
+            .collect())
+    }
+}
+
+/// Keys can be strings or keywords, which are converted without their leading `:`
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        let pairs = match value {
+            Value::HashMap(pairs) => pairs,
+            Value::Nil => return Ok(HashMap::new()),
+            value => return Err(expected("a map", value)),
+        };
+        pairs.iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    HashableValue::String(key) => key.clone(),
+                    HashableValue::Keyword(key) => key[1..].to_string(),
+                    key => return Err(expected("a string or keyword key", Value::from(key.clone()))),
+                };
+                Ok((key, T::from_value(value.clone())?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(Ok(42), i64::from_value(42.into_value()));
+        assert_eq!(Ok(Some("hi".to_string())), Option::<String>::from_value("hi".into_value()));
+        assert_eq!(Ok(None), Option::<String>::from_value(Value::Nil));
+        assert_eq!(Ok(vec![1.5, 2.0]), Vec::<f64>::from_value(vec![Value::Float(1.5), Value::Integer(2)].into_value()));
+
+        let map = HashMap::from([("a".to_string(), vec![true])]);
+        assert_eq!(Ok(map.clone()), HashMap::<String, Vec<bool>>::from_value(map.into_value()));
+    }
+
+    #[test]
+    fn test_keyword_keys() {
+        let value = Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Keyword(":a".to_string()), Value::Integer(1))]));
+        assert_eq!(Ok(HashMap::from([("a".to_string(), 1)])), HashMap::<String, i64>::from_value(value));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        assert_eq!(
+            Err(RuntimeError::IncorrectType(TypeError::Expected { expected: "an integer", value: Value::String("1".to_string()) })),
+            i64::from_value("1".into_value())
+        );
+        assert!(i32::from_value(Value::Integer(i64::MAX)).is_err());
+        assert!(Vec::<i64>::from_value(Value::Vector(sync::Vector::from_iter([Value::Integer(1), Value::Nil]))).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+
+use crate::env::Env;
+use crate::evaluator::{apply_function_to_values, RuntimeError};
+use crate::sync::Lock;
+use crate::types::{FunctionBody, HashableValue, Value};
+
+/// The type that a protocol can be extended to as a fallback for every type except nil
+pub const OBJECT_TYPE: &str = "Object";
+
+/// Remembers the method found for each dispatch key, so that repeated calls with the same kind of
+/// argument skip the search. Only keys that found a method are kept, so that calls with ever more
+/// distinct values that all fall through to a default don't grow it. It's emptied whenever the
+/// methods change.
+struct MethodCache<K>(Lock<HashMap<K, FunctionBody>>);
+
+impl<K: Eq + Hash> MethodCache<K> {
+    fn new() -> Self {
+        MethodCache(Lock::new(HashMap::new()))
+    }
+
+    fn get_or_find<Q, F>(&self, key: &Q, find: F) -> Option<FunctionBody>
+        where K: Borrow<Q>, Q: Eq + Hash + ToOwned<Owned=K> + ?Sized, F: FnOnce() -> Option<FunctionBody>
+    {
+        if let Some(method) = self.0.borrow().get(key) {
+            return Some(method.clone());
+        }
+        let method = find()?;
+        self.0.borrow_mut().insert(key.to_owned(), method.clone());
+        Some(method)
+    }
+
+    fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+/// A function defined with `defmulti`, which calls its dispatch function with the arguments and
+/// then the method that `defmethod` registered for the result, or the `:default` method.
+pub struct MultiMethod {
+    pub name: String,
+    dispatch: FunctionBody,
+    /// Dispatch values can be collections, which can't be hashed, so the methods are searched in
+    /// order and the cache only holds hashable dispatch values that have their own method
+    methods: Lock<Vec<(Value, FunctionBody)>>,
+    cache: MethodCache<HashableValue>,
+}
+
+impl MultiMethod {
+    pub fn new(name: String, dispatch: FunctionBody) -> Self {
+        MultiMethod { name, dispatch, methods: Lock::new(vec![]), cache: MethodCache::new() }
+    }
+
+    /// Adds the method for `dispatch_value`, replacing any that was already there.
+    pub fn add_method(&self, dispatch_value: Value, method: FunctionBody) {
+        let mut methods = self.methods.borrow_mut();
+        methods.retain(|(existing, _)| *existing != dispatch_value);
+        methods.push((dispatch_value, method));
+        self.cache.clear();
+    }
+
+    pub fn call(&self, env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+        let dispatch_value = apply_function_to_values(&self.dispatch, args.clone(), env)?;
+        let method = match dispatch_value.clone().try_into() {
+            Ok(key) => self.cache.get_or_find(&key, || self.find_method(&dispatch_value)),
+            Err(_) => self.find_method(&dispatch_value),
+        };
+        match method.or_else(|| self.find_method(&Value::Keyword(":default".to_string()))) {
+            Some(method) => apply_function_to_values(&method, args, env),
+            None => Err(RuntimeError::NoMethod { name: self.name.clone(), dispatch_value }),
+        }
+    }
+
+    fn find_method(&self, dispatch_value: &Value) -> Option<FunctionBody> {
+        self.methods.borrow().iter()
+            .find(|(value, _)| value == dispatch_value)
+            .map(|(_, method)| method.clone())
+    }
+}
+
+impl PartialEq for MultiMethod {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for MultiMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiMethod").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+/// A set of methods defined with `defprotocol`, which `extend-type` implements for a type. Each
+/// method dispatches on the type of its first argument, as given by `Value::type_name`.
+pub struct Protocol {
+    pub name: String,
+    pub method_names: Vec<String>,
+    /// The methods for each type they've been implemented for
+    impls: Lock<HashMap<String, HashMap<String, FunctionBody>>>,
+    /// One cache per method, keyed by the names of types that have their own implementation
+    caches: Vec<MethodCache<String>>,
+}
+
+impl Protocol {
+    pub fn new(name: String, method_names: Vec<String>) -> Self {
+        let caches = method_names.iter().map(|_| MethodCache::new()).collect();
+        Protocol { name, method_names, impls: Lock::new(HashMap::new()), caches }
+    }
+
+    /// Adds the methods in `methods` for `type_name`, keeping any that were implemented for it before.
+    pub fn extend(&self, type_name: String, methods: HashMap<String, FunctionBody>) {
+        self.impls.borrow_mut().entry(type_name).or_default().extend(methods);
+        for cache in &self.caches {
+            cache.clear();
+        }
+    }
+
+    /// Whether the protocol has been extended to the type of `value`, or to every type.
+    pub fn is_satisfied_by(&self, value: &Value) -> bool {
+        let impls = self.impls.borrow();
+        impls.contains_key(value.type_name()) || (*value != Value::Nil && impls.contains_key(OBJECT_TYPE))
+    }
+
+    /// Calls the method at `index` in `method_names` with the implementation for the type of the first argument.
+    pub fn call(&self, index: usize, env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+        let method_name = &self.method_names[index];
+        let Some(target) = args.front() else {
+            return Err(RuntimeError::FunctionApplicationWrongNumberOfArgs { given: 0, expected: 1 });
+        };
+        let type_name = target.type_name();
+        let find_in = |type_name: &str| self.impls.borrow().get(type_name)
+            .and_then(|methods| methods.get(method_name))
+            .cloned();
+        let method = self.caches[index].get_or_find(type_name, || find_in(type_name))
+            .or_else(|| if type_name == "Nil" { None } else { find_in(OBJECT_TYPE) });
+        match method {
+            Some(method) => apply_function_to_values(&method, args, env),
+            None => Err(RuntimeError::NoProtocolImpl {
+                protocol: self.name.clone(),
+                method: method_name.clone(),
+                type_name: type_name.to_string(),
+            }),
+        }
+    }
+}
+
+impl PartialEq for Protocol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for Protocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Protocol")
+            .field("name", &self.name)
+            .field("method_names", &self.method_names)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rep;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_only_holds_registered_methods() {
+        let env = Env::default();
+        rep("(defmulti f (fn* [x] x))", &env).unwrap();
+        rep("(defmethod f 1 [x] :one)", &env).unwrap();
+        rep("(defmethod f :default [x] :other)", &env).unwrap();
+        rep("(def! results (map f [1 2 3 4 5 1]))", &env).unwrap();
+        assert_eq!("(:one :other :other :other :other :one)\n", rep("results", &env).unwrap());
+        let Some(Value::Function(FunctionBody::MultiMethod(multi))) = env.resolve("f") else {
+            panic!("expected f to be a multimethod");
+        };
+        assert_eq!(1, multi.cache.0.borrow().len());
+    }
+}
@@ -0,0 +1,485 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use thiserror::Error;
+
+use crate::parser::char_from_name;
+use crate::printer::Printable;
+use crate::sync;
+use crate::types::{HashableValue, Value};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("invalid EDN at line {line}, column {column}: {kind}")]
+pub struct EdnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: EdnErrorKind,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EdnErrorKind {
+    #[error("unexpected end of input")]
+    UnexpectedEof,
+
+    #[error("unexpected `{0}`")]
+    UnexpectedChar(char),
+
+    #[error("`{0}` is only valid in code, not in EDN data")]
+    CodeOnlyForm(char),
+
+    #[error("invalid number `{0}`")]
+    InvalidNumber(String),
+
+    #[error("invalid symbol `{0}`")]
+    InvalidSymbol(String),
+
+    #[error("invalid character literal `\\{0}`")]
+    InvalidChar(String),
+
+    #[error("invalid escape sequence `\\{0}` in string")]
+    InvalidEscape(String),
+
+    #[error("invalid tag `#{0}`, tags must start with a letter")]
+    InvalidTag(String),
+
+    #[error("`{0}` can't be used as a map key or set element")]
+    Unhashable(String),
+
+    #[error("map has a key `{0}` with no value")]
+    MissingMapValue(String),
+
+    #[error("duplicate map key `{0}`")]
+    DuplicateKey(String),
+
+    #[error("duplicate set element `{0}`")]
+    DuplicateSetElement(String),
+}
+
+/// Reads the first EDN element in `text`, or nil if there isn't one.
+pub fn read_string(text: &str) -> Result<Value, EdnError> {
+    let mut reader = EdnReader { chars: text.chars().peekable(), line: 1, column: 1 };
+    match reader.read_next()? {
+        Some(value) => Ok(value),
+        None => match reader.chars.peek() {
+            Some(&c) => Err(reader.error(EdnErrorKind::UnexpectedChar(c))),
+            None => Ok(Value::Nil),
+        },
+    }
+}
+
+/// Writes `value` as EDN, or returns the first part of it that EDN can't represent, such as a function.
+pub fn write_string(value: &Value) -> Result<String, Value> {
+    check_writable(value)?;
+    Ok(value.print_value(true))
+}
+
+fn check_writable(value: &Value) -> Result<(), Value> {
+    match value {
+        Value::List(elems) => elems.iter().try_for_each(check_writable),
+        Value::Vector(elems) => elems.iter().try_for_each(check_writable),
+        Value::HashMap(pairs) => pairs.values().try_for_each(check_writable),
+        Value::Record(record) => record.entries().try_for_each(|(_, value)| check_writable(value)),
+        Value::Tagged(_, value) => check_writable(value),
+        Value::Function(_) | Value::Atom(_) | Value::Transient(_) | Value::DynamicVar(_)
+        | Value::OutputPort(_) | Value::Protocol(_) => Err(value.clone()),
+        #[cfg(feature = "sync")]
+        Value::Promise(_) | Value::Channel(_) => Err(value.clone()),
+        Value::Integer(_) | Value::Float(_) | Value::Char(_) | Value::String(_) | Value::Symbol(_)
+        | Value::Keyword(_) | Value::Boolean(_) | Value::Set(_) | Value::Nil => Ok(()),
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ',' | ';' | '(' | ')' | '[' | ']' | '{' | '}' | '"')
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '*' | '+' | '!' | '-' | '_' | '?' | '$' | '%' | '&' | '=' | '<' | '>' | '/' | ':' | '#' | '\'')
+}
+
+/// An element along with the line and column it starts at.
+type Positioned = ((usize, usize), Value);
+
+struct EdnReader<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl EdnReader<'_> {
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, kind: EdnErrorKind) -> EdnError {
+        EdnError { line: self.line, column: self.column, kind }
+    }
+
+    /// An error at an earlier position, such as the start of the element it's about.
+    fn error_at(&self, (line, column): (usize, usize), kind: EdnErrorKind) -> EdnError {
+        EdnError { line, column, kind }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ';' => while self.next_char().is_some_and(|c| c != '\n') {},
+                c if c.is_whitespace() || c == ',' => { self.next_char(); }
+                _ => break,
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if is_delimiter(c) {
+                break;
+            }
+            token.push(c);
+            self.next_char();
+        }
+        token
+    }
+
+    /// Reads the next element, skipping any `#_` discarded ones. Returns `None` at the end of the
+    /// input or at a closing delimiter, which is left for the caller.
+    fn read_next(&mut self) -> Result<Option<Value>, EdnError> {
+        loop {
+            self.skip_whitespace();
+            let start = (self.line, self.column);
+            let c = match self.chars.peek() {
+                Some(&c) => c,
+                None => return Ok(None),
+            };
+
+            let value = match c {
+                ')' | ']' | '}' => return Ok(None),
+                '(' => {
+                    self.next_char();
+                    Value::List(self.read_elements(')')?.into_iter().collect())
+                }
+                '[' => {
+                    self.next_char();
+                    Value::Vector(self.read_elements(']')?.into_iter().collect())
+                }
+                '{' => {
+                    self.next_char();
+                    self.read_map(start)?
+                }
+                '"' => {
+                    self.next_char();
+                    self.read_string()?
+                }
+                '\\' => {
+                    self.next_char();
+                    self.read_char()?
+                }
+                '#' => {
+                    self.next_char();
+                    match self.chars.peek() {
+                        Some('{') => {
+                            self.next_char();
+                            self.read_set()?
+                        }
+                        Some('_') => {
+                            self.next_char();
+                            self.read_required()?;
+                            continue;
+                        }
+                        Some('#') => {
+                            self.next_char();
+                            match self.read_token().as_str() {
+                                "Inf" => Value::Float(f64::INFINITY),
+                                "-Inf" => Value::Float(f64::NEG_INFINITY),
+                                "NaN" => Value::Float(f64::NAN),
+                                token => return Err(self.error_at(start, EdnErrorKind::InvalidSymbol(format!("##{}", token)))),
+                            }
+                        }
+                        Some(c) if c.is_alphabetic() => {
+                            let tag = self.read_token();
+                            if !tag.chars().all(is_symbol_char) {
+                                return Err(self.error_at(start, EdnErrorKind::InvalidTag(tag)));
+                            }
+                            Value::Tagged(tag, Box::new(self.read_required()?))
+                        }
+                        Some(_) => {
+                            let tag = self.read_token();
+                            return Err(self.error_at(start, EdnErrorKind::InvalidTag(tag)));
+                        }
+                        None => return Err(self.error(EdnErrorKind::UnexpectedEof)),
+                    }
+                }
+                '~' | '@' | '^' | '\'' | '`' => return Err(self.error_at(start, EdnErrorKind::CodeOnlyForm(c))),
+                _ => self.read_atom(start)?,
+            };
+            return Ok(Some(value));
+        }
+    }
+
+    /// Reads an element that must be present, such as the value after a tag.
+    fn read_required(&mut self) -> Result<Value, EdnError> {
+        match self.read_next()? {
+            Some(value) => Ok(value),
+            None => match self.chars.peek() {
+                Some(&c) => Err(self.error(EdnErrorKind::UnexpectedChar(c))),
+                None => Err(self.error(EdnErrorKind::UnexpectedEof)),
+            },
+        }
+    }
+
+    /// Reads elements up to and including `closer`, returning each with its starting position.
+    fn read_positioned_elements(&mut self, closer: char) -> Result<Vec<Positioned>, EdnError> {
+        let mut elements = vec![];
+        loop {
+            self.skip_whitespace();
+            let start = (self.line, self.column);
+            match self.read_next()? {
+                Some(value) => elements.push((start, value)),
+                None => return match self.chars.peek() {
+                    Some(&c) if c == closer => {
+                        self.next_char();
+                        Ok(elements)
+                    }
+                    Some(&c) => Err(self.error(EdnErrorKind::UnexpectedChar(c))),
+                    None => Err(self.error(EdnErrorKind::UnexpectedEof)),
+                },
+            }
+        }
+    }
+
+    fn read_elements(&mut self, closer: char) -> Result<Vec<Value>, EdnError> {
+        Ok(self.read_positioned_elements(closer)?.into_iter().map(|(_, value)| value).collect())
+    }
+
+    fn hashable(&self, start: (usize, usize), value: Value) -> Result<HashableValue, EdnError> {
+        value.clone().try_into().map_err(|_| self.error_at(start, EdnErrorKind::Unhashable(value.print_value(true))))
+    }
+
+    fn read_map(&mut self, start: (usize, usize)) -> Result<Value, EdnError> {
+        let elements = self.read_positioned_elements('}')?;
+        if !elements.len().is_multiple_of(2) {
+            let (_, key) = elements.last().expect("map to have an unmatched key");
+            return Err(self.error_at(start, EdnErrorKind::MissingMapValue(key.print_value(true))));
+        }
+
+        let mut map = sync::HashTrieMap::default();
+        let mut elements_iter = elements.into_iter();
+        while let (Some((key_start, key)), Some((_, value))) = (elements_iter.next(), elements_iter.next()) {
+            let key = self.hashable(key_start, key)?;
+            if map.contains_key(&key) {
+                return Err(self.error_at(key_start, EdnErrorKind::DuplicateKey(key.print_value(true))));
+            }
+            map.insert_mut(key, value);
+        }
+        Ok(Value::HashMap(map))
+    }
+
+    fn read_set(&mut self) -> Result<Value, EdnError> {
+        let mut set = sync::HashTrieSet::default();
+        for (elem_start, elem) in self.read_positioned_elements('}')? {
+            let elem = self.hashable(elem_start, elem)?;
+            if set.contains(&elem) {
+                return Err(self.error_at(elem_start, EdnErrorKind::DuplicateSetElement(elem.print_value(true))));
+            }
+            set.insert_mut(elem);
+        }
+        Ok(Value::Set(set))
+    }
+
+    fn read_string(&mut self) -> Result<Value, EdnError> {
+        let mut contents = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(Value::String(contents)),
+                Some('\\') => {
+                    let escape_start = (self.line, self.column - 1);
+                    match self.next_char() {
+                        Some('t') => contents.push('\t'),
+                        Some('r') => contents.push('\r'),
+                        Some('n') => contents.push('\n'),
+                        Some('b') => contents.push('\u{8}'),
+                        Some('f') => contents.push('\u{c}'),
+                        Some('\\') => contents.push('\\'),
+                        Some('"') => contents.push('"'),
+                        Some('u') => {
+                            let digits = (0..4).filter_map(|_| self.next_char()).collect::<String>();
+                            match u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() == 4).and_then(char::from_u32) {
+                                Some(c) => contents.push(c),
+                                None => return Err(self.error_at(escape_start, EdnErrorKind::InvalidEscape(format!("u{}", digits)))),
+                            }
+                        }
+                        Some(c) => return Err(self.error_at(escape_start, EdnErrorKind::InvalidEscape(c.to_string()))),
+                        None => return Err(self.error(EdnErrorKind::UnexpectedEof)),
+                    }
+                }
+                Some(c) => contents.push(c),
+                None => return Err(self.error(EdnErrorKind::UnexpectedEof)),
+            }
+        }
+    }
+
+    fn read_char(&mut self) -> Result<Value, EdnError> {
+        let start = (self.line, self.column - 1);
+        // The first character is always part of the literal, so that `\(` and `\;` work
+        let first = match self.next_char() {
+            Some(c) => c,
+            None => return Err(self.error(EdnErrorKind::UnexpectedEof)),
+        };
+        let name = format!("{}{}", first, self.read_token());
+
+        match char_from_name(&name) {
+            Some(c) => Ok(Value::Char(c)),
+            None => Err(self.error_at(start, EdnErrorKind::InvalidChar(name))),
+        }
+    }
+
+    /// Reads a number, symbol, keyword, `nil`, `true` or `false`.
+    fn read_atom(&mut self, start: (usize, usize)) -> Result<Value, EdnError> {
+        let token = self.read_token();
+        let mut token_chars = token.chars();
+        let starts_number = match (token_chars.next(), token_chars.next()) {
+            (Some(c), _) if c.is_ascii_digit() => true,
+            (Some('+' | '-'), Some(c)) => c.is_ascii_digit(),
+            _ => false,
+        };
+        if starts_number {
+            return parse_number(&token).ok_or_else(|| self.error_at(start, EdnErrorKind::InvalidNumber(token)));
+        }
+
+        let invalid_symbol = || self.error_at(start, EdnErrorKind::InvalidSymbol(token.to_string()));
+        if token.is_empty() {
+            return match self.chars.peek() {
+                Some(&c) => Err(self.error(EdnErrorKind::UnexpectedChar(c))),
+                None => Err(self.error(EdnErrorKind::UnexpectedEof)),
+            };
+        }
+        if !token.chars().all(is_symbol_char) {
+            return Err(invalid_symbol());
+        }
+
+        match token.as_str() {
+            "nil" => Ok(Value::Nil),
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            "/" => Ok(Value::Symbol(token)),
+            keyword if keyword.starts_with(':') => {
+                let name = &keyword[1..];
+                if name.is_empty() || name.starts_with(':') || name.starts_with('/') || name.ends_with('/') {
+                    return Err(invalid_symbol());
+                }
+                Ok(Value::Keyword(token))
+            }
+            symbol if symbol.starts_with('/') || symbol.ends_with('/') || symbol.starts_with('#') => Err(invalid_symbol()),
+            _ => Ok(Value::Symbol(token)),
+        }
+    }
+}
+
+/// Parses an EDN integer like `-12` or `7N`, or a float like `1.5`, `2e10` or `3M`.
+fn parse_number(token: &str) -> Option<Value> {
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let has_leading_zero = unsigned.len() > 1 && unsigned.starts_with('0') && unsigned.as_bytes()[1].is_ascii_digit();
+    if has_leading_zero {
+        return None;
+    }
+
+    if let Some(digits) = token.strip_suffix('N') {
+        return digits.parse().ok().map(Value::Integer);
+    }
+    if let Ok(num) = token.parse::<i64>() {
+        return Some(Value::Integer(num));
+    }
+
+    let float_digits = token.strip_suffix('M').unwrap_or(token);
+    let is_float_syntax = float_digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+    if !is_float_syntax {
+        return None;
+    }
+    float_digits.parse().ok().map(Value::Float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyword(name: &str) -> HashableValue {
+        HashableValue::Keyword(name.to_string())
+    }
+
+    #[test]
+    fn test_read_scalars() {
+        assert_eq!(Ok(Value::Nil), read_string(""));
+        assert_eq!(Ok(Value::Integer(-12)), read_string("-12"));
+        assert_eq!(Ok(Value::Integer(7)), read_string("7N"));
+        assert_eq!(Ok(Value::Float(1.5)), read_string("1.5"));
+        assert_eq!(Ok(Value::Float(2e10)), read_string("2e10"));
+        assert_eq!(Ok(Value::Float(3.0)), read_string("3M"));
+        assert_eq!(Ok(Value::Float(f64::NEG_INFINITY)), read_string("##-Inf"));
+        assert_eq!(Ok(Value::Char('a')), read_string("\\a"));
+        assert_eq!(Ok(Value::Char('\n')), read_string("\\newline"));
+        assert_eq!(Ok(Value::Char('é')), read_string("\\u00e9"));
+        assert_eq!(Ok(Value::String("tab\there \u{e9}".to_string())), read_string("\"tab\\there \\u00e9\""));
+        assert_eq!(Ok(Value::Keyword(":ns/name".to_string())), read_string(":ns/name"));
+        assert_eq!(Ok(Value::Symbol("my.ns/foo?".to_string())), read_string("my.ns/foo?"));
+        assert_eq!(Ok(Value::Boolean(false)), read_string("false"));
+    }
+
+    #[test]
+    fn test_read_collections() {
+        let expected = Value::HashMap(sync::HashTrieMap::from_iter([
+            (keyword(":a"), Value::List(sync::List::from_iter([Value::Integer(1), Value::Symbol("b".to_string())]))),
+            (HashableValue::Symbol("s".to_string()), Value::Set(sync::HashTrieSet::from_iter([HashableValue::Nil, keyword(":x")]))),
+            (HashableValue::String("v".to_string()), Value::Vector(sync::Vector::from_iter([Value::Tagged(
+                "my/tag".to_string(),
+                Box::new(Value::Vector(sync::Vector::default())),
+            )]))),
+        ]));
+        assert_eq!(Ok(expected), read_string("{:a (1 b), s #{nil :x} \"v\" [#my/tag [] #_ ignored] ; comment\n}"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |line, column, kind| Err(EdnError { line, column, kind });
+        assert_eq!(error(1, 2, EdnErrorKind::CodeOnlyForm('~')), read_string("[~a]"));
+        assert_eq!(error(2, 1, EdnErrorKind::CodeOnlyForm('@')), read_string("(1\n@a)"));
+        assert_eq!(error(1, 4, EdnErrorKind::UnexpectedEof), read_string("[1 "));
+        assert_eq!(error(1, 3, EdnErrorKind::UnexpectedChar(')')), read_string("[1)"));
+        assert_eq!(error(1, 7, EdnErrorKind::DuplicateKey(":a".to_string())), read_string("{:a 1 :a 2}"));
+        assert_eq!(error(1, 5, EdnErrorKind::DuplicateSetElement("1".to_string())), read_string("#{1 1}"));
+        assert_eq!(error(1, 1, EdnErrorKind::MissingMapValue(":b".to_string())), read_string("{:a 1 :b}"));
+        assert_eq!(error(1, 2, EdnErrorKind::Unhashable("[1]".to_string())), read_string("{[1] 2}"));
+        assert_eq!(error(1, 1, EdnErrorKind::InvalidNumber("012".to_string())), read_string("012"));
+        assert_eq!(error(1, 1, EdnErrorKind::InvalidNumber("1.2.3".to_string())), read_string("1.2.3"));
+        assert_eq!(error(1, 2, EdnErrorKind::InvalidEscape("q".to_string())), read_string("\"\\q\""));
+        assert_eq!(error(1, 1, EdnErrorKind::InvalidChar("foo".to_string())), read_string("\\foo"));
+        assert_eq!(error(1, 1, EdnErrorKind::InvalidTag("1".to_string())), read_string("#1 2"));
+        assert_eq!(error(1, 1, EdnErrorKind::UnexpectedChar(']')), read_string("]"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "{:a [1 -2.5 \\c \\space \"s\\\"\\n\"] :b #{sym} :c (nil true) :d #inst \"2024-01-01\"}";
+        let value = read_string(text).unwrap();
+        assert_eq!(Ok(value.clone()), read_string(&write_string(&value).unwrap()));
+        // NaN isn't equal to itself, so compare the written form instead
+        assert_eq!(Ok("##NaN".to_string()), write_string(&read_string("##NaN").unwrap()));
+
+        let value = read_string("[1.0 1e100 \\u0007 {:k \"v\"} #{1 \\a}]").unwrap();
+        assert_eq!(Ok(value.clone()), read_string(&write_string(&value).unwrap()));
+    }
+
+    #[test]
+    fn test_write_rejects_non_data() {
+        let function = Value::Function(crate::types::FunctionBody::BuiltinValues("f", |_, _| Ok(Value::Nil)));
+        let value = Value::Vector(sync::Vector::from_iter([Value::Integer(1), function.clone()]));
+        assert_eq!(Err(function), write_string(&value));
+    }
+}
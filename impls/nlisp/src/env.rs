@@ -1,50 +1,165 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 use crate::builtins;
 use crate::evaluator::RuntimeError;
+use crate::namespace::{CORE_NAMESPACE, USER_NAMESPACE};
+use crate::port::{ERR_VAR, OUT_VAR, OutputPort, PortWriter};
+use crate::runtime::Runtime;
+use crate::sandbox::{Capabilities, Limits};
+use crate::sync::{Lock, Shared};
 use crate::types::Value;
 
-#[derive(Clone, Debug, PartialEq)]
 pub struct EnvData {
-    map: RefCell<HashMap<String, Value>>,
+    map: Lock<HashMap<String, Value>>,
     outer: Option<Env>,
+    namespace: Shared<str>,
+    runtime: Shared<Runtime>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Env(Rc<EnvData>);
+#[derive(Clone)]
+pub struct Env(Shared<EnvData>);
+
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        // Closures can live in the environment they close over, so compare by identity
+        Shared::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for Env {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Env")
+            .field("namespace", &self.namespace())
+            .field("names", &self.local_names())
+            .finish_non_exhaustive()
+    }
+}
 
 impl Default for Env {
+    /// Creates the `user` namespace, whose outer environment holds the core functions.
     fn default() -> Self {
-        let new_env = Env::with_core_functions();
-        builtins::insert_core_closures(&new_env, &Env::with_core_functions());
-        new_env
+        let core_env = Env::with_core_functions();
+        builtins::insert_core_closures(&core_env, &core_env);
+        let user_env = core_env.create_namespace_env(USER_NAMESPACE);
+        core_env.runtime().namespaces.set_current(USER_NAMESPACE);
+        user_env
     }
 }
 
 impl Env {
     pub fn with_core_functions() -> Self {
-        let map = RefCell::new(HashMap::new());
-        let new_env = Env(Rc::new(EnvData { map, outer: None }));
+        let new_env = Env::with_map_in_namespace(HashMap::new(), CORE_NAMESPACE);
         builtins::insert_core_functions(&new_env);
         new_env
     }
 
     pub fn with_map(map: HashMap<String, Value>) -> Self {
-        Env(Rc::new(EnvData {
-            map: RefCell::new(map),
+        Env::with_map_in_namespace(map, USER_NAMESPACE)
+    }
+
+    fn with_map_in_namespace(map: HashMap<String, Value>, namespace: &str) -> Self {
+        let runtime = Shared::new(Runtime::default());
+        let new_env = Env(Shared::new(EnvData {
+            map: Lock::new(map),
             outer: None,
-        }))
+            namespace: Shared::from(namespace),
+            runtime,
+        }));
+        new_env.runtime().namespaces.register(namespace, new_env.clone());
+        new_env.runtime().namespaces.set_current(namespace);
+        new_env
     }
 
     pub fn create_child_env(&self) -> Self {
-        Env(Rc::new(EnvData {
-            map: RefCell::new(HashMap::new()),
+        Env(Shared::new(EnvData {
+            map: Lock::new(HashMap::new()),
             outer: Some(self.clone()),
+            namespace: self.0.namespace.clone(),
+            runtime: self.0.runtime.clone(),
         }))
     }
 
+    /// Creates and registers a new namespace. Its outer environment is the core namespace,
+    /// so that every namespace can see the core functions.
+    pub fn create_namespace_env(&self, namespace: &str) -> Self {
+        let outer = self.runtime().namespaces.get(CORE_NAMESPACE).map(|core| core.env.clone());
+        let new_env = Env(Shared::new(EnvData {
+            map: Lock::new(HashMap::new()),
+            outer,
+            namespace: Shared::from(namespace),
+            runtime: self.0.runtime.clone(),
+        }));
+        self.runtime().namespaces.register(namespace, new_env.clone());
+        new_env
+    }
+
+    /// Limits the resources used by evaluation from now on, until the limits are replaced.
+    /// `Limits::default()` removes them.
+    pub fn set_limits(&self, limits: Limits) {
+        self.runtime().budget.start(limits);
+    }
+
+    /// A flag that stops evaluation with `RuntimeError::Interrupted` when set to true, e.g. from a
+    /// Ctrl-C handler. The environment and any definitions made so far are kept.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.runtime().budget.interrupt_handle()
+    }
+
+    /// Removes the builtins for any capabilities that aren't allowed from every namespace's view of
+    /// the core functions.
+    pub fn restrict(&self, capabilities: Capabilities) {
+        if let Some(core) = self.runtime().namespaces.get(CORE_NAMESPACE) {
+            builtins::remove_disallowed(&core.env, capabilities);
+        }
+    }
+
+    /// Adds a directory that `require` searches for namespace files.
+    pub fn add_load_path(&self, dir: PathBuf) {
+        self.runtime().namespaces.add_load_path(dir);
+    }
+
+    /// Sends output printed to `*out*` to `writer` instead of stdout, except where `*out*` is
+    /// rebound with `binding` or `with-out-str`.
+    pub fn set_out(&self, writer: impl PortWriter + 'static) {
+        self.set_port(OUT_VAR, OutputPort::new(OUT_VAR, writer));
+    }
+
+    /// Sends output printed to `*err*` to `writer` instead of stderr.
+    pub fn set_err(&self, writer: impl PortWriter + 'static) {
+        self.set_port(ERR_VAR, OutputPort::new(ERR_VAR, writer));
+    }
+
+    fn set_port(&self, var: &str, port: OutputPort) {
+        match self.resolve(var) {
+            Some(Value::DynamicVar(dynamic_var)) => dynamic_var.set_root(Value::OutputPort(port)),
+            _ => self.insert(var.to_string(), Value::OutputPort(port)),
+        }
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.0.runtime
+    }
+
+    /// The name of the namespace that this environment was created in.
+    pub fn namespace(&self) -> &str {
+        &self.0.namespace
+    }
+
+    /// The environment that top-level forms should be evaluated in. This is `self`, unless
+    /// an `ns` or `in-ns` has since switched to a different namespace.
+    pub fn current_namespace_env(&self) -> Self {
+        let namespaces = &self.runtime().namespaces;
+        let current = namespaces.current();
+        if current == self.namespace() {
+            return self.clone();
+        }
+        namespaces.get(&current).map(|namespace| namespace.env.clone()).unwrap_or_else(|| self.clone())
+    }
+
     pub fn lookup(&self, symbol_name: &str) -> Option<Value> {
         if let Some(val) = self.0.map.borrow().get(symbol_name) {
             return Some(val.clone());
@@ -57,23 +172,43 @@ impl Env {
         None
     }
 
+    /// Looks up a symbol bound directly in this environment, ignoring any outer environments.
+    pub fn lookup_local(&self, symbol_name: &str) -> Option<Value> {
+        self.0.map.borrow().get(symbol_name).cloned()
+    }
+
+    /// Looks up a symbol lexically, then falls back to qualified `ns/name` symbols and
+    /// symbols referred into this environment's namespace.
+    pub fn resolve(&self, symbol_name: &str) -> Option<Value> {
+        self.lookup(symbol_name)
+            .or_else(|| self.runtime().namespaces.resolve(self.namespace(), symbol_name))
+    }
+
     pub fn lookup_err(&self, symbol_name: &str) -> Result<Value, RuntimeError> {
-        match self.lookup(symbol_name) {
+        match self.resolve(symbol_name) {
             Some(val) => Ok(val),
             None => Err(RuntimeError::UnboundSymbol(symbol_name.to_string()))
         }
     }
 
     pub fn with_symbol(&self, symbol_name: String, val: Value) -> Self {
-        Env(Rc::new(EnvData {
-            outer: Some(self.clone()),
-            map: RefCell::new(HashMap::from([(symbol_name, val)])),
-        }))
+        let new_env = self.create_child_env();
+        new_env.insert(symbol_name, val);
+        new_env
     }
 
     pub fn insert(&self, symbol_name: String, val: Value) {
         self.0.map.borrow_mut().insert(symbol_name, val);
     }
+
+    pub fn remove(&self, symbol_name: &str) {
+        self.0.map.borrow_mut().remove(symbol_name);
+    }
+
+    /// The names bound directly in this environment, excluding any outer environments.
+    pub fn local_names(&self) -> Vec<String> {
+        self.0.map.borrow().keys().cloned().collect()
+    }
 }
 
 #[cfg(test)]
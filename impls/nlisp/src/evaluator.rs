@@ -1,22 +1,53 @@
 use std::collections::VecDeque;
 
+use itertools::Itertools;
 use thiserror::Error;
 
+use crate::analyzer::check_recur_positions;
+use crate::builtins::reader_tags::read_tagged;
+use crate::edn::EdnError;
 use crate::env::Env;
 use crate::evaluator::RuntimeError::HashError;
-use crate::parser::ParseError;
-use crate::types::{Expr, FunctionBody, HashableValue, Value};
+use crate::namespace::NamespaceError;
+use crate::parser::{parse_text_to_expressions, ParseError};
+use crate::sandbox::{Limit, shallow_size};
+use crate::sync;
+use crate::types::{ClosureArity, Expr, FunctionBody, HashableValue, Value};
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum TypeError {
     #[error("miscellaneous type error. This should eventually be replaced with more specific errors")]
     Misc,
 
     #[error("expected a sequence, but given something that cannot be converted")]
     NotASeq,
+
+    #[error("values cannot be compared with each other")]
+    NotComparable,
+
+    #[error("expected a transient collection")]
+    NotATransient,
+
+    #[error("expected {expected}, but given `{value}`")]
+    Expected { expected: &'static str, value: Value },
+
+    #[error("expected a `{name}` record, but given `{value}`")]
+    ExpectedRecord { name: String, value: Value },
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DestructureError {
+    #[error("cannot destructure `{value}` with sequential binding form `{pattern}`")]
+    NotSequential { pattern: String, value: Value },
+
+    #[error("cannot destructure `{value}` with associative binding form `{pattern}`")]
+    NotAssociative { pattern: String, value: Value },
+
+    #[error("invalid binding form `{0}`")]
+    InvalidBindingForm(String),
 }
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum RuntimeError {
     #[error("parse error: `{0}`")]
     ParseError(#[from] ParseError),
@@ -30,36 +61,123 @@ pub enum RuntimeError {
     #[error("attempted to apply an expression that did not evaluate to a function")]
     CannotApplyNonFunction,
 
+    #[error("attempted to apply a special form to already-evaluated arguments")]
+    CannotApplySpecialForm,
+
     #[error("attempted to apply a function with the wrong number of arguments. Given {given} but expected {expected} args")]
     FunctionApplicationWrongNumberOfArgs { given: usize, expected: usize },
 
+    #[error("wrong number of args ({given}) passed to {name}, expected one of: {arities}")]
+    NoMatchingArity { name: String, given: usize, arities: String },
+
+    #[error("invalid function definition: {0}")]
+    InvalidFunctionDefinition(String),
+
     #[error("expected to bind to a symbol value, but expression evaluated to a different type")]
     ExpectedToBindSymbol,
 
+    #[error("{0}")]
+    Namespace(#[from] NamespaceError),
+
+    #[error("destructuring failed: {0}")]
+    Destructure(#[from] DestructureError),
+
     #[error("expression evaluated to the wrong type: {0}")]
     IncorrectType(#[from] TypeError),
 
+    #[error("attempted to use a transient after it was made persistent")]
+    TransientUsedAfterPersistent,
+
+    #[error("cannot pop from an empty collection")]
+    CannotPopEmpty,
+
     #[error("encountered a let binding identifier with no corresponding assignment")]
     UnmatchedLetBindingID,
 
+    #[error("{name} expects key/value pairs, but `{key}` has no value")]
+    UnpairedKey { name: &'static str, key: Value },
+
+    #[error("uncaught exception: {0}")]
+    Thrown(Value),
+
+    #[error("`{0}` is not a dynamic var, so it can't be rebound")]
+    NotDynamic(String),
+
+    #[error("can't change the value of `{0}` because it isn't bound with `binding`")]
+    DynamicVarNotBound(String),
+
+    /// Not a real error: this carries the values of a `recur` back up to the enclosing `loop` or
+    /// function, which catches it and starts the next iteration.
+    #[error("recur used outside of a loop or function body")]
+    Recur(Vec<Value>),
+
+    #[error("no reader function is registered for the tag `#{0}`")]
+    UnknownReaderTag(String),
+
+    #[error("invalid `#{tag}` literal {form}: {reason}")]
+    InvalidTaggedLiteral { tag: String, form: String, reason: String },
+
+    #[error(transparent)]
+    Edn(#[from] EdnError),
+
+    #[error("`{0}` can't be written as EDN")]
+    NotEdn(Value),
+
+    #[error("invalid JSON: {0}")]
+    InvalidJson(String),
+
+    #[error("`{0}` can't be written as JSON")]
+    NotJson(Value),
+
+    #[error("couldn't {action} `{path}`: {message}")]
+    Io { action: &'static str, path: String, message: String },
+
+    #[error("evaluation exceeded its {0}")]
+    LimitExceeded(Limit),
+
+    #[error("invalid atom state: the validator rejected `{0}`")]
+    InvalidAtomState(Value),
+
+    #[error("no method in multimethod `{name}` for dispatch value `{dispatch_value}`")]
+    NoMethod { name: String, dispatch_value: Value },
+
+    #[error("no implementation of `{method}` from protocol `{protocol}` for type `{type_name}`")]
+    NoProtocolImpl { protocol: String, method: String, type_name: String },
+
+    #[error("a thread panicked: {0}")]
+    Panicked(String),
+
+    #[error("interrupted")]
+    Interrupted,
+
+    #[error("recur can only be used in tail position")]
+    RecurNotInTailPosition,
+
+    #[error("recur was given {given} args, but expected {expected}")]
+    RecurWrongNumberOfArgs { given: usize, expected: usize },
+
     #[error("a miscellaneous error. These should eventually be replaced with more specific errors")]
     Misc,
 }
 
 pub fn evaluate_expr(expr: Expr, env: &Env) -> Result<Value, RuntimeError> {
+    env.runtime().budget.step()?;
     match expr {
         Expr::Integer(num) => Ok(Value::Integer(num)),
+        Expr::Float(num) => Ok(Value::Float(num)),
+        Expr::Char(c) => Ok(Value::Char(c)),
         Expr::String(s) => Ok(Value::String(s)),
         Expr::Keyword(s) => Ok(Value::Keyword(s)),
         Expr::Symbol(s) => {
-            match env.lookup(s.as_str()) {
+            match env.resolve(s.as_str()) {
+                Some(Value::DynamicVar(var)) => Ok(var.get()),
                 Some(val) => Ok(val),
                 None => Err(RuntimeError::UnboundSymbol(s)),
             }
         }
         Expr::Nil => Ok(Value::Nil),
         Expr::Boolean(b) => Ok(Value::Boolean(b)),
-        Expr::Quote(_) => todo!(),
+        Expr::Quote(quoted) => quote(*quoted),
         Expr::Quasiquote(_) => todo!(),
         Expr::Unquote(_) => todo!(),
         Expr::SpliceUnquote(_) => todo!(),
@@ -77,18 +195,18 @@ pub fn evaluate_expr(expr: Expr, env: &Env) -> Result<Value, RuntimeError> {
                     Ok(_) => Err(RuntimeError::CannotApplyNonFunction),
                     Err(e) => Err(e),
                 }
-                None => Ok(Value::List(rpds::List::new()))
+                None => Ok(Value::List(sync::List::default()))
             }
         }
         Expr::Vector(v) => {
-            let mut ret_vec = rpds::Vector::new();
+            let mut ret_vec = sync::Vector::default();
             for expr_elem in v {
                 ret_vec.push_back_mut(evaluate_expr(expr_elem, env)?);
             }
             Ok(Value::Vector(ret_vec))
         }
         Expr::HashMap(hashmap_pairs) => {
-            let mut ret_hashmap = rpds::HashTrieMap::new();
+            let mut ret_hashmap = sync::HashTrieMap::default();
             for (key_expr, value_expr) in hashmap_pairs {
                 let key_value = evaluate_expr(key_expr, env)?;
                 let value_value = evaluate_expr(value_expr, env)?;
@@ -98,53 +216,184 @@ pub fn evaluate_expr(expr: Expr, env: &Env) -> Result<Value, RuntimeError> {
 
             Ok(Value::HashMap(ret_hashmap))
         }
+        Expr::Set(elems) => {
+            let mut ret_set = sync::HashTrieSet::default();
+            for expr_elem in elems {
+                let elem_value = evaluate_expr(expr_elem, env)?;
+                ret_set.insert_mut(elem_value.clone().try_into().map_err(|_| HashError(elem_value))?);
+            }
+            Ok(Value::Set(ret_set))
+        }
+        Expr::Tagged(tag, form) => read_tagged(env, &tag, quote(*form)?),
+    }
+}
+
+/// Parses and evaluates every top-level form in `text`, returning their values.
+///
+/// Each form is evaluated in the current namespace, so an `ns` or `in-ns` form affects the forms after it.
+pub fn evaluate_text(text: &str, env: &Env) -> Result<Vec<Value>, RuntimeError> {
+    let exprs = parse_text_to_expressions(text)?;
+
+    let mut values = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        values.push(evaluate_form(expr, env)?);
+    }
+    Ok(values)
+}
+
+/// Evaluates a single top-level form in the current namespace.
+pub fn evaluate_form(expr: Expr, env: &Env) -> Result<Value, RuntimeError> {
+    check_recur_positions(&expr, false)?;
+    evaluate_expr(expr, &env.current_namespace_env())
+}
+
+/// Converts an unevaluated expression into the equivalent data, as `quote` does.
+pub fn quote(expr: Expr) -> Result<Value, RuntimeError> {
+    let quote_form = |name: &str, quoted: Expr| -> Result<Value, RuntimeError> {
+        Ok(Value::List(sync::List::from_iter([Value::Symbol(name.to_string()), quote(quoted)?])))
+    };
+
+    match expr {
+        Expr::Integer(num) => Ok(Value::Integer(num)),
+        Expr::Float(num) => Ok(Value::Float(num)),
+        Expr::Char(c) => Ok(Value::Char(c)),
+        Expr::String(s) => Ok(Value::String(s)),
+        Expr::Symbol(s) => Ok(Value::Symbol(s)),
+        Expr::Keyword(s) => Ok(Value::Keyword(s)),
+        Expr::Nil => Ok(Value::Nil),
+        Expr::Boolean(b) => Ok(Value::Boolean(b)),
+        Expr::Quote(quoted) => quote_form("quote", *quoted),
+        Expr::Quasiquote(quoted) => quote_form("quasiquote", *quoted),
+        Expr::Unquote(quoted) => quote_form("unquote", *quoted),
+        Expr::SpliceUnquote(quoted) => quote_form("splice-unquote", *quoted),
+        Expr::List(elems) => Ok(Value::List(elems.into_iter().map(quote).collect::<Result<_, _>>()?)),
+        Expr::Vector(elems) => Ok(Value::Vector(elems.into_iter().map(quote).collect::<Result<_, _>>()?)),
+        Expr::HashMap(pairs) => {
+            let mut ret_hashmap = sync::HashTrieMap::default();
+            for (key_expr, value_expr) in pairs {
+                let key_value = quote(key_expr)?;
+                let key_hash: HashableValue = key_value.clone().try_into().map_err(|_| HashError(key_value))?;
+                ret_hashmap.insert_mut(key_hash, quote(value_expr)?);
+            }
+            Ok(Value::HashMap(ret_hashmap))
+        }
+        Expr::Set(elems) => {
+            let mut ret_set = sync::HashTrieSet::default();
+            for elem_expr in elems {
+                let elem_value = quote(elem_expr)?;
+                ret_set.insert_mut(elem_value.clone().try_into().map_err(|_| HashError(elem_value))?);
+            }
+            Ok(Value::Set(ret_set))
+        }
+        // Reader functions need an environment, so a quoted tagged literal is left as tagged data
+        Expr::Tagged(tag, form) => Ok(Value::Tagged(tag, Box::new(quote(*form)?))),
     }
 }
 
 fn apply_function(function_body: FunctionBody, arg_exprs: VecDeque<Expr>, env: &Env) -> Result<Value, RuntimeError> {
     match function_body {
-        FunctionBody::BuiltinExpressions(func_pointer) => func_pointer(env, arg_exprs),
-        FunctionBody::BuiltinValues(func_pointer) => {
+        FunctionBody::BuiltinExpressions(_, func_pointer) => func_pointer(env, arg_exprs),
+        function_body => {
             let mut arg_values = VecDeque::with_capacity(arg_exprs.len());
             for arg_expr in arg_exprs {
                 arg_values.push_back(evaluate_expr(arg_expr, env)?);
             }
-            func_pointer(env, arg_values)
+            apply_function_to_values(&function_body, arg_values, env)
         }
-        FunctionBody::Closure { closed_env, params, variadic_param, body } => {
-            if arg_exprs.len() < params.len() || (arg_exprs.len() > params.len() && variadic_param.is_none()) {
-                return Err(RuntimeError::FunctionApplicationWrongNumberOfArgs { expected: params.len(), given: arg_exprs.len() });
-            }
+    }
+}
 
-            let mut args_iter = arg_exprs.into_iter();
+/// Calls a builtin or native function, charging it for what it allocates when there's an allocation limit.
+fn apply_builtin<F>(env: &Env, arg_values: VecDeque<Value>, apply: F) -> Result<Value, RuntimeError>
+    where F: FnOnce(VecDeque<Value>) -> Result<Value, RuntimeError>
+{
+    let budget = &env.runtime().budget;
+    if !budget.tracks_allocation() {
+        return apply(arg_values);
+    }
+    let largest_arg = arg_values.iter().map(shallow_size).max().unwrap_or(0);
+    let result = apply(arg_values)?;
+    budget.charge_allocation(largest_arg, &result)?;
+    Ok(result)
+}
 
-            // Consume the named arguments
-            let mut arg_values = Vec::with_capacity(params.len());
-            for _ in 0..(params.len()) {
-                arg_values.push(evaluate_expr(
-                    args_iter.next().expect("arg to be present"),
-                    env)?);
-            }
+/// Applies a function to arguments that have already been evaluated.
+///
+/// This is the shared entry point for builtins that need to call back into user code,
+/// such as `map` or `reduce`. Special forms operate on unevaluated expressions, so they
+/// cannot be applied this way.
+pub fn apply_function_to_values(function_body: &FunctionBody, arg_values: VecDeque<Value>, env: &Env) -> Result<Value, RuntimeError> {
+    match function_body {
+        FunctionBody::BuiltinExpressions(..) => Err(RuntimeError::CannotApplySpecialForm),
+        FunctionBody::BuiltinValues(_, func_pointer) => apply_builtin(env, arg_values, |args| func_pointer(env, args)),
+        FunctionBody::Native(native) => apply_builtin(env, arg_values, |args| native.call(env, args)),
+        FunctionBody::MultiMethod(multi) => multi.call(env, arg_values),
+        FunctionBody::ProtocolMethod(protocol, index) => protocol.call(*index, env, arg_values),
+        FunctionBody::Closure { closed_env, name, arities, .. } => {
+            let _call = env.runtime().budget.enter_call()?;
+            // Prefer a fixed arity over the variadic one when both accept the arguments
+            let matching_arity = arities.iter()
+                .filter(|arity| arity.accepts(arg_values.len()))
+                .min_by_key(|arity| arity.variadic_param.is_some());
+            let arity = match matching_arity {
+                Some(arity) => arity,
+                None => return Err(RuntimeError::NoMatchingArity {
+                    name: name.clone().unwrap_or_else(|| "fn*".to_string()),
+                    given: arg_values.len(),
+                    arities: arities.iter().map(|arity| arity.to_string()).join(" "),
+                }),
+            };
 
-            let new_env = closed_env.create_child_env();
-            for (param_name, arg_value) in params.iter().zip(arg_values) {
-                new_env.insert(param_name.to_string(), arg_value);
-            }
+            // `recur` in tail position rebinds the arguments and re-runs the body without growing the stack
+            let mut arg_values = arg_values;
+            loop {
+                env.runtime().budget.check_interrupt()?;
+                let new_env = closed_env.create_child_env();
+                // A named function can refer to itself without needing a `def!`
+                if let Some(name) = name {
+                    new_env.insert(name.to_string(), Value::Function(function_body.clone()));
+                }
 
-            // Consume the remaining arguments
-            if let Some(variadic_param_name) = variadic_param {
-                let mut variadic_values = rpds::List::new();
-                for arg_expr in args_iter.rev() {
-                    variadic_values.push_front_mut(evaluate_expr(arg_expr, env)?);
+                // Bind the named arguments
+                let mut args_iter = arg_values.into_iter();
+                for param_name in arity.params.iter() {
+                    new_env.insert(param_name.to_string(), args_iter.next().expect("arg to be present"));
                 }
-                new_env.insert(variadic_param_name, Value::List(variadic_values));
-            }
 
-            evaluate_expr(body, &new_env)
+                // Bind the remaining arguments
+                if let Some(variadic_param_name) = &arity.variadic_param {
+                    new_env.insert(variadic_param_name.to_string(), Value::List(args_iter.collect()));
+                }
+
+                match evaluate_expr(arity.body.clone(), &new_env) {
+                    Err(RuntimeError::Recur(recur_values)) => {
+                        arg_values = recur_args_to_call_args(arity, recur_values)?;
+                    }
+                    result => return result,
+                }
+            }
         }
     }
 }
 
+/// `recur` passes the variadic arguments of a function as a single sequence, so they need to be
+/// spread back out before being rebound.
+fn recur_args_to_call_args(arity: &ClosureArity, mut recur_values: Vec<Value>) -> Result<VecDeque<Value>, RuntimeError> {
+    let expected = arity.params.len() + usize::from(arity.variadic_param.is_some());
+    if recur_values.len() != expected {
+        return Err(RuntimeError::RecurWrongNumberOfArgs { given: recur_values.len(), expected });
+    }
+
+    if arity.variadic_param.is_some() {
+        let rest = recur_values.pop().expect("recur to have a variadic argument");
+        let mut arg_values = VecDeque::from(recur_values);
+        arg_values.extend(rest.to_seq()?.iter().cloned());
+        Ok(arg_values)
+    } else {
+        Ok(VecDeque::from(recur_values))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::LinkedList;
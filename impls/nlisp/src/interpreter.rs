@@ -0,0 +1,201 @@
+
+use crate::convert::{FromValue, IntoValue};
+use crate::env::Env;
+use crate::evaluator::{apply_function_to_values, evaluate_text, RuntimeError};
+use crate::sandbox::{Capabilities, Limits};
+use crate::types::{FunctionBody, NativeFn, NativeFunction, Value};
+
+/// An nlisp interpreter for embedding in a Rust program. Unlike `rep`, results are returned as
+/// values rather than printed, and Rust closures can be registered as nlisp functions.
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an interpreter without the builtins for capabilities that aren't allowed.
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        let interpreter = Self::default();
+        interpreter.env.restrict(capabilities);
+        interpreter
+    }
+
+    /// The environment of the `user` namespace, for use with `rep` and the other lower level functions.
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Evaluates every form in `input`, returning the value of the last one, or nil if there were none.
+    pub fn eval(&self, input: &str) -> Result<Value, RuntimeError> {
+        Ok(evaluate_text(input, &self.env)?.pop().unwrap_or(Value::Nil))
+    }
+
+    /// Like `eval`, but stops with `RuntimeError::LimitExceeded` if evaluating `input` uses more
+    /// than `limits` allow. The limits only apply to this call, after which any set with
+    /// `Env::set_limits` apply again.
+    pub fn eval_with_limits(&self, input: &str, limits: Limits) -> Result<Value, RuntimeError> {
+        let _limits = self.env.runtime().budget.start_scoped(limits);
+        self.eval(input)
+    }
+
+    /// Like `eval`, but converts the result to a Rust type.
+    pub fn eval_as<T: FromValue>(&self, input: &str) -> Result<T, RuntimeError> {
+        T::from_value(self.eval(input)?)
+    }
+
+    /// Binds `name` in the current namespace, as `def!` would.
+    pub fn define(&self, name: &str, value: impl IntoValue) {
+        self.env.current_namespace_env().insert(name.to_string(), value.into_value());
+    }
+
+    /// Looks up `name` and converts its value to a Rust type. Dynamic vars give their current value.
+    pub fn get<T: FromValue>(&self, name: &str) -> Result<T, RuntimeError> {
+        let value = match self.env.current_namespace_env().lookup_err(name)? {
+            Value::DynamicVar(var) => var.get(),
+            value => value,
+        };
+        T::from_value(value)
+    }
+
+    /// Calls the function bound to `name` with already evaluated arguments.
+    pub fn call(&self, name: &str, args: impl IntoIterator<Item=Value>) -> Result<Value, RuntimeError> {
+        let env = self.env.current_namespace_env();
+        match env.lookup_err(name)? {
+            Value::Function(function_body) => apply_function_to_values(&function_body, args.into_iter().collect(), &env),
+            _ => Err(RuntimeError::CannotApplyNonFunction),
+        }
+    }
+
+    /// Registers a Rust closure as the function `name`. The closure can capture state shared with
+    /// the host, such as an `Rc<RefCell<_>>`, or an `Arc<Mutex<_>>` with the `sync` feature, and
+    /// receives its arguments already evaluated.
+    pub fn register_fn<F>(&self, name: &str, func: F)
+        where F: NativeFn + 'static
+    {
+        self.define(name, Value::Function(FunctionBody::Native(NativeFunction::new(name, func))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::time::Duration;
+
+    use crate::sandbox::Limit;
+
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let interpreter = Interpreter::new();
+        assert_eq!(Ok(Value::Integer(3)), interpreter.eval("(def! x 1) (+ x 2)"));
+        assert_eq!(Ok(Value::Nil), interpreter.eval(""));
+        assert_eq!(Ok(vec![1, 2]), interpreter.eval_as::<Vec<i64>>("(list 1 2)"));
+        assert!(interpreter.eval_as::<String>("1").is_err());
+    }
+
+    #[test]
+    fn test_define_and_call() {
+        let interpreter = Interpreter::new();
+        interpreter.define("limit", 10);
+        interpreter.eval("(def! over-limit? (fn* [n] (> n limit)))").unwrap();
+        assert_eq!(Ok(Value::Boolean(true)), interpreter.call("over-limit?", [Value::Integer(11)]));
+        assert_eq!(Ok(10), interpreter.get::<i64>("limit"));
+        assert_eq!(Err(RuntimeError::CannotApplyNonFunction), interpreter.call("limit", []));
+    }
+
+    #[test]
+    fn test_register_fn_with_state() {
+        let interpreter = Interpreter::new();
+        let calls = Arc::new(AtomicI64::new(0));
+        let counter = calls.clone();
+        interpreter.register_fn("tick!", move |_env, args| {
+            let total = counter.fetch_add(args.len() as i64, Ordering::Relaxed) + args.len() as i64;
+            Ok(total.into_value())
+        });
+
+        assert_eq!(Ok(Value::Integer(3)), interpreter.eval("(do (tick! :a) (tick! :b :c))"));
+        assert_eq!(Ok(Value::Integer(9)), interpreter.eval("(reduce + 0 (map (fn* [x] (tick! x)) [1 2]))"));
+        assert_eq!(5, calls.load(Ordering::Relaxed));
+        assert_eq!(Ok("#<native tick!>".to_string()), interpreter.eval_as::<String>("(pr-str tick!)"));
+    }
+
+    #[test]
+    fn test_limits() {
+        let interpreter = Interpreter::new();
+        let steps = Limits { max_steps: Some(1000), ..Limits::default() };
+        assert_eq!(
+            Err(RuntimeError::LimitExceeded(Limit::Steps(1000))),
+            interpreter.eval_with_limits("(loop [i 0] (recur (+ i 1)))", steps)
+        );
+        assert_eq!(Ok(Value::Integer(3)), interpreter.eval_with_limits("(+ 1 2)", steps));
+
+        let depth = Limits { max_depth: Some(50), ..Limits::default() };
+        interpreter.eval("(def! f (fn* [] (f)))").unwrap();
+        assert_eq!(Err(RuntimeError::LimitExceeded(Limit::Depth(50))), interpreter.eval_with_limits("(f)", depth));
+        // The depth is unwound after the error, so calls work again
+        assert_eq!(Ok(Value::Integer(1)), interpreter.eval_with_limits("((fn* [x] x) 1)", depth));
+
+        let timeout = Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() };
+        assert_eq!(
+            Err(RuntimeError::LimitExceeded(Limit::Timeout(Duration::from_millis(20)))),
+            interpreter.eval_with_limits("(loop [] (recur))", timeout)
+        );
+
+        let allocation = Limits { max_allocation: Some(100), ..Limits::default() };
+        let grow = "(loop [s \"\" i 0] (if (< i 200) (recur (str s \"x\") (+ i 1)) i))";
+        assert_eq!(Err(RuntimeError::LimitExceeded(Limit::Allocation(100))), interpreter.eval_with_limits(grow, allocation));
+        assert_eq!(Ok(Value::Integer(200)), interpreter.eval(grow));
+    }
+
+    #[test]
+    fn test_limits_are_restored() {
+        let interpreter = Interpreter::new();
+        let outer = Limits { max_steps: Some(1000), ..Limits::default() };
+        interpreter.env().set_limits(outer);
+        let depth = Limits { max_depth: Some(50), ..Limits::default() };
+        interpreter.eval("(def! f (fn* [] (f)))").unwrap();
+        assert_eq!(Err(RuntimeError::LimitExceeded(Limit::Depth(50))), interpreter.eval_with_limits("(f)", depth));
+        assert_eq!(outer, interpreter.env().runtime().budget.limits());
+        assert_eq!(Err(RuntimeError::LimitExceeded(Limit::Steps(1000))), interpreter.eval("(loop [] (recur))"));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let interpreter = Interpreter::new();
+        interpreter.eval("(def! kept 1)").unwrap();
+        let interrupt = interpreter.env().interrupt_handle();
+        let handle = interrupt.clone();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            handle.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(Err(RuntimeError::Interrupted), interpreter.eval("(loop [] (recur))"));
+        interrupter.join().unwrap();
+
+        // The interrupt is cleared once it has stopped evaluation, and the session is kept
+        assert_eq!(Ok(Value::Integer(2)), interpreter.eval("(+ kept 1)"));
+
+        // `try*` can't catch an interrupt, and recursion without `loop` is interrupted too
+        interrupt.store(true, Ordering::Relaxed);
+        assert_eq!(Err(RuntimeError::Interrupted), interpreter.eval("(try* ((fn* f [] (f))) (catch* e :caught))"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let interpreter = Interpreter::with_capabilities(Capabilities { io: true, ..Capabilities::NONE });
+        assert_eq!(Err(RuntimeError::UnboundSymbol("slurp".to_string())), interpreter.eval("(slurp \"/etc/hostname\")"));
+        assert_eq!(Err(RuntimeError::UnboundSymbol("sh".to_string())), interpreter.eval("(sh \"ls\")"));
+        assert_eq!(Err(RuntimeError::UnboundSymbol("add-load-path!".to_string())), interpreter.eval("(add-load-path! \"/\")"));
+        assert_eq!(Ok(Value::String("1".to_string())), interpreter.eval("(with-out-str (print 1))"));
+
+        let no_io = Interpreter::with_capabilities(Capabilities { io: false, ..Capabilities::ALL });
+        assert_eq!(Err(RuntimeError::UnboundSymbol("prn".to_string())), no_io.eval("(prn 1)"));
+        assert_eq!(Ok(Value::String("1".to_string())), no_io.eval("(str 1)"));
+    }
+}
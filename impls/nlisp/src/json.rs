@@ -0,0 +1,192 @@
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+use crate::types::{HashableValue, Value};
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonOptions {
+    /// Indent nested arrays and objects by two spaces per level, one element per line
+    pub pretty: bool,
+    /// Write object keys in sorted order, rather than the map's own order
+    pub sort_keys: bool,
+}
+
+/// Parses JSON text into a value. Objects become maps with string keys, or keyword keys if
+/// `keywordize_keys` is set.
+pub fn parse(text: &str, keywordize_keys: bool) -> Result<Value, serde_json::Error> {
+    let json = serde_json::from_str(text)?;
+    Ok(from_json(json, keywordize_keys))
+}
+
+fn from_json(json: serde_json::Value, keywordize_keys: bool) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Boolean(b),
+        serde_json::Value::Number(num) => match num.as_i64() {
+            Some(num) => Value::Integer(num),
+            // Integers too large for an i64 lose precision rather than failing to parse
+            None => Value::Float(num.as_f64().expect("a JSON number to be representable as a float")),
+        },
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(elems) => {
+            Value::Vector(elems.into_iter().map(|elem| from_json(elem, keywordize_keys)).collect())
+        }
+        serde_json::Value::Object(pairs) => Value::HashMap(pairs.into_iter()
+            .map(|(key, value)| {
+                let key = if keywordize_keys {
+                    HashableValue::Keyword(format!(":{}", key))
+                } else {
+                    HashableValue::String(key)
+                };
+                (key, from_json(value, keywordize_keys))
+            })
+            .collect()),
+    }
+}
+
+/// Writes `value` as JSON, or returns the first part of it that JSON can't represent, such as a
+/// function or a NaN.
+pub fn stringify(value: &Value, options: &JsonOptions) -> Result<String, Value> {
+    let mut output = String::new();
+    write_json(value, options, 0, &mut output)?;
+    Ok(output)
+}
+
+fn write_string(s: &str, output: &mut String) {
+    output.push_str(&serde_json::to_string(s).expect("strings to always serialize"));
+}
+
+/// Keywords, symbols and chars are written as strings, without a keyword's leading `:`.
+fn write_name(value: &Value, output: &mut String) -> bool {
+    match value {
+        Value::String(s) | Value::Symbol(s) => write_string(s, output),
+        Value::Keyword(k) => write_string(&k[1..], output),
+        Value::Char(c) => write_string(&c.to_string(), output),
+        _ => return false,
+    }
+    true
+}
+
+fn write_json(value: &Value, options: &JsonOptions, depth: usize, output: &mut String) -> Result<(), Value> {
+    match value {
+        Value::Nil => output.push_str("null"),
+        Value::Boolean(b) => write!(output, "{}", b).expect("to be able to write to a string"),
+        Value::Integer(num) => write!(output, "{}", num).expect("to be able to write to a string"),
+        Value::Float(num) if num.is_finite() => {
+            output.push_str(&serde_json::to_string(num).expect("finite floats to always serialize"))
+        }
+        Value::List(elems) => write_array(elems.iter(), options, depth, output)?,
+        Value::Vector(elems) => write_array(elems.iter(), options, depth, output)?,
+        Value::Set(elems) => {
+            let elems = elems.iter().map(|elem| Value::from(elem.clone())).collect::<Vec<_>>();
+            write_array(elems.iter(), options, depth, output)?
+        }
+        Value::HashMap(pairs) => write_object(pairs.iter(), options, depth, output)?,
+        Value::Record(record) => write_object(record.entries(), options, depth, output)?,
+        // Tagged literals like `#inst "2024-01-31"` are written as their underlying form
+        Value::Tagged(_, value) => write_json(value, options, depth, output)?,
+        value => if !write_name(value, output) {
+            return Err(value.clone());
+        },
+    }
+    Ok(())
+}
+
+fn write_object<'a>(pairs: impl Iterator<Item=(&'a HashableValue, &'a Value)>, options: &JsonOptions, depth: usize, output: &mut String) -> Result<(), Value> {
+    let mut entries = vec![];
+    for (key, value) in pairs {
+        let mut key_json = String::new();
+        match Value::from(key.clone()) {
+            Value::Integer(num) => write_string(&num.to_string(), &mut key_json),
+            key => if !write_name(&key, &mut key_json) {
+                return Err(key);
+            },
+        }
+        entries.push((key_json, value));
+    }
+    if options.sort_keys {
+        entries.sort_by(|(l, _), (r, _)| l.cmp(r));
+    }
+
+    let mut entries = entries.into_iter().map(|(key, value)| {
+        let mut entry = key;
+        entry.push_str(if options.pretty { ": " } else { ":" });
+        write_json(value, options, depth + 1, &mut entry)?;
+        Ok(entry)
+    }).collect::<Result<Vec<_>, Value>>()?.into_iter();
+    write_delimited('{', '}', &mut entries, options, depth, output);
+    Ok(())
+}
+
+fn write_array<'a>(elems: impl Iterator<Item=&'a Value>, options: &JsonOptions, depth: usize, output: &mut String) -> Result<(), Value> {
+    let mut elems = elems.map(|elem| {
+        let mut elem_json = String::new();
+        write_json(elem, options, depth + 1, &mut elem_json)?;
+        Ok(elem_json)
+    }).collect::<Result<Vec<_>, Value>>()?.into_iter();
+    write_delimited('[', ']', &mut elems, options, depth, output);
+    Ok(())
+}
+
+fn write_delimited(open: char, close: char, items: &mut impl ExactSizeIterator<Item=String>, options: &JsonOptions, depth: usize, output: &mut String) {
+    output.push(open);
+    if options.pretty && items.len() > 0 {
+        let indent = "  ".repeat(depth + 1);
+        output.push('\n');
+        output.push_str(&indent);
+        output.push_str(&items.join(&format!(",\n{}", indent)));
+        output.push('\n');
+        output.push_str(&"  ".repeat(depth));
+    } else {
+        output.push_str(&items.join(","));
+    }
+    output.push(close);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let expected = Value::HashMap(sync::HashTrieMap::from_iter([
+            (HashableValue::String("name".to_string()), Value::String("nlisp".to_string())),
+            (HashableValue::String("items".to_string()), Value::Vector(sync::Vector::from_iter([
+                Value::Integer(1), Value::Float(2.5), Value::Nil, Value::Boolean(true),
+            ]))),
+        ]));
+        assert_eq!(expected, parse(r#"{"name": "nlisp", "items": [1, 2.5, null, true]}"#, false).unwrap());
+
+        let keywordized = parse(r#"{"a": {"b": 1}}"#, true).unwrap();
+        let inner = Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Keyword(":b".to_string()), Value::Integer(1))]));
+        assert_eq!(Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Keyword(":a".to_string()), inner)])), keywordized);
+
+        assert_eq!(Value::Float(18446744073709551615.0), parse("18446744073709551615", false).unwrap());
+        assert!(parse("{\"a\": }", false).is_err());
+        assert!(parse("[1] [2]", false).is_err());
+    }
+
+    #[test]
+    fn test_stringify() {
+        let value = parse(r#"{"b": [1, "two\n", null], "a": {}, "c": -0.5}"#, true).unwrap();
+        let sorted = JsonOptions { pretty: false, sort_keys: true };
+        assert_eq!(Ok(r#"{"a":{},"b":[1,"two\n",null],"c":-0.5}"#.to_string()), stringify(&value, &sorted));
+
+        let pretty = JsonOptions { pretty: true, sort_keys: true };
+        assert_eq!(Ok("{\n  \"a\": {},\n  \"b\": [\n    1,\n    \"two\\n\",\n    null\n  ],\n  \"c\": -0.5\n}".to_string()), stringify(&value, &pretty));
+
+        let set = Value::Set(sync::HashTrieSet::from_iter([HashableValue::Symbol("sym".to_string())]));
+        assert_eq!(Ok(r#"["sym"]"#.to_string()), stringify(&set, &JsonOptions::default()));
+    }
+
+    #[test]
+    fn test_stringify_rejects_non_json() {
+        assert_eq!(Err(Value::Float(f64::INFINITY)), stringify(&Value::Float(f64::INFINITY), &JsonOptions::default()));
+
+        let map = Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Boolean(true), Value::Nil)]));
+        assert_eq!(Err(Value::Boolean(true)), stringify(&map, &JsonOptions::default()));
+    }
+}
@@ -2,28 +2,83 @@ extern crate core;
 
 use std::fmt::Write;
 
+pub use atom::Atom;
+pub use convert::{FromValue, IntoValue};
 pub use env::Env;
+pub use evaluator::{RuntimeError, TypeError};
+pub use interpreter::Interpreter;
+pub use parser::ParseError;
+pub use reader::{Reader, ReadError};
+pub use sandbox::{Capabilities, Limit, Limits};
+#[cfg(feature = "serde")]
+pub use serde_value::{from_value, SerdeError, to_value};
+pub use sync::{HashTrieMap, HashTrieSet, List, Shared, ThreadSafe, Vector};
+pub use types::{Expr, FunctionBody, HashableValue, NativeFn, NativeFunction, Value};
 
-use crate::evaluator::{evaluate_expr, RuntimeError};
-use crate::parser::parse_text_to_expressions;
+use crate::evaluator::{evaluate_form, evaluate_text};
+use crate::pprint::pprint_value;
+use crate::printer::PrintOptions;
 
 mod types;
+mod atom;
+mod convert;
+mod interpreter;
+// The parser's original code is kept as written rather than reworked for these lints
+#[allow(clippy::is_digit_ascii_radix, clippy::absurd_extreme_comparisons, clippy::needless_return)]
 mod parser;
+mod edn;
+mod json;
 mod printer;
+mod port;
+#[cfg(feature = "sync")]
+mod promise;
+mod reader;
+mod record;
+mod pprint;
 mod evaluator;
 mod env;
+mod dispatch;
 mod builtins;
+#[cfg(feature = "sync")]
+mod channel;
+mod analyzer;
+mod namespace;
+mod runtime;
+mod sandbox;
+mod sync;
+#[cfg(feature = "serde")]
+mod serde_value;
 
 pub type Result<T> = std::result::Result<T, RuntimeError>;
 
 pub fn rep(input: &str, env: &Env) -> Result<String> {
-    let exprs = parse_text_to_expressions(input)?;
+    let mut output = String::new();
+    for result in evaluate_text(input, env)? {
+        writeln!(output, "{}", result.print_value_with_options(true, &PrintOptions::from_env(env))).expect("to be able to write to a string");
+    }
+
+    Ok(output)
+}
 
+/// Like `rep`, but pretty prints each result using the current `*print-right-margin*`,
+/// `*print-length*` and `*print-level*`.
+pub fn rep_pprint(input: &str, env: &Env) -> Result<String> {
     let mut output = String::new();
-    for expr in exprs {
-        let result = evaluate_expr(expr, env)?;
-        writeln!(output, "{}", result).expect("to be able to write to a string");
+    for result in evaluate_text(input, env)? {
+        writeln!(output, "{}", pprint_value(&result, &PrintOptions::from_env(env))).expect("to be able to write to a string");
     }
 
     Ok(output)
 }
+
+/// Evaluates a form that has already been read, e.g. by a `Reader`, and prints the result.
+pub fn rep_form(expr: Expr, env: &Env) -> Result<String> {
+    let result = evaluate_form(expr, env)?;
+    Ok(format!("{}\n", result.print_value_with_options(true, &PrintOptions::from_env(env))))
+}
+
+/// Like `rep_form`, but pretty prints the result.
+pub fn rep_pprint_form(expr: Expr, env: &Env) -> Result<String> {
+    let result = evaluate_form(expr, env)?;
+    Ok(format!("{}\n", pprint_value(&result, &PrintOptions::from_env(env))))
+}
@@ -1,25 +1,67 @@
 use std::io;
 use std::io::Write;
+use std::sync::atomic::Ordering;
 
-use nlisp::{Env, rep};
+use nlisp::{Env, Reader, ReadError, rep_form, rep_pprint_form};
 
 fn main() -> Result<(), io::Error> {
     let env = Env::default();
+    if let Some(load_path) = std::env::var_os("NLISP_LOAD_PATH") {
+        for dir in std::env::split_paths(&load_path) {
+            env.add_load_path(dir);
+        }
+    }
+    // `--pprint` lays out results to fit the terminal instead of printing them on one line
+    let pretty = std::env::args().skip(1).any(|arg| arg == "--pprint");
 
+    // Ctrl-C interrupts the form being evaluated rather than exiting, so a runaway loop can be
+    // stopped without losing the session
+    let interrupted = env.interrupt_handle();
+    let handler_flag = interrupted.clone();
+    if let Err(e) = ctrlc::set_handler(move || handler_flag.store(true, Ordering::Relaxed)) {
+        eprintln!("warning: couldn't install the Ctrl-C handler: {}", e);
+    }
+
+    // Forms can span several lines, so input is read line by line until each form is complete
+    let mut reader = Reader::new();
     loop {
-        print!("user> ");
+        print!("{}", if reader.is_empty() { "user> " } else { "  ... " });
         io::stdout().flush()?;
 
         let mut input_buffer = String::new();
         let bytes = io::stdin().read_line(&mut input_buffer)?;
+        // A Ctrl-C pressed at the prompt shouldn't interrupt the next form
+        interrupted.store(false, Ordering::Relaxed);
 
         if bytes == 0 {
-            return Ok(());
+            reader.finish();
+        } else {
+            reader.feed_str(&input_buffer);
         }
 
-        match rep(input_buffer.as_str(), &env) {
-            Ok(output) => print!("{}", output),
-            Err(e) => println!("error: {}", e),
+        loop {
+            let expr = match reader.next_expr() {
+                Ok(Some(expr)) => expr,
+                Ok(None) | Err(ReadError::Incomplete) => break,
+                Err(e) => {
+                    println!("error: {}", e);
+                    continue;
+                }
+            };
+
+            let result = if pretty {
+                rep_pprint_form(expr, &env)
+            } else {
+                rep_form(expr, &env)
+            };
+            match result {
+                Ok(output) => print!("{}", output),
+                Err(e) => println!("error: {}", e),
+            }
+        }
+
+        if bytes == 0 {
+            return Ok(());
         }
     }
 }
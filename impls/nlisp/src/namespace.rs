@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::env::Env;
+use crate::evaluator::{evaluate_text, RuntimeError};
+use crate::sync::{Lock, Shared};
+use crate::types::Value;
+
+pub const CORE_NAMESPACE: &str = "nlisp.core";
+pub const USER_NAMESPACE: &str = "user";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum NamespaceError {
+    #[error("could not find namespace `{name}` on the load path, expected a file named `{file}`")]
+    NotFound { name: String, file: String },
+
+    #[error("could not load namespace `{name}`: {reason}")]
+    LoadFailed { name: String, reason: String },
+
+    #[error("cyclic load dependency: {0}")]
+    CyclicDependency(String),
+
+    #[error("invalid require spec `{0}`")]
+    InvalidRequireSpec(String),
+}
+
+/// A namespace's definitions live in its own `Env`. Aliases and referred symbols are kept
+/// alongside it and consulted only when a symbol isn't bound lexically.
+pub struct Namespace {
+    pub env: Env,
+    aliases: Lock<HashMap<String, String>>,
+    refers: Lock<HashMap<String, (String, String)>>,
+}
+
+/// The registry of every namespace in a runtime, along with the current namespace and the
+/// directories that `require` searches for namespace files.
+pub struct Namespaces {
+    namespaces: Lock<HashMap<String, Shared<Namespace>>>,
+    current: Lock<String>,
+    loaded: Lock<HashSet<String>>,
+    loading: Lock<Vec<String>>,
+    load_path: Lock<Vec<PathBuf>>,
+}
+
+impl Default for Namespaces {
+    fn default() -> Self {
+        Namespaces {
+            namespaces: Lock::new(HashMap::new()),
+            current: Lock::new(USER_NAMESPACE.to_string()),
+            loaded: Lock::new(HashSet::new()),
+            loading: Lock::new(vec![]),
+            load_path: Lock::new(vec![PathBuf::from(".")]),
+        }
+    }
+}
+
+impl Namespaces {
+    pub fn register(&self, name: &str, env: Env) {
+        self.namespaces.borrow_mut().insert(name.to_string(), Shared::new(Namespace {
+            env,
+            aliases: Lock::new(HashMap::new()),
+            refers: Lock::new(HashMap::new()),
+        }));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Shared<Namespace>> {
+        self.namespaces.borrow().get(name).cloned()
+    }
+
+    pub fn current(&self) -> String {
+        self.current.borrow().to_string()
+    }
+
+    pub fn set_current(&self, name: &str) {
+        *self.current.borrow_mut() = name.to_string();
+    }
+
+    pub fn load_path(&self) -> Vec<PathBuf> {
+        self.load_path.borrow().clone()
+    }
+
+    pub fn clear_load_path(&self) {
+        self.load_path.borrow_mut().clear();
+    }
+
+    pub fn add_load_path(&self, dir: PathBuf) {
+        self.load_path.borrow_mut().push(dir);
+    }
+
+    /// Resolves a symbol that isn't bound lexically, either as `alias/name`, `namespace/name`,
+    /// or as a symbol referred into `from_namespace`.
+    pub fn resolve(&self, from_namespace: &str, symbol_name: &str) -> Option<Value> {
+        let from = self.get(from_namespace)?;
+
+        if let Some((namespace_part, name)) = split_qualified(symbol_name) {
+            let target_name = from.aliases.borrow().get(namespace_part).cloned()
+                .unwrap_or_else(|| namespace_part.to_string());
+            return self.get(&target_name)?.env.lookup(name);
+        }
+
+        let (target_name, name) = from.refers.borrow().get(symbol_name).cloned()?;
+        self.get(&target_name)?.env.lookup(&name)
+    }
+
+    pub fn add_alias(&self, namespace: &str, alias: &str, target: &str) {
+        if let Some(namespace) = self.get(namespace) {
+            namespace.aliases.borrow_mut().insert(alias.to_string(), target.to_string());
+        }
+    }
+
+    pub fn add_refer(&self, namespace: &str, name: &str, target: &str) {
+        if let Some(namespace) = self.get(namespace) {
+            namespace.refers.borrow_mut().insert(name.to_string(), (target.to_string(), name.to_string()));
+        }
+    }
+
+    pub fn mark_loaded(&self, name: &str) {
+        self.loaded.borrow_mut().insert(name.to_string());
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.loaded.borrow().contains(name)
+    }
+}
+
+/// Splits `ns/name` into its parts. `/` on its own is the division function, not a qualified symbol.
+pub fn split_qualified(symbol_name: &str) -> Option<(&str, &str)> {
+    match symbol_name.split_once('/') {
+        Some((namespace_part, name)) if !namespace_part.is_empty() && !name.is_empty() => Some((namespace_part, name)),
+        _ => None,
+    }
+}
+
+/// The file that a namespace is expected to be defined in, relative to a load path directory.
+/// Like Clojure, `my-lib.util` maps to `my_lib/util.mal`.
+pub fn namespace_file(name: &str) -> String {
+    format!("{}.mal", name.replace('.', "/").replace('-', "_"))
+}
+
+/// Finds the environment for a namespace, creating it if it doesn't exist yet.
+pub fn find_or_create_namespace(env: &Env, name: &str) -> Env {
+    match env.runtime().namespaces.get(name) {
+        Some(namespace) => namespace.env.clone(),
+        None => env.create_namespace_env(name),
+    }
+}
+
+/// Loads a namespace from the load path, unless it has already been loaded.
+/// The current namespace is restored afterwards, even if loading fails.
+pub fn load_namespace(env: &Env, name: &str, reload: bool) -> Result<(), RuntimeError> {
+    let namespaces = &env.runtime().namespaces;
+    if namespaces.is_loaded(name) && !reload {
+        return Ok(());
+    }
+    if namespaces.loading.borrow().iter().any(|loading| loading == name) {
+        let mut chain = namespaces.loading.borrow().clone();
+        chain.push(name.to_string());
+        return Err(NamespaceError::CyclicDependency(chain.join(" -> ")).into());
+    }
+
+    let file = namespace_file(name);
+    let path = namespaces.load_path().into_iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+        .ok_or_else(|| NamespaceError::NotFound { name: name.to_string(), file: file.clone() })?;
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| NamespaceError::LoadFailed { name: name.to_string(), reason: e.to_string() })?;
+
+    let previous_namespace = namespaces.current();
+    let namespace_env = find_or_create_namespace(env, name);
+    namespaces.set_current(name);
+    namespaces.loading.borrow_mut().push(name.to_string());
+
+    let result = evaluate_text(&source, &namespace_env);
+
+    namespaces.loading.borrow_mut().pop();
+    namespaces.set_current(&previous_namespace);
+    result?;
+
+    namespaces.mark_loaded(name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_qualified() {
+        assert_eq!(Some(("str", "join")), split_qualified("str/join"));
+        assert_eq!(Some(("a.b", "c/d")), split_qualified("a.b/c/d"));
+        assert_eq!(None, split_qualified("/"));
+        assert_eq!(None, split_qualified("foo"));
+    }
+
+    #[test]
+    fn test_namespace_file() {
+        assert_eq!("my_lib/string_utils.mal", namespace_file("my-lib.string-utils"));
+    }
+
+    #[test]
+    fn test_resolve_alias_and_refer() {
+        let env = Env::default();
+        let lib_env = env.create_namespace_env("my.lib");
+        lib_env.insert("helper".to_string(), Value::Integer(1));
+
+        let namespaces = &env.runtime().namespaces;
+        assert_eq!(Some(Value::Integer(1)), namespaces.resolve(USER_NAMESPACE, "my.lib/helper"));
+        assert_eq!(None, namespaces.resolve(USER_NAMESPACE, "lib/helper"));
+        assert_eq!(None, namespaces.resolve(USER_NAMESPACE, "helper"));
+
+        namespaces.add_alias(USER_NAMESPACE, "lib", "my.lib");
+        namespaces.add_refer(USER_NAMESPACE, "helper", "my.lib");
+        assert_eq!(Some(Value::Integer(1)), namespaces.resolve(USER_NAMESPACE, "lib/helper"));
+        assert_eq!(Some(Value::Integer(1)), namespaces.resolve(USER_NAMESPACE, "helper"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::LinkedList;
 use std::iter::Peekable;
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::str::Chars;
 
 use itertools::Itertools;
@@ -9,7 +9,7 @@ use thiserror::Error;
 use crate::parser::ParseError::EmptyExpr;
 use crate::types::Expr;
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum ParseError {
     #[error("expected an expression but got an empty string")]
     EmptyExpr,
@@ -17,6 +17,9 @@ pub enum ParseError {
     #[error("invalid integer")]
     IntegerParseError(#[from] ParseIntError),
 
+    #[error("invalid float")]
+    FloatParseError(#[from] ParseFloatError),
+
     #[error("parentheses were unbalanced in expression")]
     UnbalancedParens,
 
@@ -37,8 +40,26 @@ pub enum ParseError {
 
     #[error("the input string was invalid: {0}")]
     InvalidExpr(String),
+
+    #[error("`{0}` must be followed by a form")]
+    MissingForm(String),
+
+    #[error("`#()` anonymous functions can't be nested")]
+    NestedFnLiteral,
+
+    #[error("the input ended part way through a form")]
+    UnexpectedEndOfInput,
+
+    #[error("a reader conditional must contain pairs of a feature keyword and a form")]
+    InvalidReaderConditional,
+
+    #[error("invalid character literal `\\{0}`")]
+    InvalidChar(String),
 }
 
+/// The feature that `#?(...)` reader conditionals select, besides `:default`
+pub const READER_FEATURE: &str = ":nlisp";
+
 pub fn parse_text_to_expressions(text: &str) -> Result<Vec<Expr>, ParseError> {
     let mut chars = text.chars().peekable();
     let mut exprs = vec![];
@@ -63,7 +84,7 @@ pub fn parse_chars(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     loop {
         match chars.peek() {
             Some(c) => match c {
-                c if c.is_digit(10) => return parse_integer(chars),
+                c if c.is_digit(10) => return parse_number(chars),
                 c if c.is_whitespace() => { chars.next(); }
                 ',' => { chars.next(); }
                 ';' => consume_comment(chars)?,
@@ -75,12 +96,16 @@ pub fn parse_chars(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
                 '}' => return Err(ParseError::UnbalancedParens),
                 '~' => return parse_tilde(chars),
                 '^' => return parse_metadata(chars),
+                '#' => if let Some(expr) = parse_dispatch(chars)? {
+                    return Ok(expr);
+                },
                 '\"' => return parse_string(chars),
+                '\\' => return parse_char(chars),
                 '-' => return match parse_symbol(chars) {
-                    // Special case to allow both negative numbers and the `-` function
-                    Ok(Expr::Symbol(c)) => match c.as_str() {
-                        "-" => Ok(Expr::Symbol(c)), // return the `-` symbol
-                        c => parse_integer(&mut c.chars().peekable()) // else, assume it is an integer
+                    // Special case to allow both negative numbers and symbols like `-` and `->Point`
+                    Ok(Expr::Symbol(c)) => match c[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                        true => parse_number(&mut c.chars().peekable()),
+                        false => Ok(Expr::Symbol(c)),
                     }
                     Err(e) => Err(e),
                     _ => panic!("expected parse_symbol to return either a symbol or an error")
@@ -163,6 +188,29 @@ fn consume_chars_between(chars: &mut Peekable<Chars>, open_delim: char, close_de
                 consume_comment(chars)?;
                 inner_text.push(' ');
             }
+            // A character literal like `\(` or `\"` doesn't open anything
+            '\\' => {
+                inner_text.push(c);
+                inner_text.extend(chars.next());
+            }
+            // Delimiters and `;` inside a string are part of the string
+            '"' => {
+                inner_text.push(c);
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            inner_text.push('\\');
+                            inner_text.extend(chars.next());
+                        }
+                        Some('"') => {
+                            inner_text.push('"');
+                            break;
+                        }
+                        Some(c) => inner_text.push(c),
+                        None => return Err(ParseError::UnbalancedString),
+                    }
+                }
+            }
             _ => inner_text.push(c)
         }
     }
@@ -208,6 +256,11 @@ fn parse_vector(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     Ok(Expr::Vector(expr_elements))
 }
 
+fn parse_set(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
+    let inner_text = consume_chars_between(chars, '{', '}')?;
+    Ok(Expr::Set(parse_text_to_expressions(inner_text.as_str())?))
+}
+
 fn parse_hashmap(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     let inner_text = consume_chars_between(chars, '{', '}')?;
     let inner_expressions = parse_text_to_expressions(inner_text.as_str())?;
@@ -247,25 +300,34 @@ fn parse_symbol(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     // check if this corresponds to a special identifier
     match symbol_str.as_str() {
         "nil" => Ok(Expr::Nil),
-        "true" | "#t" => Ok(Expr::Boolean(true)),
-        "false" | "#f" => Ok(Expr::Boolean(false)),
+        "true" => Ok(Expr::Boolean(true)),
+        "false" => Ok(Expr::Boolean(false)),
         _ => Ok(Expr::Symbol(symbol_str))
     }
 }
 
-fn parse_integer(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
-    let mut integer_str = String::new();
+/// Parses an integer, or a float like `1.5` or `2e-3`.
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
+    let mut number_str = String::new();
 
     // check for negative numbers
     if let Some('-') = chars.peek() {
         chars.next();
-        integer_str.push('-');
+        number_str.push('-');
     }
 
     while let Some(c) = chars.peek() {
         match c {
             c if c.is_digit(10) => {
-                integer_str.push(*c);
+                number_str.push(*c);
+                chars.next();
+            }
+            '.' | 'e' | 'E' => {
+                number_str.push(*c);
+                chars.next();
+            }
+            '-' | '+' if number_str.ends_with(['e', 'E']) => {
+                number_str.push(*c);
                 chars.next();
             }
             c if c.is_whitespace() => {
@@ -278,12 +340,67 @@ fn parse_integer(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
         }
     }
 
-    match integer_str.parse::<i64>() {
+    if number_str.contains(['.', 'e', 'E']) {
+        return Ok(Expr::Float(number_str.parse::<f64>()?));
+    }
+    match number_str.parse::<i64>() {
         Ok(val) => Ok(Expr::Integer(val)),
         Err(e) => Err(ParseError::IntegerParseError(e))
     }
 }
 
+/// Parses a character literal like `\a`, `\newline` or `\u00e9`.
+fn parse_char(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
+    match chars.next() {
+        Some('\\') => {}
+        _ => return Err(ParseError::InvalidExpr("expected a character to begin with a backslash".to_string()))
+    }
+
+    // The first character is always part of the literal, so that `\(` and `\;` work
+    let mut name = match chars.next() {
+        Some(c) => c.to_string(),
+        None => return Err(ParseError::UnexpectedEndOfInput),
+    };
+    while let Some(c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => break,
+            ',' | ';' | '(' | ')' | '[' | ']' | '{' | '}' | '"' => break,
+            c => {
+                name.push(*c);
+                chars.next();
+            }
+        }
+    }
+
+    match char_from_name(&name) {
+        Some(c) => Ok(Expr::Char(c)),
+        None => Err(ParseError::InvalidChar(name)),
+    }
+}
+
+/// The character for the text after the backslash in a character literal: either the character
+/// itself, a name like `newline`, or a code point like `u00e9`.
+pub fn char_from_name(name: &str) -> Option<char> {
+    let mut name_chars = name.chars();
+    match (name_chars.next(), name_chars.next()) {
+        (Some(c), None) => return Some(c),
+        (None, _) => return None,
+        _ => {}
+    }
+    match name {
+        "newline" => Some('\n'),
+        "space" => Some(' '),
+        "tab" => Some('\t'),
+        "return" => Some('\r'),
+        "backspace" => Some('\u{8}'),
+        "formfeed" => Some('\u{c}'),
+        name => name.strip_prefix('u')
+            .filter(|digits| digits.len() == 4)
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .and_then(char::from_u32),
+    }
+}
+
 fn parse_string(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     // 1. make sure the string begins with a double-quote
     match chars.next() {
@@ -348,6 +465,133 @@ fn parse_metadata(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
     ])))
 }
 
+/// Parses a form starting with `#`. Returns `None` when the form reads as nothing, as with `#_`
+/// or a reader conditional with no matching feature.
+fn parse_dispatch(chars: &mut Peekable<Chars>) -> Result<Option<Expr>, ParseError> {
+    match chars.next() {
+        Some('#') => {}
+        _ => return Err(ParseError::InvalidExpr("expected dispatch expression to begin with a `#`".to_string()))
+    }
+
+    let parse_form = |chars: &mut Peekable<Chars>, prefix: &str| match parse_chars(chars) {
+        Err(ParseError::EmptyExpr) => Err(ParseError::MissingForm(prefix.to_string())),
+        result => result,
+    };
+
+    match chars.peek() {
+        Some('_') => {
+            chars.next();
+            parse_form(chars, "#_")?;
+            Ok(None)
+        }
+        Some('(') => parse_fn_literal(chars).map(Some),
+        Some('{') => parse_set(chars).map(Some),
+        // `##Inf`, `##-Inf` and `##NaN`, as floats are printed
+        Some('#') => {
+            chars.next();
+            match parse_symbol(chars)? {
+                Expr::Symbol(name) if name == "Inf" => Ok(Some(Expr::Float(f64::INFINITY))),
+                Expr::Symbol(name) if name == "-Inf" => Ok(Some(Expr::Float(f64::NEG_INFINITY))),
+                Expr::Symbol(name) if name == "NaN" => Ok(Some(Expr::Float(f64::NAN))),
+                _ => Err(ParseError::InvalidExpr("expected `Inf`, `-Inf` or `NaN` after `##`".to_string())),
+            }
+        }
+        Some('?') => {
+            chars.next();
+            match parse_form(chars, "#?")? {
+                Expr::List(clauses) => select_reader_conditional(clauses),
+                _ => Err(ParseError::InvalidReaderConditional),
+            }
+        }
+        _ => match parse_symbol(chars)? {
+            // `#t` and `#f` are accepted as booleans
+            Expr::Symbol(tag) if tag == "t" => Ok(Some(Expr::Boolean(true))),
+            Expr::Symbol(tag) if tag == "f" => Ok(Some(Expr::Boolean(false))),
+            Expr::Symbol(tag) if !tag.is_empty() => {
+                let form = parse_form(chars, &format!("#{}", tag))?;
+                Ok(Some(Expr::Tagged(tag, Box::new(form))))
+            }
+            _ => Err(ParseError::InvalidExpr("expected a tag, `_`, `?`, `(`, `{` or `#` after `#`".to_string())),
+        },
+    }
+}
+
+/// Picks the form for the first feature in `#?(:feature form ...)` that is either `:nlisp` or `:default`.
+fn select_reader_conditional(clauses: LinkedList<Expr>) -> Result<Option<Expr>, ParseError> {
+    if !clauses.len().is_multiple_of(2) {
+        return Err(ParseError::InvalidReaderConditional);
+    }
+    for (feature, form) in clauses.into_iter().tuples() {
+        match feature {
+            Expr::Keyword(feature) if feature == READER_FEATURE || feature == ":default" => return Ok(Some(form)),
+            Expr::Keyword(_) => {}
+            _ => return Err(ParseError::InvalidReaderConditional),
+        }
+    }
+    Ok(None)
+}
+
+/// Parses `#(...)` into a `fn*`, where `%` or `%1`, `%2`, ... are the positional parameters and
+/// `%&` is the rest parameter. For example, `#(+ % %2)` becomes `(fn* [%1 %2] (+ %1 %2))`.
+fn parse_fn_literal(chars: &mut Peekable<Chars>) -> Result<Expr, ParseError> {
+    let body = parse_list(chars)?;
+
+    let mut max_param = 0;
+    let mut has_rest = false;
+    let body = replace_fn_literal_params(body, &mut max_param, &mut has_rest)?;
+
+    let mut params = (1..=max_param).map(|i| Expr::Symbol(format!("%{}", i))).collect_vec();
+    if has_rest {
+        params.push(Expr::Symbol("&".to_string()));
+        params.push(Expr::Symbol("%&".to_string()));
+    }
+    Ok(Expr::List(LinkedList::from([Expr::Symbol("fn*".to_string()), Expr::Vector(params), body])))
+}
+
+fn replace_fn_literal_params(expr: Expr, max_param: &mut usize, has_rest: &mut bool) -> Result<Expr, ParseError> {
+    let mut replace = |expr| replace_fn_literal_params(expr, max_param, has_rest);
+    match expr {
+        Expr::Symbol(s) if s == "%" => {
+            *max_param = (*max_param).max(1);
+            Ok(Expr::Symbol("%1".to_string()))
+        }
+        Expr::Symbol(s) if s == "%&" => {
+            *has_rest = true;
+            Ok(Expr::Symbol(s))
+        }
+        Expr::Symbol(s) => match s.strip_prefix('%').map(str::parse::<usize>) {
+            Some(Ok(n)) if n > 0 => {
+                *max_param = (*max_param).max(n);
+                Ok(Expr::Symbol(s))
+            }
+            _ => Ok(Expr::Symbol(s)),
+        },
+        // An inner `#()` has already been turned into a `fn*` whose parameters start with `%`
+        Expr::List(elems) if is_fn_literal(&elems) => Err(ParseError::NestedFnLiteral),
+        Expr::List(elems) => Ok(Expr::List(elems.into_iter().map(replace).collect::<Result<_, _>>()?)),
+        Expr::Vector(elems) => Ok(Expr::Vector(elems.into_iter().map(replace).collect::<Result<_, _>>()?)),
+        Expr::Set(elems) => Ok(Expr::Set(elems.into_iter().map(replace).collect::<Result<_, _>>()?)),
+        Expr::HashMap(pairs) => Ok(Expr::HashMap(pairs.into_iter()
+            .map(|(key, value)| Ok((replace(key)?, replace(value)?)))
+            .collect::<Result<_, ParseError>>()?)),
+        Expr::Quote(inner) => Ok(Expr::Quote(Box::new(replace(*inner)?))),
+        Expr::Quasiquote(inner) => Ok(Expr::Quasiquote(Box::new(replace(*inner)?))),
+        Expr::Unquote(inner) => Ok(Expr::Unquote(Box::new(replace(*inner)?))),
+        Expr::SpliceUnquote(inner) => Ok(Expr::SpliceUnquote(Box::new(replace(*inner)?))),
+        Expr::Tagged(tag, inner) => Ok(Expr::Tagged(tag, Box::new(replace(*inner)?))),
+        expr => Ok(expr),
+    }
+}
+
+fn is_fn_literal(elems: &LinkedList<Expr>) -> bool {
+    let mut elems_iter = elems.iter();
+    match (elems_iter.next(), elems_iter.next()) {
+        (Some(Expr::Symbol(head)), Some(Expr::Vector(params))) if head == "fn*" => params.iter()
+            .any(|param| matches!(param, Expr::Symbol(param) if param.starts_with('%'))),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -363,6 +607,7 @@ mod tests {
         assert_eq!(Ok(Expr::Symbol("foo-bar".to_string())), parse_text_to_expression(" foo-bar   "));
         assert_eq!(Ok(Expr::Symbol("hi".to_string())), parse_text_to_expression("    hi"));
         assert_eq!(Ok(Expr::Symbol("-".to_string())), parse_text_to_expression("-"));
+        assert_eq!(Ok(Expr::Symbol("->Point".to_string())), parse_text_to_expression("->Point"));
     }
 
     #[test]
@@ -379,6 +624,36 @@ mod tests {
         assert_eq!(Ok(Expr::Integer(-4)), parse_text_to_expression("      -4    "));
     }
 
+    #[test]
+    fn test_parse_float() {
+        assert_eq!(Ok(Expr::Float(1.5)), parse_text_to_expression("1.5"));
+        assert_eq!(Ok(Expr::Float(-0.25)), parse_text_to_expression(" -0.25 "));
+        assert_eq!(Ok(Expr::Float(2e-3)), parse_text_to_expression("2e-3"));
+        assert_eq!(Ok(Expr::Float(f64::INFINITY)), parse_text_to_expression("##Inf"));
+        assert!(matches!(parse_text_to_expression("1.2.3"), Err(ParseError::FloatParseError(_))));
+    }
+
+    #[test]
+    fn test_parse_char() {
+        assert_eq!(Ok(Expr::Char('a')), parse_text_to_expression("\\a"));
+        assert_eq!(Ok(Expr::Char('\n')), parse_text_to_expression("\\newline"));
+        assert_eq!(Ok(Expr::Char('é')), parse_text_to_expression("\\u00e9"));
+        assert_eq!(
+            Ok(Expr::List(LinkedList::from([Expr::Char('('), Expr::Char('"'), Expr::Char(')')]))),
+            parse_text_to_expression("(\\( \\\" \\))"),
+        );
+        assert_eq!(Err(ParseError::InvalidChar("bad".to_string())), parse_text_to_expression("\\bad"));
+    }
+
+    #[test]
+    fn test_parse_set() {
+        assert_eq!(Ok(Expr::Set(vec![])), parse_text_to_expression("#{}"));
+        assert_eq!(
+            Ok(Expr::Set(vec![Expr::Integer(1), Expr::Keyword(":a".to_string())])),
+            parse_text_to_expression("#{1 :a}"),
+        );
+    }
+
     #[test]
     fn test_parse_integer_invalid() {
         assert_eq!(Err(ParseError::IntegerContainsNonNumericChar('a')), parse_text_to_expression("1a"));
@@ -506,4 +781,53 @@ mod tests {
     fn test_parse_newlines() {
         assert_eq!(Ok(Expr::Integer(1)), parse_text_to_expression("\n1\n\n"));
     }
+
+    fn sym(s: &str) -> Expr {
+        Expr::Symbol(s.to_string())
+    }
+
+    #[test]
+    fn test_parse_delimiters_in_strings() {
+        assert_eq!(
+            Ok(Expr::List(LinkedList::from([sym("str"), Expr::String("(a] ; \\\"b".to_string())]))),
+            parse_text_to_expression("(str \"(a] ; \\\\\\\"b\")")
+        );
+    }
+
+    #[test]
+    fn test_parse_discard() {
+        assert_eq!(Ok(vec![Expr::Integer(1), Expr::Integer(3)]), parse_text_to_expressions("1 #_2 3"));
+        assert_eq!(Ok(Expr::List(LinkedList::from([sym("a"), sym("c")]))), parse_text_to_expression("(a #_(b [1]) c)"));
+        assert_eq!(Ok(Expr::Vector(vec![])), parse_text_to_expression("[#_ #_ 1 2]"));
+        assert_eq!(Err(ParseError::MissingForm("#_".to_string())), parse_text_to_expression("#_"));
+    }
+
+    #[test]
+    fn test_parse_fn_literal() {
+        assert_eq!(parse_text_to_expression("(fn* [%1 %2] (+ %1 %2))"), parse_text_to_expression("#(+ % %2)"));
+        assert_eq!(parse_text_to_expression("(fn* [%1 & %&] (apply f %1 %&))"), parse_text_to_expression("#(apply f % %&)"));
+        assert_eq!(parse_text_to_expression("(fn* [] (rand))"), parse_text_to_expression("#(rand)"));
+        assert_eq!(Err(ParseError::NestedFnLiteral), parse_text_to_expression("#(map #(+ 1 %) %)"));
+    }
+
+    #[test]
+    fn test_parse_tagged() {
+        assert_eq!(
+            Ok(Expr::Tagged("inst".to_string(), Box::new(Expr::String("2024-01-31".to_string())))),
+            parse_text_to_expression("#inst \"2024-01-31\"")
+        );
+        assert_eq!(
+            Ok(Expr::Tagged("my/point".to_string(), Box::new(Expr::Vector(vec![Expr::Integer(1), Expr::Integer(2)])))),
+            parse_text_to_expression("#my/point [1 2]")
+        );
+        assert_eq!(Err(ParseError::MissingForm("#inst".to_string())), parse_text_to_expression("#inst "));
+    }
+
+    #[test]
+    fn test_parse_reader_conditional() {
+        assert_eq!(Ok(Expr::Integer(1)), parse_text_to_expression("#?(:rust2 0 :nlisp 1 :default 2)"));
+        assert_eq!(Ok(Expr::Integer(2)), parse_text_to_expression("#?(:rust2 0 :default 2)"));
+        assert_eq!(Ok(vec![Expr::Integer(3)]), parse_text_to_expressions("#?(:rust2 0) 3"));
+        assert_eq!(Err(ParseError::InvalidReaderConditional), parse_text_to_expression("#?(:nlisp)"));
+    }
 }
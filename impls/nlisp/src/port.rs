@@ -0,0 +1,86 @@
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+
+use crate::env::Env;
+use crate::evaluator::RuntimeError;
+use crate::sync::{Lock, Shared, ThreadSafe};
+use crate::types::Value;
+
+/// The dynamic var that printing builtins like `prn` write to
+pub const OUT_VAR: &str = "*out*";
+/// The dynamic var for error output, which is stderr unless an embedder changes it
+pub const ERR_VAR: &str = "*err*";
+
+/// Anything a port can write to. With the `sync` feature it must also be `Send + Sync`.
+pub trait PortWriter: Write + ThreadSafe {}
+
+impl<W: Write + ThreadSafe> PortWriter for W {}
+
+/// A destination for printed output. Ports are shared, so a port that's been rebound with
+/// `binding` writes to the same place as the var it was taken from.
+#[derive(Clone)]
+pub struct OutputPort {
+    pub name: String,
+    writer: Shared<Lock<Box<dyn PortWriter>>>,
+}
+
+impl OutputPort {
+    pub fn new(name: impl Into<String>, writer: impl PortWriter + 'static) -> Self {
+        OutputPort { name: name.into(), writer: Shared::new(Lock::new(Box::new(writer))) }
+    }
+
+    pub fn write_str(&self, s: &str) -> Result<(), RuntimeError> {
+        self.writer.borrow_mut().write_all(s.as_bytes()).map_err(|e| self.error(e))
+    }
+
+    pub fn flush(&self) -> Result<(), RuntimeError> {
+        self.writer.borrow_mut().flush().map_err(|e| self.error(e))
+    }
+
+    fn error(&self, error: std::io::Error) -> RuntimeError {
+        RuntimeError::Io { action: "write to", path: self.name.clone(), message: error.to_string() }
+    }
+}
+
+impl PartialEq for OutputPort {
+    fn eq(&self, other: &Self) -> bool {
+        Shared::ptr_eq(&self.writer, &other.writer)
+    }
+}
+
+impl Debug for OutputPort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputPort").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+/// A writer that appends to a buffer which can still be read after the writer is handed to a port,
+/// as `with-out-str` does.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Shared<Lock<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The port currently bound to `var`, falling back to stdout or stderr in environments without
+/// the core io builtins.
+pub fn current_port(env: &Env, var: &str) -> Result<OutputPort, RuntimeError> {
+    let value = match env.lookup(var) {
+        Some(Value::DynamicVar(dynamic_var)) => dynamic_var.get(),
+        Some(value) => value,
+        None if var == ERR_VAR => return Ok(OutputPort::new(ERR_VAR, std::io::stderr())),
+        None => return Ok(OutputPort::new(OUT_VAR, std::io::stdout())),
+    };
+    match value {
+        Value::OutputPort(port) => Ok(port),
+        _ => Err(RuntimeError::IncorrectType(crate::evaluator::TypeError::Misc)),
+    }
+}
@@ -0,0 +1,289 @@
+use crate::printer::{Printable, PrintOptions};
+use crate::sync;
+use crate::types::Value;
+
+/// How the elements of a collection are arranged when it doesn't fit on one line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    /// Every element on its own line, aligned with the first
+    Aligned,
+    /// Two elements per line, for map entries and `let*` bindings
+    Pairs,
+    /// `(f a` followed by the remaining arguments aligned with `a`
+    Call,
+    /// The head and the given number of following elements on the first line, with the body
+    /// indented by two spaces, as in `(let* [bindings]` or `(fn* [params]`
+    Body(usize),
+}
+
+/// A value that has been broken down into the pieces that the layout works with. Each
+/// collection caches its width when printed on one line, so that deciding whether it fits
+/// doesn't need to print it.
+#[derive(Debug)]
+enum Doc {
+    Text(String),
+    Group {
+        open: &'static str,
+        close: &'static str,
+        items: Vec<Doc>,
+        layout: Layout,
+        width: usize,
+    },
+}
+
+impl Doc {
+    fn group(open: &'static str, close: &'static str, items: Vec<Doc>, layout: Layout) -> Doc {
+        let separators = items.len().saturating_sub(1);
+        let width = open.len() + close.len() + separators + items.iter().map(Doc::width).sum::<usize>();
+        Doc::Group { open, close, items, layout, width }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Doc::Text(text) => text.chars().count(),
+            Doc::Group { width, .. } => *width,
+        }
+    }
+}
+
+/// Prints `value` readably, breaking collections over several lines so that they fit within
+/// `options.width` where possible.
+pub fn pprint_value(value: &Value, options: &PrintOptions) -> String {
+    let doc = to_doc(value, options, 0, Layout::Aligned);
+    let mut output = String::new();
+    render(&doc, 0, options.width, &mut output);
+    output
+}
+
+/// Lists that start with one of these symbols indent their body rather than aligning it.
+fn body_layout(head: &str, args: &sync::List<Value>) -> Option<Layout> {
+    match head {
+        "fn*" => match args.iter().nth(1) {
+            // `(fn* name [params] ...)`
+            Some(Value::Symbol(_)) => Some(Layout::Body(2)),
+            _ => Some(Layout::Body(1)),
+        },
+        "let*" | "loop" | "binding" | "def!" | "defmacro!" | "def-dynamic" | "ns" | "when" | "when-not" => Some(Layout::Body(1)),
+        "do" | "try*" => Some(Layout::Body(0)),
+        _ => None,
+    }
+}
+
+fn binds_pairs(head: &str) -> bool {
+    matches!(head, "let*" | "loop" | "binding")
+}
+
+fn to_doc(value: &Value, options: &PrintOptions, depth: usize, layout_hint: Layout) -> Doc {
+    let is_collection = matches!(value, Value::List(_) | Value::Vector(_) | Value::HashMap(_) | Value::Set(_));
+    if is_collection && options.level.is_some_and(|level| depth >= level) {
+        return Doc::Text("#".to_string());
+    }
+
+    match value {
+        Value::List(elems) => {
+            let (layout, pair_bindings) = match elems.first() {
+                Some(Value::Symbol(head)) => (body_layout(head, elems).unwrap_or(Layout::Call), binds_pairs(head)),
+                _ => (Layout::Aligned, false),
+            };
+            let items = truncate(elems.iter(), options, |i, elem| {
+                let hint = if pair_bindings && i == 1 { Layout::Pairs } else { Layout::Aligned };
+                to_doc(elem, options, depth + 1, hint)
+            });
+            Doc::group("(", ")", items, layout)
+        }
+        Value::Vector(elems) => {
+            let items = truncate(elems.iter(), options, |_, elem| to_doc(elem, options, depth + 1, Layout::Aligned));
+            Doc::group("[", "]", items, layout_hint)
+        }
+        Value::HashMap(pairs) => {
+            let mut items = vec![];
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if options.length.is_some_and(|length| i >= length) {
+                    items.push(Doc::Text("...".to_string()));
+                    break;
+                }
+                items.push(Doc::Text(key.print_value(true)));
+                items.push(to_doc(value, options, depth + 1, Layout::Aligned));
+            }
+            Doc::group("{", "}", items, Layout::Pairs)
+        }
+        Value::Set(elems) => {
+            let elems = elems.iter().map(|elem| Value::from(elem.clone())).collect::<Vec<_>>();
+            let items = truncate(elems.iter(), options, |_, elem| to_doc(elem, options, depth + 1, Layout::Aligned));
+            Doc::group("#{", "}", items, Layout::Aligned)
+        }
+        value => Doc::Text(value.print_value_with_options(true, options)),
+    }
+}
+
+fn truncate<'a, T, F>(elems: T, options: &PrintOptions, mut to_doc: F) -> Vec<Doc>
+    where T: Iterator<Item=&'a Value>,
+          F: FnMut(usize, &'a Value) -> Doc
+{
+    let mut items = vec![];
+    for (i, elem) in elems.enumerate() {
+        if options.length.is_some_and(|length| i >= length) {
+            items.push(Doc::Text("...".to_string()));
+            break;
+        }
+        items.push(to_doc(i, elem));
+    }
+    items
+}
+
+fn render_flat(doc: &Doc, output: &mut String) {
+    match doc {
+        Doc::Text(text) => output.push_str(text),
+        Doc::Group { open, close, items, .. } => {
+            output.push_str(open);
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push(' ');
+                }
+                render_flat(item, output);
+            }
+            output.push_str(close);
+        }
+    }
+}
+
+fn current_column(output: &str) -> usize {
+    let line_start = output.rfind('\n').map_or(0, |newline| newline + 1);
+    output[line_start..].chars().count()
+}
+
+fn new_line(column: usize, output: &mut String) {
+    output.push('\n');
+    output.extend(std::iter::repeat_n(' ', column));
+}
+
+/// Renders `doc` starting at `column`, which is where the output currently ends.
+fn render(doc: &Doc, column: usize, width: usize, output: &mut String) {
+    let (open, close, items, layout) = match doc {
+        Doc::Group { open, close, items, layout, .. } if column + doc.width() > width && !items.is_empty() => {
+            (open, close, items, *layout)
+        }
+        doc => return render_flat(doc, output),
+    };
+
+    output.push_str(open);
+    let inner_column = column + open.len();
+    match layout {
+        Layout::Aligned => render_lines(items.iter().map(std::slice::from_ref), inner_column, width, output),
+        Layout::Pairs => render_lines(items.chunks(2), inner_column, width, output),
+        Layout::Call => {
+            let head = &items[0];
+            let args_column = inner_column + head.width() + 1;
+            // Aligning with the first argument is only worthwhile if it leaves room for the arguments
+            if items.len() > 1 && args_column < width / 2 {
+                render_flat(head, output);
+                output.push(' ');
+                render_lines(items[1..].iter().map(std::slice::from_ref), args_column, width, output);
+            } else {
+                render_lines(items.iter().map(std::slice::from_ref), inner_column, width, output);
+            }
+        }
+        Layout::Body(head_args) => {
+            let head_len = (head_args + 1).min(items.len());
+            render_line(&items[..head_len], inner_column, width, output);
+            for item in &items[head_len..] {
+                new_line(column + 2, output);
+                render(item, column + 2, width, output);
+            }
+        }
+    }
+    output.push_str(close);
+}
+
+fn render_lines<'a, T>(lines: T, column: usize, width: usize, output: &mut String)
+    where T: Iterator<Item=&'a [Doc]>
+{
+    for (i, line) in lines.enumerate() {
+        if i > 0 {
+            new_line(column, output);
+        }
+        render_line(line, column, width, output);
+    }
+}
+
+/// Renders several items on the same line, breaking any that are too wide.
+fn render_line(items: &[Doc], mut column: usize, width: usize, output: &mut String) {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            output.push(' ');
+            column += 1;
+        }
+        render(item, column, width, output);
+        // A broken item leaves the output on a later line, so carry on from wherever it ended
+        column = current_column(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_text_to_expression;
+    use crate::evaluator::quote;
+
+    use super::*;
+
+    fn read(src: &str) -> Value {
+        quote(parse_text_to_expression(src).expect("src to parse")).expect("src to quote")
+    }
+
+    fn pprint(src: &str, width: usize) -> String {
+        pprint_value(&read(src), &PrintOptions { width, ..PrintOptions::default() })
+    }
+
+    #[test]
+    fn test_fits_on_one_line() {
+        assert_eq!("[1 2 [3 4] {:a 1}]", pprint("[1 2 [3 4] {:a 1}]", 80));
+        assert_eq!("\"a\\nb\"", pprint("\"a\\nb\"", 1));
+    }
+
+    #[test]
+    fn test_aligned() {
+        assert_eq!("[1\n 2\n [3 4]]", pprint("[1 2 [3 4]]", 8));
+        assert_eq!("[[1\n  2]\n 3]", pprint("[[1 2] 3]", 4));
+    }
+
+    #[test]
+    fn test_map_entries() {
+        assert_eq!("{:a [1 2 3]}", pprint("{:a [1 2 3]}", 80));
+        assert_eq!("{:a [1\n     2\n     3]}", pprint("{:a [1 2 3]}", 8));
+    }
+
+    #[test]
+    fn test_call_alignment() {
+        assert_eq!("(foo 1\n     2\n     300)", pprint("(foo 1 2 300)", 12));
+    }
+
+    #[test]
+    fn test_body_indentation() {
+        assert_eq!(
+            "(let* [a 1\n       b 2]\n  (+ a b))",
+            pprint("(let* [a 1 b 2] (+ a b))", 12)
+        );
+        assert_eq!(
+            "(fn* add [a b]\n  (prn a)\n  (+ a b))",
+            pprint("(fn* add [a b] (prn a) (+ a b))", 20)
+        );
+    }
+
+    #[test]
+    fn test_truncation() {
+        let options = PrintOptions { length: Some(2), level: Some(2), ..PrintOptions::default() };
+        assert_eq!("[1 2 ...]", pprint_value(&read("[1 2 3 4]"), &options));
+        assert_eq!("[1 [2 #]]", pprint_value(&read("[1 [2 [3]]]"), &options));
+        assert_eq!("(1 2)", pprint_value(&read("(1 2)"), &options));
+    }
+
+    #[test]
+    fn test_large_map() {
+        let map = Value::HashMap((0..200)
+            .map(|i| (crate::types::HashableValue::Integer(i), Value::Vector((0..i).map(Value::Integer).collect())))
+            .collect());
+        let output = pprint_value(&map, &PrintOptions::default());
+        assert!(output.lines().count() > 200);
+        assert!(output.lines().all(|line| line.chars().count() <= 80));
+    }
+}
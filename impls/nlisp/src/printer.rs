@@ -1,6 +1,9 @@
 use std::fmt::{Display, Formatter};
 
-use crate::types::{Expr, HashableValue, Value};
+use crate::atom::Atom;
+use crate::Env;
+use crate::sync::Shared;
+use crate::types::{ClosureArity, Expr, FunctionBody, HashableValue, TransientCollection, Value};
 
 pub fn write_delimiter_separated_elems<T>(elems: T, delimiter: &str) -> String
     where T: IntoIterator,
@@ -16,19 +19,64 @@ pub fn write_delimiter_separated_elems<T>(elems: T, delimiter: &str) -> String
     result
 }
 
-pub fn write_delimiter_separated_printables<T>(elems: T, delimiter: &str, print_readably: bool) -> String
-    where T: IntoIterator,
-          T::Item: Printable
-{
-    let mut result = String::new();
+pub const PRINT_LENGTH_VAR: &str = "*print-length*";
+pub const PRINT_LEVEL_VAR: &str = "*print-level*";
+pub const PRINT_RIGHT_MARGIN_VAR: &str = "*print-right-margin*";
+pub const PRINT_ATOM_DEPTH_VAR: &str = "*print-atom-depth*";
+pub const PRINT_ATOMS_OPAQUE_VAR: &str = "*print-atoms-opaque*";
+pub const DEFAULT_RIGHT_MARGIN: usize = 80;
+pub const DEFAULT_ATOM_DEPTH: usize = 16;
 
-    for (i, elem) in elems.into_iter().enumerate() {
-        if i > 0 {
-            result.push_str(delimiter);
+/// Controls how values are printed. The width and truncation settings only apply to `pprint`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintOptions {
+    /// The column that lines should try not to go past
+    pub width: usize,
+    /// How many elements of each collection to print before eliding the rest with `...`
+    pub length: Option<usize>,
+    /// How deeply collections can nest before being printed as `#`
+    pub level: Option<usize>,
+    /// How many atoms deep to print the contents of nested atoms, past which they are printed
+    /// as `#<atom 0x..>`
+    pub atom_depth: Option<usize>,
+    /// Print every atom as `#<atom 0x..>` without its contents
+    pub opaque_atoms: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            width: DEFAULT_RIGHT_MARGIN,
+            length: None,
+            level: None,
+            atom_depth: Some(DEFAULT_ATOM_DEPTH),
+            opaque_atoms: false,
+        }
+    }
+}
+
+impl PrintOptions {
+    /// Reads the options from the current values of `*print-right-margin*`, `*print-length*`,
+    /// `*print-level*`, `*print-atom-depth*` and `*print-atoms-opaque*`. For the numeric options,
+    /// anything other than a non-negative integer means no limit.
+    pub fn from_env(env: &Env) -> Self {
+        let lookup = |name: &str| match env.lookup(name) {
+            Some(Value::DynamicVar(var)) => var.get(),
+            _ => Value::Nil,
+        };
+        let limit = |name: &str| match lookup(name) {
+            Value::Integer(n) if n >= 0 => Some(n as usize),
+            _ => None,
+        };
+
+        PrintOptions {
+            width: limit(PRINT_RIGHT_MARGIN_VAR).unwrap_or(DEFAULT_RIGHT_MARGIN),
+            length: limit(PRINT_LENGTH_VAR),
+            level: limit(PRINT_LEVEL_VAR),
+            atom_depth: limit(PRINT_ATOM_DEPTH_VAR),
+            opaque_atoms: lookup(PRINT_ATOMS_OPAQUE_VAR).is_truthy(),
         }
-        result.push_str(&elem.print_value(print_readably));
     }
-    result
 }
 
 pub trait Printable {
@@ -41,6 +89,12 @@ impl Display for Expr {
             Expr::Integer(val) => {
                 write!(f, "{}", val)
             }
+            Expr::Float(val) => {
+                write!(f, "{}", format_float(*val))
+            }
+            Expr::Char(c) => {
+                write!(f, "{}", format_char_literal(*c))
+            }
             Expr::Symbol(val) | Expr::Keyword(val) => {
                 write!(f, "{}", val)
             }
@@ -63,6 +117,9 @@ impl Display for Expr {
             Expr::Vector(elements) => {
                 write!(f, "[{}]", write_delimiter_separated_elems(elements, " "))
             }
+            Expr::Set(elements) => {
+                write!(f, "#{{{}}}", write_delimiter_separated_elems(elements, " "))
+            }
             Expr::HashMap(pairs) => {
                 let inner_str = write_delimiter_separated_elems(pairs.iter().map(|(key, value)|
                     format!("{} {}", key, value)
@@ -81,23 +138,70 @@ impl Display for Expr {
             Expr::SpliceUnquote(expr) => {
                 write!(f, "(splice-unquote {})", expr)
             }
+            Expr::Tagged(tag, expr) => {
+                write!(f, "#{} {}", tag, expr)
+            }
         }
     }
 }
 
 impl Printable for Value {
     fn print_value(&self, readable: bool) -> String {
+        self.print_value_with_options(readable, &PrintOptions::default())
+    }
+}
+
+impl Value {
+    pub fn print_value_with_options(&self, readable: bool, options: &PrintOptions) -> String {
+        let mut result = String::new();
+        self.write_value(readable, options, &mut Vec::new(), &mut result);
+        result
+    }
+
+    /// `atom_path` holds the atoms currently being printed, from the outermost inwards, so that an
+    /// atom reached again through its own contents is printed as a back-reference.
+    fn write_value(&self, readable: bool, options: &PrintOptions, atom_path: &mut Vec<*const Atom>, result: &mut String) {
+        let write_elems = |elems: &mut dyn Iterator<Item=&Value>, atom_path: &mut Vec<_>, result: &mut String| {
+            for (i, elem) in elems.enumerate() {
+                if i > 0 {
+                    result.push(' ');
+                }
+                elem.write_value(readable, options, atom_path, result);
+            }
+        };
+        let write_pairs = |pairs: &mut dyn Iterator<Item=(&HashableValue, &Value)>, atom_path: &mut Vec<_>, result: &mut String| {
+            result.push('{');
+            for (i, (key, value)) in pairs.enumerate() {
+                if i > 0 {
+                    result.push(' ');
+                }
+                result.push_str(&key.print_value(readable));
+                result.push(' ');
+                value.write_value(readable, options, atom_path, result);
+            }
+            result.push('}');
+        };
+
         match self {
             Value::Integer(val) => {
-                format!("{}", val)
+                result.push_str(&format!("{}", val));
+            }
+            Value::Float(val) => {
+                result.push_str(&format_float(*val));
+            }
+            Value::Char(c) => {
+                if readable {
+                    result.push_str(&format_char_literal(*c));
+                } else {
+                    result.push(*c);
+                }
             }
             Value::Symbol(val) | Value::Keyword(val) => {
-                format!("{}", val)
+                result.push_str(val);
             }
             Value::String(val) => {
                 if readable {
-                    let mut result = String::new();
-
+                    result.push('"');
                     for c in val.chars() {
                         match c {
                             '\"' => result.push_str("\\\""),
@@ -106,39 +210,113 @@ impl Printable for Value {
                             _ => result.push(c),
                         }
                     }
-                    format!("\"{}\"", result)
+                    result.push('"');
                 } else {
-                    val.to_string()
+                    result.push_str(val);
                 }
             }
             Value::Nil => {
-                "nil".to_string()
+                result.push_str("nil");
             }
             Value::Boolean(b) => {
-                if *b {
-                    "true".to_string()
-                } else {
-                    "false".to_string()
-                }
+                result.push_str(if *b { "true" } else { "false" });
             }
             Value::List(elements) => {
-                format!("({})", write_delimiter_separated_printables(elements.iter().cloned(), " ", readable))
+                result.push('(');
+                write_elems(&mut elements.iter(), atom_path, result);
+                result.push(')');
             }
             Value::Vector(elements) => {
-                format!("[{}]", write_delimiter_separated_printables(elements.iter().cloned(), " ", readable))
+                result.push('[');
+                write_elems(&mut elements.iter(), atom_path, result);
+                result.push(']');
             }
             Value::HashMap(pairs) => {
-                let elements = pairs.iter().map(|(key, value)|
-                    format!("{} {}", key.print_value(readable), value.print_value(readable))
-                );
-
-                format!("{{{}}}", write_delimiter_separated_elems(elements, " "))
+                write_pairs(&mut pairs.iter(), atom_path, result);
             }
-            Value::Function(_function_body) => {
-                "(fn ...)".to_string()
+            Value::Record(record) => {
+                result.push('#');
+                result.push_str(&record.record_type.name);
+                write_pairs(&mut record.entries(), atom_path, result);
+            }
+            Value::Set(elems) => {
+                result.push_str("#{");
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        result.push(' ');
+                    }
+                    result.push_str(&elem.print_value(readable));
+                }
+                result.push('}');
+            }
+            Value::Function(FunctionBody::BuiltinValues(name, _) | FunctionBody::BuiltinExpressions(name, _)) => {
+                result.push_str(&format!("#<builtin {}>", name));
+            }
+            Value::Function(FunctionBody::Native(native)) => {
+                result.push_str(&format!("#<native {}>", native.name));
+            }
+            Value::Function(FunctionBody::MultiMethod(multi)) => {
+                result.push_str(&format!("#<multi {}>", multi.name));
+            }
+            Value::Function(FunctionBody::ProtocolMethod(protocol, index)) => {
+                result.push_str(&format!("#<protocol-fn {}/{}>", protocol.name, protocol.method_names[*index]));
+            }
+            Value::Function(FunctionBody::Closure { name, defined_as, arities, .. }) => {
+                result.push_str("#<fn");
+                if let Some(name) = defined_as.as_ref().or(name.as_ref()) {
+                    result.push(' ');
+                    result.push_str(name);
+                }
+                for arity in arities.iter() {
+                    result.push_str(&format!(" {}", arity));
+                }
+                result.push('>');
             }
             Value::Atom(val_ref) => {
-                format!("(atom {})", val_ref.borrow())
+                let ptr = Shared::as_ptr(val_ref);
+                let too_deep = options.atom_depth.is_some_and(|depth| atom_path.len() >= depth);
+                if atom_path.contains(&ptr) {
+                    result.push_str(&format!("#<cycle atom {:p}>", ptr));
+                    return;
+                }
+                if options.opaque_atoms || too_deep {
+                    result.push_str(&format!("#<atom {:p}>", ptr));
+                } else {
+                    result.push_str("(atom ");
+                    atom_path.push(ptr);
+                    val_ref.get().write_value(readable, options, atom_path, result);
+                    atom_path.pop();
+                    result.push(')');
+                }
+            }
+            Value::DynamicVar(var) => {
+                result.push_str(&format!("#'{}", var.name));
+            }
+            Value::OutputPort(port) => {
+                result.push_str(&format!("#<port {}>", port.name));
+            }
+            Value::Protocol(protocol) => {
+                result.push_str(&format!("#<protocol {}>", protocol.name));
+            }
+            #[cfg(feature = "sync")]
+            Value::Promise(promise) => {
+                let status = if promise.is_realized() { "ready" } else { "pending" };
+                result.push_str(&format!("#<{} {}>", promise.kind, status));
+            }
+            #[cfg(feature = "sync")]
+            Value::Channel(channel) => {
+                result.push_str(&format!("#<chan {:p}>", Shared::as_ptr(channel)));
+            }
+            Value::Tagged(tag, value) => {
+                result.push_str(&format!("#{} ", tag));
+                value.write_value(readable, options, atom_path, result);
+            }
+            Value::Transient(transient) => {
+                result.push_str(match &*transient.borrow() {
+                    Some(TransientCollection::Vector(_)) => "#<transient vector>",
+                    Some(TransientCollection::HashMap(_)) => "#<transient map>",
+                    None => "#<transient persisted>",
+                });
             }
         }
     }
@@ -150,28 +328,65 @@ impl Display for Value {
     }
 }
 
-impl Display for HashableValue {
+impl Display for ClosureArity {
+    /// Prints the parameter list as it was written, e.g. `[x {:keys [y]} & more]`
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            HashableValue::Integer(num) => write!(f, "{}", num),
-            HashableValue::String(s) => write!(f, "\"{}\"", s),
-            HashableValue::Keyword(s) => write!(f, "{}", s),
+        match &self.source {
+            Expr::List(elems) => match elems.front() {
+                // `(fn* (x y) ...)` is also accepted, but is shown the same way as a vector
+                Some(Expr::List(params)) => write!(f, "[{}]", write_delimiter_separated_elems(params, " ")),
+                Some(params) => write!(f, "{}", params),
+                None => write!(f, "[]"),
+            },
+            _ => {
+                let variadic = self.variadic_param.iter().flat_map(|param| ["&", param.as_str()]);
+                write!(f, "[{}]", write_delimiter_separated_elems(self.params.iter().map(String::as_str).chain(variadic), " "))
+            }
         }
     }
 }
 
+impl Display for HashableValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.print_value(true))
+    }
+}
+
 impl Printable for HashableValue {
     fn print_value(&self, readable: bool) -> String {
-        match self {
-            HashableValue::Integer(num) => format!("{}", num),
-            HashableValue::String(s) => Value::String(s.to_string()).print_value(readable),
-            HashableValue::Keyword(s) => s.to_string(),
-        }
+        Value::from(self.clone()).print_value(readable)
+    }
+}
+
+/// Prints a float so that it reads back as a float, e.g. `1.0` rather than `1`
+fn format_float(num: f64) -> String {
+    if num.is_nan() {
+        "##NaN".to_string()
+    } else if num.is_infinite() {
+        if num > 0.0 { "##Inf" } else { "##-Inf" }.to_string()
+    } else {
+        format!("{:?}", num)
+    }
+}
+
+/// Prints a character literal, e.g. `\a`, `\newline` or `\u0007`
+fn format_char_literal(c: char) -> String {
+    match c {
+        '\n' => "\\newline".to_string(),
+        ' ' => "\\space".to_string(),
+        '\t' => "\\tab".to_string(),
+        '\r' => "\\return".to_string(),
+        '\u{8}' => "\\backspace".to_string(),
+        '\u{c}' => "\\formfeed".to_string(),
+        c if c.is_control() => format!("\\u{:04x}", c as u32),
+        c => format!("\\{}", c),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::sync;
+
     use super::*;
 
     #[test]
@@ -212,9 +427,9 @@ mod tests {
 
     #[test]
     fn test_display_list() {
-        assert_eq!("(1)", Value::List(rpds::List::from_iter([Value::Integer(1)])).to_string());
+        assert_eq!("(1)", Value::List(sync::List::from_iter([Value::Integer(1)])).to_string());
 
-        assert_eq!("(+ 1 2)", Value::List(rpds::List::from_iter([
+        assert_eq!("(+ 1 2)", Value::List(sync::List::from_iter([
             Value::Symbol("+".to_string()),
             Value::Integer(1),
             Value::Integer(2)
@@ -223,9 +438,9 @@ mod tests {
 
     #[test]
     fn test_display_nested_list() {
-        assert_eq!("(+ (* 12 8) 2)", Value::List(rpds::List::from_iter([
+        assert_eq!("(+ (* 12 8) 2)", Value::List(sync::List::from_iter([
             Value::Symbol("+".to_string()),
-            Value::List(rpds::List::from_iter([
+            Value::List(sync::List::from_iter([
                 Value::Symbol("*".to_string()),
                 Value::Integer(12),
                 Value::Integer(8),
@@ -236,9 +451,9 @@ mod tests {
 
     #[test]
     fn test_display_vector() {
-        assert_eq!("[1]", Value::Vector(rpds::Vector::from_iter([Value::Integer(1)])).to_string());
+        assert_eq!("[1]", Value::Vector(sync::Vector::from_iter([Value::Integer(1)])).to_string());
 
-        assert_eq!("[foo 1 2]", Value::Vector(rpds::Vector::from_iter([
+        assert_eq!("[foo 1 2]", Value::Vector(sync::Vector::from_iter([
             Value::Symbol("foo".to_string()),
             Value::Integer(1),
             Value::Integer(2),
@@ -248,7 +463,61 @@ mod tests {
     #[test]
     fn test_display_hashmap() {
         assert_eq!("{\"foo\" 1}", Value::HashMap(
-            rpds::HashTrieMap::from_iter([(HashableValue::String("foo".to_string()), Value::Integer(1))])).to_string());
+            sync::HashTrieMap::from_iter([(HashableValue::String("foo".to_string()), Value::Integer(1))])).to_string());
+    }
+
+    #[test]
+    fn test_display_functions() {
+        let env = Env::default();
+        assert_eq!("#<builtin +>", env.lookup("+").unwrap().to_string());
+        assert_eq!("#<builtin if>", env.lookup("if").unwrap().to_string());
+
+        crate::rep("(def! f (fn* [x {:keys [y]} & more] x))", &env).unwrap();
+        assert_eq!("#<fn f [x {:keys [y]} & more]>", env.lookup("f").unwrap().to_string());
+        crate::rep("(def! g f)", &env).unwrap();
+        assert_eq!("#<fn f [x {:keys [y]} & more]>", env.lookup("g").unwrap().to_string());
+        assert_eq!("#<fn h [] [x]>\n", crate::rep("(fn* h ([] 0) ([x] x))", &env).unwrap());
+        assert_eq!("#<fn [x]>\n", crate::rep("(fn* [x] x)", &env).unwrap());
+    }
+
+    fn atom(value: Value) -> Shared<Atom> {
+        Shared::new(Atom::new(value))
+    }
+
+    #[test]
+    fn test_display_atom() {
+        assert_eq!("(atom [1])", Value::Atom(atom(Value::Vector(sync::Vector::from_iter([Value::Integer(1)])))).to_string());
+    }
+
+    #[test]
+    fn test_display_cyclic_atom() {
+        let env = Env::default();
+        let a = atom(Value::Nil);
+        a.reset(&env, Value::Vector(sync::Vector::from_iter([Value::Integer(1), Value::Atom(a.clone())]))).unwrap();
+        let expected = format!("(atom [1 #<cycle atom {:p}>])", Shared::as_ptr(&a));
+        assert_eq!(expected, Value::Atom(a.clone()).to_string());
+
+        // Break the cycle so the atom can be freed
+        a.reset(&env, Value::Nil).unwrap();
+    }
+
+    #[test]
+    fn test_display_atom_depth_and_opaque() {
+        let inner = atom(Value::Integer(1));
+        let outer = Value::Atom(atom(Value::Atom(inner.clone())));
+        let shallow = PrintOptions { atom_depth: Some(1), ..PrintOptions::default() };
+        assert_eq!(format!("(atom #<atom {:p}>)", Shared::as_ptr(&inner)), outer.print_value_with_options(true, &shallow));
+
+        let opaque = PrintOptions { opaque_atoms: true, ..PrintOptions::default() };
+        assert_eq!(format!("#<atom {:p}>", Shared::as_ptr(&inner)), Value::Atom(inner.clone()).print_value_with_options(true, &opaque));
+    }
+
+    #[test]
+    fn test_display_atom_while_swapping() {
+        // `swap!` doesn't hold the atom while its function runs, so the function can print it
+        let env = Env::default();
+        crate::rep("(def! a (atom 1))", &env).unwrap();
+        assert_eq!("\"(atom 1)\"\n", crate::rep("(swap! a (fn* [_] (pr-str a)))", &env).unwrap());
     }
 
     // #[test]
@@ -269,11 +538,4 @@ mod tests {
     //     assert_eq!("(quasiquote a)", Expr::Quasiquote(Box::new(Expr::Symbol("a".to_string()))).to_string());
     //     assert_eq!("(quasiquote 123)", Expr::Quasiquote(Box::new(Expr::Integer(123))).to_string());
     // }
-
-    // #[test]
-    // fn test_display_function() {
-    //     let val = Value::Function(FunctionBody::BuiltinValues(builtins::add));
-    //
-    //     assert_eq!("(fn ...)", val.to_string());
-    // }
 }
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::evaluator::RuntimeError;
+use crate::types::Value;
+
+/// A result that's delivered once, either by `deliver` or by the thread running a `future`, which
+/// `deref` waits for.
+pub struct Promise {
+    /// Either "promise" or "future", for printing
+    pub kind: &'static str,
+    result: Mutex<Option<Result<Value, RuntimeError>>>,
+    delivered: Condvar,
+}
+
+impl Promise {
+    pub fn new(kind: &'static str) -> Self {
+        Promise { kind, result: Mutex::new(None), delivered: Condvar::new() }
+    }
+
+    /// Delivers the result and wakes everything waiting for it. Returns false, leaving the result
+    /// unchanged, if one was already delivered.
+    pub fn deliver(&self, result: Result<Value, RuntimeError>) -> bool {
+        let mut slot = self.result.lock().unwrap_or_else(PoisonError::into_inner);
+        if slot.is_some() {
+            return false;
+        }
+        *slot = Some(result);
+        self.delivered.notify_all();
+        true
+    }
+
+    pub fn is_realized(&self) -> bool {
+        self.result.lock().unwrap_or_else(PoisonError::into_inner).is_some()
+    }
+
+    /// Waits for the result, giving up after `timeout` if there is one.
+    pub fn wait(&self, timeout: Option<Duration>) -> Option<Result<Value, RuntimeError>> {
+        let slot = self.result.lock().unwrap_or_else(PoisonError::into_inner);
+        let slot = match timeout {
+            Some(timeout) => self.delivered
+                .wait_timeout_while(slot, timeout, |result| result.is_none())
+                .unwrap_or_else(PoisonError::into_inner).0,
+            None => self.delivered
+                .wait_while(slot, |result| result.is_none())
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+        slot.clone()
+    }
+}
+
+impl Debug for Promise {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Promise")
+            .field("kind", &self.kind)
+            .field("realized", &self.is_realized())
+            .finish_non_exhaustive()
+    }
+}
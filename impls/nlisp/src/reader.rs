@@ -0,0 +1,352 @@
+use thiserror::Error;
+
+use crate::parser::{parse_text_to_expressions, ParseError};
+use crate::types::Expr;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ReadError {
+    /// Not really an error: the buffered input ends part way through a form, so more is needed
+    #[error("the input ended part way through a form")]
+    Incomplete,
+
+    #[error("the input was not valid UTF-8")]
+    InvalidUtf8,
+
+    #[error(transparent)]
+    Syntax(#[from] ParseError),
+}
+
+/// A resumable reader that is fed input in chunks, such as lines from a terminal or reads from a
+/// socket, and yields each form once all of it has arrived.
+#[derive(Debug, Default)]
+pub struct Reader {
+    buffer: String,
+    /// The start of the input that hasn't been read yet. Consumed input is only removed from the
+    /// buffer occasionally, so that reading many small forms from a large buffer stays linear.
+    start: usize,
+    /// The bytes at the end of the last chunk that are the start of a UTF-8 character split across chunks
+    partial_char: Vec<u8>,
+    at_eof: bool,
+}
+
+impl Reader {
+    pub fn new() -> Self {
+        Reader::default()
+    }
+
+    /// Adds a chunk of input. A UTF-8 character can be split across chunks.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<(), ReadError> {
+        self.partial_char.extend_from_slice(bytes);
+        match std::str::from_utf8(&self.partial_char) {
+            Ok(text) => {
+                self.buffer.push_str(text);
+                self.partial_char.clear();
+                Ok(())
+            }
+            // The chunk ends part way through a character, so keep those bytes for the next chunk
+            Err(e) if e.error_len().is_none() => {
+                let valid_len = e.valid_up_to();
+                let text = std::str::from_utf8(&self.partial_char[..valid_len]).expect("prefix to be valid UTF-8");
+                self.buffer.push_str(text);
+                self.partial_char.drain(..valid_len);
+                Ok(())
+            }
+            Err(_) => {
+                self.partial_char.clear();
+                Err(ReadError::InvalidUtf8)
+            }
+        }
+    }
+
+    pub fn feed_str(&mut self, text: &str) {
+        self.buffer.push_str(text);
+    }
+
+    /// Marks the end of the input. Afterwards, a form that is still incomplete is a syntax error,
+    /// and a symbol or number at the very end of the input is complete.
+    pub fn finish(&mut self) {
+        self.at_eof = true;
+    }
+
+    /// Whether there is no unread input other than whitespace.
+    pub fn is_empty(&self) -> bool {
+        self.partial_char.is_empty() && self.buffer[self.start..].chars().all(|c| c.is_whitespace() || c == ',')
+    }
+
+    /// Reads the next complete form. Returns `Ok(None)` when there is no more input to read, and
+    /// `Err(ReadError::Incomplete)` when a form has been started but not finished.
+    ///
+    /// After a syntax error, the reader skips past the offending input so that reading can continue.
+    pub fn next_expr(&mut self) -> Result<Option<Expr>, ReadError> {
+        loop {
+            let unread = &self.buffer[self.start..];
+            match scan_form(unread.as_bytes(), 0, self.at_eof) {
+                Scan::Empty(len) => {
+                    self.consume(len);
+                    if self.at_eof && !self.partial_char.is_empty() {
+                        self.partial_char.clear();
+                        return Err(ReadError::InvalidUtf8);
+                    }
+                    return Ok(None);
+                }
+                Scan::Form(end) => {
+                    let result = parse_text_to_expressions(&unread[..end]);
+                    self.consume(end);
+                    match result?.into_iter().next() {
+                        Some(expr) => return Ok(Some(expr)),
+                        // The form read as nothing, like `#_ x`, so carry on to the next one
+                        None => continue,
+                    }
+                }
+                Scan::Closer(position) => {
+                    self.consume(position + 1);
+                    return Err(ParseError::UnbalancedParens.into());
+                }
+                Scan::Incomplete if self.at_eof => {
+                    self.consume(unread.len());
+                    return Err(ParseError::UnexpectedEndOfInput.into());
+                }
+                Scan::Incomplete => return Err(ReadError::Incomplete),
+                Scan::Error(position, e) => {
+                    self.consume(position);
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        if self.start > self.buffer.len() / 2 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+    }
+}
+
+/// The result of looking for the end of a form.
+#[derive(Debug, PartialEq)]
+enum Scan {
+    /// A complete form ends at this byte offset
+    Form(usize),
+    /// There's nothing but whitespace and comments, up to this byte offset
+    Empty(usize),
+    /// A closing delimiter was found at this byte offset instead of a form
+    Closer(usize),
+    /// The input ends part way through a form
+    Incomplete,
+    /// The form is invalid. Input up to the byte offset should be skipped.
+    Error(usize, ParseError),
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || matches!(b, b',' | b';' | b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'"')
+}
+
+/// Skips whitespace, commas and comments. Returns `None` if a comment runs to the end of the
+/// input, since the rest of it may not have arrived yet.
+fn skip_whitespace(bytes: &[u8], mut pos: usize, at_eof: bool) -> Option<usize> {
+    while let Some(&b) = bytes.get(pos) {
+        match b {
+            b';' => match bytes[pos..].iter().position(|&b| b == b'\n') {
+                Some(newline) => pos += newline + 1,
+                None if at_eof => pos = bytes.len(),
+                None => return None,
+            },
+            b',' => pos += 1,
+            b if b.is_ascii_whitespace() => pos += 1,
+            _ => break,
+        }
+    }
+    Some(pos)
+}
+
+/// Finds the end of the form starting at `pos`. Syntax characters are all ASCII, so the input
+/// can be scanned as bytes.
+fn scan_form(bytes: &[u8], pos: usize, at_eof: bool) -> Scan {
+    let pos = match skip_whitespace(bytes, pos, at_eof) {
+        Some(pos) => pos,
+        None => return Scan::Incomplete,
+    };
+    let b = match bytes.get(pos) {
+        Some(&b) => b,
+        None => return Scan::Empty(pos),
+    };
+
+    match b {
+        b'(' => scan_collection(bytes, pos + 1, b')', at_eof),
+        b'[' => scan_collection(bytes, pos + 1, b']', at_eof),
+        b'{' => scan_collection(bytes, pos + 1, b'}', at_eof),
+        b')' | b']' | b'}' => Scan::Closer(pos),
+        b'"' => scan_string(bytes, pos + 1),
+        // The character after the backslash is part of the literal even if it's a delimiter, as in `\(`
+        b'\\' => match bytes.get(pos + 1) {
+            Some(_) => scan_token(bytes, pos + 2, at_eof),
+            None if at_eof => Scan::Form(pos + 1),
+            None => Scan::Incomplete,
+        },
+        b'\'' | b'`' | b'@' => scan_following_forms(bytes, pos + 1, 1, at_eof),
+        b'~' if bytes.get(pos + 1) == Some(&b'@') => scan_following_forms(bytes, pos + 2, 1, at_eof),
+        b'~' => scan_following_forms(bytes, pos + 1, 1, at_eof),
+        // `^meta form`
+        b'^' => scan_following_forms(bytes, pos + 1, 2, at_eof),
+        b'#' => match bytes.get(pos + 1) {
+            Some(b'_') | Some(b'?') => scan_following_forms(bytes, pos + 2, 1, at_eof),
+            Some(b'(') => scan_collection(bytes, pos + 2, b')', at_eof),
+            Some(b'{') => scan_collection(bytes, pos + 2, b'}', at_eof),
+            // `##Inf`, `##-Inf` or `##NaN`
+            Some(b'#') => scan_token(bytes, pos + 2, at_eof),
+            Some(_) => match scan_token(bytes, pos + 1, at_eof) {
+                Scan::Form(end) if matches!(&bytes[pos + 1..end], b"t" | b"f") => Scan::Form(end),
+                // `#tag form`
+                Scan::Form(end) => scan_following_forms(bytes, end, 1, at_eof),
+                scan => scan,
+            },
+            None if at_eof => Scan::Error(pos + 1, ParseError::MissingForm("#".to_string())),
+            None => Scan::Incomplete,
+        },
+        _ => scan_token(bytes, pos, at_eof),
+    }
+}
+
+/// A symbol, keyword or number. One that reaches the end of the input might continue in the next chunk.
+fn scan_token(bytes: &[u8], pos: usize, at_eof: bool) -> Scan {
+    match bytes[pos..].iter().position(|&b| is_delimiter(b)) {
+        Some(len) => Scan::Form(pos + len),
+        None if at_eof => Scan::Form(bytes.len()),
+        None => Scan::Incomplete,
+    }
+}
+
+fn scan_string(bytes: &[u8], mut pos: usize) -> Scan {
+    while let Some(&b) = bytes.get(pos) {
+        match b {
+            b'\\' => pos += 2,
+            b'"' => return Scan::Form(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    Scan::Incomplete
+}
+
+fn scan_collection(bytes: &[u8], mut pos: usize, closer: u8, at_eof: bool) -> Scan {
+    loop {
+        match scan_form(bytes, pos, at_eof) {
+            Scan::Form(end) => pos = end,
+            Scan::Closer(position) if bytes[position] == closer => return Scan::Form(position + 1),
+            Scan::Closer(position) => return Scan::Error(position + 1, ParseError::UnbalancedParens),
+            Scan::Empty(_) | Scan::Incomplete => return Scan::Incomplete,
+            error @ Scan::Error(..) => return error,
+        }
+    }
+}
+
+/// Scans the forms that must follow a prefix like `'` or `#_`.
+fn scan_following_forms(bytes: &[u8], mut pos: usize, count: usize, at_eof: bool) -> Scan {
+    for _ in 0..count {
+        match scan_form(bytes, pos, at_eof) {
+            Scan::Form(end) => pos = end,
+            Scan::Closer(position) => return Scan::Error(position, ParseError::MissingForm(
+                String::from_utf8_lossy(&bytes[..position]).trim().to_string()
+            )),
+            Scan::Empty(_) | Scan::Incomplete => return Scan::Incomplete,
+            error @ Scan::Error(..) => return error,
+        }
+    }
+    Scan::Form(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(reader: &mut Reader) -> Vec<Result<Expr, ReadError>> {
+        let mut results = vec![];
+        loop {
+            match reader.next_expr() {
+                Ok(Some(expr)) => results.push(Ok(expr)),
+                Ok(None) | Err(ReadError::Incomplete) => return results,
+                Err(e) => results.push(Err(e)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_forms_split_across_chunks() {
+        let mut reader = Reader::new();
+        reader.feed(b"(def! x [1 2").unwrap();
+        assert_eq!(Err(ReadError::Incomplete), reader.next_expr());
+        reader.feed(b" \"a)b\"]) (prn").unwrap();
+        assert_eq!(Ok(Some(Expr::List([
+            Expr::Symbol("def!".to_string()),
+            Expr::Symbol("x".to_string()),
+            Expr::Vector(vec![Expr::Integer(1), Expr::Integer(2), Expr::String("a)b".to_string())]),
+        ].into_iter().collect()))), reader.next_expr());
+        assert_eq!(Err(ReadError::Incomplete), reader.next_expr());
+        reader.feed(b" x)\n").unwrap();
+        assert!(matches!(reader.next_expr(), Ok(Some(Expr::List(_)))));
+        assert_eq!(Ok(None), reader.next_expr());
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_token_at_end_of_chunk() {
+        let mut reader = Reader::new();
+        reader.feed(b"12").unwrap();
+        assert_eq!(Err(ReadError::Incomplete), reader.next_expr());
+        reader.feed(b"3 ").unwrap();
+        assert_eq!(Ok(Some(Expr::Integer(123))), reader.next_expr());
+
+        reader.feed(b"45").unwrap();
+        reader.finish();
+        assert_eq!(Ok(Some(Expr::Integer(45))), reader.next_expr());
+        assert_eq!(Ok(None), reader.next_expr());
+    }
+
+    #[test]
+    fn test_split_utf8() {
+        let mut reader = Reader::new();
+        let text = "\"héllo\" ".as_bytes();
+        reader.feed(&text[..3]).unwrap();
+        reader.feed(&text[3..]).unwrap();
+        assert_eq!(Ok(Some(Expr::String("héllo".to_string()))), reader.next_expr());
+        assert_eq!(Err(ReadError::InvalidUtf8), reader.feed(&[0xff, b' ']));
+    }
+
+    #[test]
+    fn test_prefixes_and_dispatch() {
+        let mut reader = Reader::new();
+        reader.feed_str("'(a) ^:dynamic x #_ (ignored) #? (:nlisp 1) #t #inst \"2024-01-01\" ;; comment\n");
+        assert_eq!(5, read_all(&mut reader).into_iter().filter(Result::is_ok).count());
+
+        reader.feed_str("#{1 2} \\) ##Inf ");
+        assert_eq!(3, read_all(&mut reader).into_iter().filter(Result::is_ok).count());
+
+        reader.feed_str("'");
+        assert_eq!(Err(ReadError::Incomplete), reader.next_expr());
+        reader.feed_str("x ");
+        assert_eq!(Ok(Some(Expr::Quote(Box::new(Expr::Symbol("x".to_string()))))), reader.next_expr());
+    }
+
+    #[test]
+    fn test_syntax_errors_are_not_incomplete() {
+        let mut reader = Reader::new();
+        reader.feed_str(") 1 (a ] 2 ");
+        assert_eq!(vec![
+            Err(ReadError::Syntax(ParseError::UnbalancedParens)),
+            Ok(Expr::Integer(1)),
+            Err(ReadError::Syntax(ParseError::UnbalancedParens)),
+            Ok(Expr::Integer(2)),
+        ], read_all(&mut reader));
+    }
+
+    #[test]
+    fn test_incomplete_at_end_of_input() {
+        let mut reader = Reader::new();
+        reader.feed_str("(+ 1 ");
+        assert_eq!(Err(ReadError::Incomplete), reader.next_expr());
+        reader.finish();
+        assert_eq!(Err(ReadError::Syntax(ParseError::UnexpectedEndOfInput)), reader.next_expr());
+        assert_eq!(Ok(None), reader.next_expr());
+    }
+}
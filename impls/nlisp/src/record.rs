@@ -0,0 +1,69 @@
+use crate::sync::{self, Shared};
+use crate::types::{HashableValue, Value};
+
+/// A type declared with `defrecord`: its name and the fields that its constructor takes, in order.
+#[derive(Debug, PartialEq)]
+pub struct RecordType {
+    pub name: String,
+    /// The name qualified with the namespace it was declared in, e.g. `user/Point`, which protocols
+    /// dispatch on. Built-in type names are never qualified, so they can't clash with a record's.
+    pub qualified_name: String,
+    /// The field names as keywords, e.g. `:x`
+    pub fields: Vec<HashableValue>,
+}
+
+/// An instance of a record type. It behaves like a map whose keys are the field keywords, and
+/// can also hold keys that aren't fields, which are added with `assoc`. Two records are equal when
+/// their types and entries are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub record_type: Shared<RecordType>,
+    entries: sync::HashTrieMap<HashableValue, Value>,
+}
+
+impl Record {
+    /// Creates a record from its entries, setting any fields that are missing to nil.
+    pub fn new(record_type: Shared<RecordType>, mut entries: sync::HashTrieMap<HashableValue, Value>) -> Self {
+        for field in &record_type.fields {
+            if !entries.contains_key(field) {
+                entries.insert_mut(field.clone(), Value::Nil);
+            }
+        }
+        Record { record_type, entries }
+    }
+
+    pub fn type_name(&self) -> &str {
+        &self.record_type.qualified_name
+    }
+
+    pub fn get(&self, key: &HashableValue) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    pub fn assoc(&self, key: HashableValue, value: Value) -> Record {
+        Record { record_type: self.record_type.clone(), entries: self.entries.insert(key, value) }
+    }
+
+    /// Removes a key. Without one of its fields, the result is no longer a record, so removing a
+    /// field gives a plain map.
+    pub fn dissoc(&self, key: &HashableValue) -> Value {
+        let entries = self.entries.remove(key);
+        match self.record_type.fields.contains(key) {
+            true => Value::HashMap(entries),
+            false => Value::Record(Record { record_type: self.record_type.clone(), entries }),
+        }
+    }
+
+    /// The entries with the fields first, in the order they were declared, then any other keys.
+    pub fn entries(&self) -> impl Iterator<Item=(&HashableValue, &Value)> {
+        let fields = self.record_type.fields.iter()
+            .filter_map(|field| self.entries.get_key_value(field));
+        let others = self.entries.iter()
+            .filter(|(key, _)| !self.record_type.fields.contains(key));
+        fields.chain(others)
+    }
+
+    pub fn to_map(&self) -> sync::HashTrieMap<HashableValue, Value> {
+        self.entries.clone()
+    }
+}
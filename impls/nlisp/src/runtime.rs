@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+
+use crate::namespace::Namespaces;
+use crate::sandbox::Budget;
+use crate::sync::Lock;
+use crate::types::Value;
+
+/// State shared by every environment created from the same root, such as the namespace registry.
+#[derive(Default)]
+pub struct Runtime {
+    pub namespaces: Namespaces,
+    /// The function used to read each tagged literal, keyed by tag name without the `#`
+    pub reader_tags: Lock<HashMap<String, Value>>,
+    /// The resource limits for untrusted code, and how much of them has been used
+    pub budget: Budget,
+}
+
+impl PartialEq for Runtime {
+    fn eq(&self, other: &Self) -> bool {
+        // Runtimes own environments that point back at them, so compare by identity
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for Runtime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("current_namespace", &self.namespaces.current())
+            .finish_non_exhaustive()
+    }
+}
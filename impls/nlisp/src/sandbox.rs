@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::fmt::{Display, Formatter};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::evaluator::RuntimeError;
+use crate::sync::{Lock, Slot};
+use crate::types::Value;
+
+/// Resource limits for evaluating untrusted code. `None` means no limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// The number of expressions that can be evaluated
+    pub max_steps: Option<u64>,
+    /// How deeply function calls can nest
+    pub max_depth: Option<usize>,
+    /// Roughly how much data builtins can create, counted in collection elements and string bytes
+    pub max_allocation: Option<usize>,
+    /// How long evaluation can take, starting from when the limits are set
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Depth(usize),
+    Allocation(usize),
+    Timeout(Duration),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::Steps(max) => write!(f, "limit of {} evaluation steps", max),
+            Limit::Depth(max) => write!(f, "maximum call depth of {}", max),
+            Limit::Allocation(max) => write!(f, "allocation limit of {}", max),
+            Limit::Timeout(timeout) => write!(f, "timeout of {:?}", timeout),
+        }
+    }
+}
+
+/// Tracks how much of its `Limits` an evaluation has used. Each runtime has one, which is shared
+/// by every thread evaluating in it, so the counters are atomic. Call depth is counted separately
+/// for each thread, since threads don't call each other's functions.
+#[derive(Debug, Default)]
+pub struct Budget {
+    limits: Slot<Limits>,
+    deadline: Slot<Option<Instant>>,
+    steps: AtomicU64,
+    /// The call depth of each thread that is currently in a function call
+    depths: Lock<HashMap<ThreadId, usize>>,
+    allocated: AtomicUsize,
+    /// Set from outside the evaluation, e.g. by a Ctrl-C handler, to ask it to stop
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Budget {
+    /// Replaces the limits and starts counting from zero.
+    pub fn start(&self, limits: Limits) {
+        self.limits.set(limits);
+        self.deadline.set(limits.timeout.map(|timeout| Instant::now() + timeout));
+        self.steps.store(0, Ordering::Relaxed);
+        self.allocated.store(0, Ordering::Relaxed);
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits.get()
+    }
+
+    /// Starts counting against `limits` until the returned guard is dropped, which puts back the
+    /// previous limits and how much of them had been used.
+    pub fn start_scoped(&self, limits: Limits) -> LimitsGuard<'_> {
+        let guard = LimitsGuard {
+            budget: self,
+            limits: self.limits.get(),
+            deadline: self.deadline.get(),
+            steps: self.steps.load(Ordering::Relaxed),
+            allocated: self.allocated.load(Ordering::Relaxed),
+        };
+        self.start(limits);
+        guard
+    }
+
+    /// Counts one evaluation step, also checking the deadline.
+    pub fn step(&self) -> Result<(), RuntimeError> {
+        let steps = self.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        let limits = self.limits.get();
+        if let Some(max) = limits.max_steps.filter(|max| steps > *max) {
+            return Err(RuntimeError::LimitExceeded(Limit::Steps(max)));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline.get(), limits.timeout) {
+            if Instant::now() > deadline {
+                return Err(RuntimeError::LimitExceeded(Limit::Timeout(timeout)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Enters a function call, which lasts until the returned guard is dropped.
+    pub fn enter_call(&self) -> Result<CallGuard<'_>, RuntimeError> {
+        let thread_id = thread::current().id();
+        let mut depths = self.depths.borrow_mut();
+        let depth = depths.get(&thread_id).copied().unwrap_or(0) + 1;
+        if let Some(max) = self.limits.get().max_depth.filter(|max| depth > *max) {
+            return Err(RuntimeError::LimitExceeded(Limit::Depth(max)));
+        }
+        depths.insert(thread_id, depth);
+        Ok(CallGuard(self))
+    }
+
+    /// The flag that interrupts evaluation when set. It can be shared with a signal handler or
+    /// another thread.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupted.clone()
+    }
+
+    /// Stops with `RuntimeError::Interrupted` if an interrupt was requested, clearing the request so
+    /// that the next evaluation runs normally. This is checked wherever evaluation can go on
+    /// indefinitely: at each function call and each iteration of a `loop`.
+    pub fn check_interrupt(&self) -> Result<(), RuntimeError> {
+        if self.interrupted.swap(false, Ordering::Relaxed) {
+            return Err(RuntimeError::Interrupted);
+        }
+        Ok(())
+    }
+
+    pub fn tracks_allocation(&self) -> bool {
+        self.limits.get().max_allocation.is_some()
+    }
+
+    /// Charges a builtin for the data it created: how much bigger its result is than its largest
+    /// argument. This is only an estimate, but it means `conj` costs one element rather than the
+    /// whole collection.
+    pub fn charge_allocation(&self, largest_arg: usize, result: &Value) -> Result<(), RuntimeError> {
+        let charge = shallow_size(result).saturating_sub(largest_arg);
+        let allocated = self.allocated.fetch_add(charge, Ordering::Relaxed) + charge;
+        match self.limits.get().max_allocation {
+            Some(max) if allocated > max => Err(RuntimeError::LimitExceeded(Limit::Allocation(max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct CallGuard<'a>(&'a Budget);
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        let mut depths = self.0.depths.borrow_mut();
+        let thread_id = thread::current().id();
+        match depths.get(&thread_id).copied() {
+            Some(1) | None => depths.remove(&thread_id),
+            Some(depth) => depths.insert(thread_id, depth - 1),
+        };
+    }
+}
+
+pub struct LimitsGuard<'a> {
+    budget: &'a Budget,
+    limits: Limits,
+    deadline: Option<Instant>,
+    steps: u64,
+    allocated: usize,
+}
+
+impl Drop for LimitsGuard<'_> {
+    fn drop(&mut self) {
+        self.budget.limits.set(self.limits);
+        self.budget.deadline.set(self.deadline);
+        self.budget.steps.store(self.steps, Ordering::Relaxed);
+        self.budget.allocated.store(self.allocated, Ordering::Relaxed);
+    }
+}
+
+/// The number of elements in a collection or bytes in a string, without looking inside nested values.
+pub fn shallow_size(value: &Value) -> usize {
+    match value {
+        Value::String(s) => s.len(),
+        Value::List(elems) => elems.len(),
+        Value::Vector(elems) => elems.len(),
+        Value::HashMap(pairs) => pairs.size(),
+        Value::Set(elems) => elems.size(),
+        _ => 0,
+    }
+}
+
+/// Which groups of builtins an environment provides. Untrusted code can be given an environment
+/// without the ones that reach outside the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Printing to `*out*`, such as `prn` and `println`
+    pub io: bool,
+    /// Reading and writing files, including loading namespaces from the load path
+    pub fs: bool,
+    /// Environment variables, `exit` and running subprocesses with `sh`
+    pub process: bool,
+}
+
+impl Capabilities {
+    pub const ALL: Capabilities = Capabilities { io: true, fs: true, process: true };
+    pub const NONE: Capabilities = Capabilities { io: false, fs: false, process: false };
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::ALL
+    }
+}
@@ -0,0 +1,600 @@
+use std::fmt::{Display, Formatter};
+
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Error as _, SerializeMap, SerializeSeq};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::printer::Printable;
+use crate::sync;
+use crate::types::{HashableValue, Value};
+
+#[derive(Error, Debug, PartialEq)]
+#[error("{0}")]
+pub struct SerdeError(String);
+
+impl ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+impl serde::de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+/// Converts any serializable Rust value into an nlisp value. Structs become maps with keyword
+/// keys, so their fields can be destructured with `{:keys [...]}`, and other maps keep their keys as
+/// they are.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, SerdeError> {
+    value.serialize(ValueSerializer)
+}
+
+/// Converts an nlisp value into any deserializable Rust type. Keywords are read as strings without
+/// their leading `:`, so a map with keyword keys can fill in a struct.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, SerdeError> {
+    T::deserialize(value)
+}
+
+/// The error message for a value that has no serde representation
+fn unsupported(value: &Value, action: &str) -> String {
+    match value {
+        Value::Function(_) => format!("functions can't be {}: {}", action, value.print_value(true)),
+        Value::Atom(_) => format!("atoms can't be {}, deref them first: {}", action, value.print_value(true)),
+        #[cfg(feature = "sync")]
+        Value::Promise(_) => format!("promises can't be {}, deref them first: {}", action, value.print_value(true)),
+        _ => format!("`{}` can't be {}", value.print_value(true), action),
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Nil => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Integer(num) => serializer.serialize_i64(*num),
+            Value::Float(num) => serializer.serialize_f64(*num),
+            Value::Char(c) => serializer.serialize_char(*c),
+            Value::String(s) | Value::Symbol(s) => serializer.serialize_str(s),
+            Value::Keyword(k) => serializer.serialize_str(&k[1..]),
+            Value::List(elems) => serializer.collect_seq(elems.iter()),
+            Value::Vector(elems) => serializer.collect_seq(elems.iter()),
+            Value::Set(elems) => serializer.collect_seq(elems.iter()),
+            Value::HashMap(pairs) => {
+                let mut map = serializer.serialize_map(Some(pairs.size()))?;
+                for (key, value) in pairs.iter() {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Value::Record(record) => serializer.collect_map(record.entries()),
+            Value::Tagged(_, value) => value.serialize(serializer),
+            _ => Err(S::Error::custom(unsupported(self, "serialized"))),
+        }
+    }
+}
+
+impl Serialize for HashableValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from(self.clone()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("any nlisp value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn visit_i64<E>(self, num: i64) -> Result<Value, E> {
+        Ok(Value::Integer(num))
+    }
+
+    /// Integers too large for an i64 lose precision rather than failing
+    fn visit_u64<E>(self, num: u64) -> Result<Value, E> {
+        Ok(i64::try_from(num).map(Value::Integer).unwrap_or(Value::Float(num as f64)))
+    }
+
+    fn visit_f64<E>(self, num: f64) -> Result<Value, E> {
+        Ok(Value::Float(num))
+    }
+
+    fn visit_char<E>(self, c: char) -> Result<Value, E> {
+        Ok(Value::Char(c))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elems = sync::Vector::default();
+        while let Some(elem) = seq.next_element()? {
+            elems.push_back_mut(elem);
+        }
+        Ok(Value::Vector(elems))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut pairs = sync::HashTrieMap::default();
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            let key = key.clone().try_into()
+                .map_err(|_| serde::de::Error::custom(format!("`{}` can't be used as a map key", key.print_value(true))))?;
+            pairs.insert_mut(key, value);
+        }
+        Ok(Value::HashMap(pairs))
+    }
+}
+
+/// Reads Rust types straight out of a value, the inverse of `ValueSerializer`, so floats that JSON
+/// can't hold, chars and non-string map keys all survive `from_value`.
+impl<'de> Deserializer<'de> for Value {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::Nil => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Integer(num) => visitor.visit_i64(num),
+            Value::Float(num) => visitor.visit_f64(num),
+            Value::Char(c) => visitor.visit_char(c),
+            Value::String(s) | Value::Symbol(s) => visitor.visit_string(s),
+            Value::Keyword(k) => visitor.visit_string(k[1..].to_string()),
+            Value::List(elems) => visit_seq(visitor, elems.iter().cloned()),
+            Value::Vector(elems) => visit_seq(visitor, elems.iter().cloned()),
+            Value::Set(elems) => visit_seq(visitor, elems.iter().cloned().map(Value::from)),
+            Value::HashMap(pairs) => visit_map(visitor, pairs.iter().map(|(key, value)| (key.clone(), value.clone()))),
+            Value::Record(record) => visit_map(visitor, record.entries().map(|(key, value)| (key.clone(), value.clone()))),
+            Value::Tagged(_, value) => (*value).clone().deserialize_any(visitor),
+            value => Err(SerdeError(unsupported(&value, "deserialized"))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::Nil => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Fieldless variants are read from keywords or strings, and variants with data from
+    /// single-entry `{:variant value}` maps
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Value::Keyword(k) => visitor.visit_enum(k[1..].to_string().into_deserializer()),
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            Value::HashMap(pairs) if pairs.size() == 1 => {
+                let pairs = pairs.iter().map(|(key, value)| (Value::from(key.clone()), value.clone()));
+                visitor.visit_enum(MapAccessDeserializer::new(MapDeserializer::new(pairs)))
+            }
+            value => Err(SerdeError(format!("expected a keyword or a single-entry map for an enum, got `{}`", value.print_value(true)))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerdeError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+fn visit_seq<'de, V: Visitor<'de>>(visitor: V, elems: impl Iterator<Item=Value>) -> Result<V::Value, SerdeError> {
+    let mut seq = SeqDeserializer::new(elems);
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_map<'de, V: Visitor<'de>>(visitor: V, pairs: impl Iterator<Item=(HashableValue, Value)>) -> Result<V::Value, SerdeError> {
+    let mut map = MapDeserializer::new(pairs.map(|(key, value)| (Value::from(key), value)));
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+/// Builds values directly from a `Serialize` implementation, which unlike going through
+/// `Deserialize` can tell structs apart from maps.
+struct ValueSerializer;
+
+fn keyword(name: &str) -> HashableValue {
+    HashableValue::Keyword(format!(":{}", name))
+}
+
+/// Wraps `value` as `{:variant value}`, the way enum variants with data are represented.
+fn variant_map(variant: &str, value: Value) -> Value {
+    Value::HashMap(sync::HashTrieMap::from_iter([(keyword(variant), value)]))
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, b: bool) -> Result<Value, SerdeError> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn serialize_i8(self, num: i8) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_i16(self, num: i16) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_i32(self, num: i32) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_i64(self, num: i64) -> Result<Value, SerdeError> {
+        Ok(Value::Integer(num))
+    }
+
+    fn serialize_u8(self, num: u8) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_u16(self, num: u16) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_u32(self, num: u32) -> Result<Value, SerdeError> {
+        self.serialize_i64(num as i64)
+    }
+
+    fn serialize_u64(self, num: u64) -> Result<Value, SerdeError> {
+        ValueVisitor.visit_u64(num)
+    }
+
+    fn serialize_f32(self, num: f32) -> Result<Value, SerdeError> {
+        self.serialize_f64(num as f64)
+    }
+
+    fn serialize_f64(self, num: f64) -> Result<Value, SerdeError> {
+        Ok(Value::Float(num))
+    }
+
+    fn serialize_char(self, c: char) -> Result<Value, SerdeError> {
+        Ok(Value::Char(c))
+    }
+
+    fn serialize_str(self, s: &str) -> Result<Value, SerdeError> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn serialize_bytes(self, bytes: &[u8]) -> Result<Value, SerdeError> {
+        Ok(Value::Vector(bytes.iter().map(|b| Value::Integer(*b as i64)).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::Nil)
+    }
+
+    /// Fieldless enum variants become keywords, e.g. `Status::Active` becomes `:Active`
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value, SerdeError> {
+        Ok(Value::Keyword(format!(":{}", variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Value, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Value, SerdeError> {
+        Ok(variant_map(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer { elems: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer { elems: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer { pairs: sync::HashTrieMap::default(), next_key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapSerializer, SerdeError> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer { pairs: sync::HashTrieMap::default(), next_key: None, variant: Some(variant) })
+    }
+}
+
+struct SeqSerializer {
+    elems: Vec<Value>,
+    variant: Option<&'static str>,
+}
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.elems.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        let vector = Value::Vector(self.elems.into_iter().collect());
+        Ok(match self.variant {
+            Some(variant) => variant_map(variant, vector),
+            None => vector,
+        })
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    pairs: sync::HashTrieMap<HashableValue, Value>,
+    next_key: Option<HashableValue>,
+    variant: Option<&'static str>,
+}
+
+impl MapSerializer {
+    fn end_map(self) -> Value {
+        let map = Value::HashMap(self.pairs);
+        match self.variant {
+            Some(variant) => variant_map(variant, map),
+            None => map,
+        }
+    }
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        let key = key.serialize(ValueSerializer)?;
+        let hashable = key.clone().try_into()
+            .map_err(|_| SerdeError(format!("`{}` can't be used as a map key", key.print_value(true))))?;
+        self.next_key = Some(hashable);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.next_key.take().expect("serialize_key to be called before serialize_value");
+        self.pairs.insert_mut(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.end_map())
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, field: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.pairs.insert_mut(keyword(field), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.end_map())
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, field: &'static str, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeStruct::serialize_field(self, field, value)
+    }
+
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(self.end_map())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::Interpreter;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Tier {
+        Free,
+        Paid { seats: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Request {
+        user: String,
+        amount: f64,
+        tags: Vec<String>,
+        tier: Tier,
+        headers: BTreeMap<String, String>,
+        referrer: Option<String>,
+    }
+
+    fn request() -> Request {
+        Request {
+            user: "ada".to_string(),
+            amount: 12.5,
+            tags: vec!["new".to_string()],
+            tier: Tier::Paid { seats: 3 },
+            headers: BTreeMap::from([("user-agent".to_string(), "curl".to_string())]),
+            referrer: None,
+        }
+    }
+
+    #[test]
+    fn test_struct_round_trip() {
+        let value = to_value(&request()).unwrap();
+        assert_eq!(Ok(request()), from_value::<Request>(value));
+        assert_eq!(Ok(Tier::Free), from_value::<Tier>(to_value(&Tier::Free).unwrap()));
+    }
+
+    #[test]
+    fn test_structs_have_keyword_keys() {
+        let interpreter = Interpreter::new();
+        interpreter.define("request", to_value(&request()).unwrap());
+        let result = interpreter.eval(r#"
+            (let* [{:keys [user tier headers]} request
+                   {{:keys [seats]} :Paid} tier
+                   {:strs [user-agent]} headers]
+              {:user user :team? (> seats 1) :seats seats :agent user-agent})
+        "#).unwrap();
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Decision {
+            user: String,
+            #[serde(rename = "team?")]
+            team: bool,
+            seats: u32,
+            agent: String,
+        }
+        let expected = Decision { user: "ada".to_string(), team: true, seats: 3, agent: "curl".to_string() };
+        assert_eq!(Ok(expected), from_value::<Decision>(result));
+    }
+
+    #[test]
+    fn test_deserialize_value() {
+        let value: Value = serde_json::from_str(r#"{"a": [1, 2.5, null, "s"]}"#).unwrap();
+        assert_eq!(crate::json::parse(r#"{"a": [1, 2.5, null, "s"]}"#, false).unwrap(), value);
+    }
+
+    #[test]
+    fn test_functions_and_atoms_are_errors() {
+        let interpreter = Interpreter::new();
+        let function = interpreter.eval("(fn* [x] x)").unwrap();
+        assert_eq!(
+            "functions can't be serialized: #<fn [x]>",
+            serde_json::to_string(&function).unwrap_err().to_string()
+        );
+        let atom = interpreter.eval("[(atom 1)]").unwrap();
+        assert!(from_value::<Vec<i64>>(atom).unwrap_err().to_string().starts_with("atoms can't be deserialized"));
+    }
+
+    #[test]
+    fn test_from_value_keeps_what_json_cant_hold() {
+        let interpreter = Interpreter::new();
+        let floats = from_value::<Vec<f64>>(interpreter.eval("[##Inf ##NaN 1]").unwrap()).unwrap();
+        assert_eq!(f64::INFINITY, floats[0]);
+        assert!(floats[1].is_nan());
+        assert_eq!(1.0, floats[2]);
+
+        let value = interpreter.eval(r#"{1 \a 2 \b}"#).unwrap();
+        assert_eq!(Ok(BTreeMap::from([(1, 'a'), (2, 'b')])), from_value::<BTreeMap<i64, char>>(value));
+        assert_eq!(Ok(Tier::Paid { seats: 2 }), from_value::<Tier>(interpreter.eval("{:Paid {:seats 2}}").unwrap()));
+    }
+}
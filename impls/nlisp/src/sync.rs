@@ -0,0 +1,103 @@
+//! The pointer and cell types that values and environments are built from. By default they're the
+//! single-threaded `Rc` and `RefCell`. With the `sync` feature they're `Arc` and `RwLock`, which
+//! makes `Value` and `Env` `Send + Sync` so that evaluation can be spread across threads.
+
+#[cfg(not(feature = "sync"))]
+use std::cell::{Cell, Ref, RefCell, RefMut};
+#[cfg(feature = "sync")]
+use std::sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(not(feature = "sync"))]
+pub use std::rc::Rc as Shared;
+#[cfg(feature = "sync")]
+pub use std::sync::Arc as Shared;
+
+/// The kind of pointer used inside persistent collections
+#[cfg(not(feature = "sync"))]
+pub type PtrKind = archery::RcK;
+#[cfg(feature = "sync")]
+pub type PtrKind = archery::ArcTK;
+
+pub type List<T> = rpds::List<T, PtrKind>;
+pub type Vector<T> = rpds::Vector<T, PtrKind>;
+pub type HashTrieMap<K, V> = rpds::HashTrieMap<K, V, PtrKind>;
+pub type HashTrieSet<T> = rpds::HashTrieSet<T, PtrKind>;
+
+/// Implemented by every type that can be shared with other threads when the `sync` feature is
+/// enabled, and by every type otherwise. Closures and writers handed to the interpreter need it.
+#[cfg(feature = "sync")]
+pub trait ThreadSafe: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> ThreadSafe for T {}
+
+#[cfg(not(feature = "sync"))]
+pub trait ThreadSafe {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> ThreadSafe for T {}
+
+/// A `RefCell`, or a `RwLock` with the `sync` feature, with `RefCell`'s method names.
+#[derive(Debug, Default)]
+pub struct Lock<T>(
+    #[cfg(not(feature = "sync"))] RefCell<T>,
+    #[cfg(feature = "sync")] RwLock<T>,
+);
+
+#[cfg(not(feature = "sync"))]
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock(RefCell::new(value))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Self {
+        Lock(RwLock::new(value))
+    }
+
+    /// A panic while the lock was held can't leave an nlisp value half updated, so poisoning is ignored
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A `Cell`, or a `Mutex` with the `sync` feature, for small `Copy` values.
+#[derive(Debug, Default)]
+pub struct Slot<T: Copy>(
+    #[cfg(not(feature = "sync"))] Cell<T>,
+    #[cfg(feature = "sync")] Mutex<T>,
+);
+
+#[cfg(not(feature = "sync"))]
+impl<T: Copy> Slot<T> {
+    pub fn get(&self) -> T {
+        self.0.get()
+    }
+
+    pub fn set(&self, value: T) {
+        self.0.set(value)
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Copy> Slot<T> {
+    pub fn get(&self) -> T {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
@@ -1,15 +1,26 @@
-use std::cell::RefCell;
-use std::collections::{LinkedList, VecDeque};
-use std::rc::Rc;
+use std::collections::{HashMap, LinkedList, VecDeque};
+use std::fmt::{Debug, Formatter};
+use std::thread::{self, ThreadId};
 
 use thiserror::Error;
 
+use crate::atom::Atom;
+#[cfg(feature = "sync")]
+use crate::channel::Channel;
+use crate::dispatch::{MultiMethod, Protocol};
 use crate::env::Env;
 use crate::evaluator::{RuntimeError, TypeError};
+use crate::port::OutputPort;
+#[cfg(feature = "sync")]
+use crate::promise::Promise;
+use crate::record::Record;
+use crate::sync::{self, Lock, Shared, ThreadSafe};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Integer(i64),
+    Float(f64),
+    Char(char),
     String(String),
     Symbol(String),
     Keyword(String),
@@ -22,35 +33,186 @@ pub enum Expr {
     List(LinkedList<Expr>),
     Vector(Vec<Expr>),
     HashMap(Vec<(Expr, Expr)>),
+    Set(Vec<Expr>),
+    /// A tagged literal like `#inst "2024-01-01T00:00:00Z"`, which is passed to the reader function
+    /// registered for its tag when evaluated
+    Tagged(String, Box<Expr>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[allow(unpredictable_function_pointer_comparisons)]
 pub enum FunctionBody {
-    BuiltinValues(fn(&Env, VecDeque<Value>) -> Result<Value, RuntimeError>),
-    BuiltinExpressions(fn(&Env, VecDeque<Expr>) -> Result<Value, RuntimeError>),
-    Closure { closed_env: Env, params: Vec<String>, variadic_param: Option<String>, body: Expr },
+    /// A builtin, along with the name it was registered under
+    BuiltinValues(&'static str, fn(&Env, VecDeque<Value>) -> Result<Value, RuntimeError>),
+    BuiltinExpressions(&'static str, fn(&Env, VecDeque<Expr>) -> Result<Value, RuntimeError>),
+    /// A Rust closure registered by an embedding program, which unlike a builtin can capture state
+    Native(NativeFunction),
+    /// `name` is the name given in `(fn* name ...)`, which the body can use to call itself.
+    /// `defined_as` is the name it was first bound to with `def!`, which is only used for printing.
+    Closure { closed_env: Env, name: Option<String>, defined_as: Option<String>, arities: Shared<Vec<ClosureArity>> },
+    /// A function defined with `defmulti`
+    MultiMethod(Shared<MultiMethod>),
+    /// One of the methods of a protocol, given by its index in the protocol's `method_names`
+    ProtocolMethod(Shared<Protocol>, usize),
+}
+
+/// The signature shared by builtins that take evaluated arguments and native functions. With the
+/// `sync` feature, native functions must also be `Send + Sync`.
+pub trait NativeFn: Fn(&Env, VecDeque<Value>) -> Result<Value, RuntimeError> + ThreadSafe {}
+
+impl<F: Fn(&Env, VecDeque<Value>) -> Result<Value, RuntimeError> + ThreadSafe> NativeFn for F {}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    func: Shared<dyn NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(name: impl Into<String>, func: impl NativeFn + 'static) -> Self {
+        NativeFunction { name: name.into(), func: Shared::new(func) }
+    }
+
+    pub fn call(&self, env: &Env, args: VecDeque<Value>) -> Result<Value, RuntimeError> {
+        (self.func)(env, args)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Shared::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunction").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+/// One parameter list and body of a closure. Closures can have several, dispatched on the number of arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosureArity {
+    pub params: Vec<String>,
+    pub variadic_param: Option<String>,
+    pub body: Expr,
+    /// The arity as it was written, e.g. `([x & more] (prn x) more)`, before destructuring and
+    /// the implicit `do` were expanded
+    pub source: Expr,
+}
+
+impl ClosureArity {
+    pub fn accepts(&self, num_args: usize) -> bool {
+        match self.variadic_param {
+            Some(_) => num_args >= self.params.len(),
+            None => num_args == self.params.len(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Integer(i64),
+    Float(f64),
+    Char(char),
     String(String),
     Symbol(String),
     Keyword(String),
     Boolean(bool),
-    List(rpds::List<Value>),
-    Vector(rpds::Vector<Value>),
-    HashMap(rpds::HashTrieMap<HashableValue, Value>),
+    List(sync::List<Value>),
+    Vector(sync::Vector<Value>),
+    HashMap(sync::HashTrieMap<HashableValue, Value>),
+    Set(sync::HashTrieSet<HashableValue>),
+    /// An instance of a type declared with `defrecord`
+    Record(Record),
     Function(FunctionBody),
-    Atom(Rc<RefCell<Value>>),
+    Atom(Shared<Atom>),
+    Transient(Shared<Lock<Option<TransientCollection>>>),
+    DynamicVar(Shared<DynamicVar>),
+    OutputPort(OutputPort),
+    /// A protocol defined with `defprotocol`, which `extend-type` adds implementations to
+    Protocol(Shared<Protocol>),
+    /// A future or promise, whose value is delivered by another thread
+    #[cfg(feature = "sync")]
+    Promise(Shared<Promise>),
+    /// A core.async style channel, for passing values between go blocks
+    #[cfg(feature = "sync")]
+    Channel(Shared<Channel>),
+    /// Data with a tag, such as the result of reading `#inst "..."`, that prints back the same way
+    Tagged(String, Box<Value>),
     Nil,
 }
 
+/// A var that can be temporarily rebound with `binding`. Evaluating its symbol gives the
+/// innermost binding, or the root value when it isn't bound. Bindings belong to the thread that
+/// made them, so a `future` sees the root value rather than its parent's bindings.
+#[derive(Debug)]
+pub struct DynamicVar {
+    pub name: String,
+    root: Lock<Value>,
+    bindings: Lock<HashMap<ThreadId, Vec<Value>>>,
+}
+
+impl DynamicVar {
+    pub fn new(name: String, root: Value) -> Self {
+        DynamicVar { name, root: Lock::new(root), bindings: Lock::new(HashMap::new()) }
+    }
+
+    pub fn get(&self) -> Value {
+        match self.bindings.borrow().get(&thread::current().id()).and_then(|bindings| bindings.last()) {
+            Some(value) => value.clone(),
+            None => self.root.borrow().clone(),
+        }
+    }
+
+    pub fn set_root(&self, value: Value) {
+        *self.root.borrow_mut() = value;
+    }
+
+    pub fn push_binding(&self, value: Value) {
+        self.bindings.borrow_mut().entry(thread::current().id()).or_default().push(value);
+    }
+
+    pub fn pop_binding(&self) {
+        let mut bindings = self.bindings.borrow_mut();
+        let thread_id = thread::current().id();
+        if let Some(thread_bindings) = bindings.get_mut(&thread_id) {
+            thread_bindings.pop();
+            if thread_bindings.is_empty() {
+                bindings.remove(&thread_id);
+            }
+        }
+    }
+
+    /// Changes the innermost binding, as `set!` does. Returns false if the var isn't bound.
+    pub fn set_binding(&self, value: Value) -> bool {
+        match self.bindings.borrow_mut().get_mut(&thread::current().id()).and_then(|bindings| bindings.last_mut()) {
+            Some(binding) => {
+                *binding = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The mutable contents of a transient. Once `persistent!` is called, the collection is taken
+/// out of its cell, leaving `None` so that any further use can be detected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransientCollection {
+    Vector(sync::Vector<Value>),
+    HashMap(sync::HashTrieMap<HashableValue, Value>),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum HashableValue {
     Integer(i64),
+    Char(char),
     String(String),
+    Symbol(String),
     Keyword(String),
+    Boolean(bool),
+    Nil,
 }
 
 #[derive(Error, Debug)]
@@ -65,23 +227,58 @@ impl TryInto<HashableValue> for Value {
     fn try_into(self) -> Result<HashableValue, Self::Error> {
         match self {
             Value::Integer(num) => Ok(HashableValue::Integer(num)),
+            Value::Char(c) => Ok(HashableValue::Char(c)),
             Value::String(s) => Ok(HashableValue::String(s)),
+            Value::Symbol(s) => Ok(HashableValue::Symbol(s)),
             Value::Keyword(s) => Ok(HashableValue::Keyword(s)),
+            Value::Boolean(b) => Ok(HashableValue::Boolean(b)),
+            Value::Nil => Ok(HashableValue::Nil),
             _ => Err(HashValueError::UnhashableValue(self))
         }
     }
 }
 
+impl From<HashableValue> for Value {
+    fn from(value: HashableValue) -> Self {
+        match value {
+            HashableValue::Integer(num) => Value::Integer(num),
+            HashableValue::Char(c) => Value::Char(c),
+            HashableValue::String(s) => Value::String(s),
+            HashableValue::Symbol(s) => Value::Symbol(s),
+            HashableValue::Keyword(s) => Value::Keyword(s),
+            HashableValue::Boolean(b) => Value::Boolean(b),
+            HashableValue::Nil => Value::Nil,
+        }
+    }
+}
+
 impl PartialEq<Value> for Value {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Integer(num_l), Value::Integer(num_r)) => num_l == num_r,
+            (Value::Float(num_l), Value::Float(num_r)) => num_l == num_r,
+            (Value::Char(c_l), Value::Char(c_r)) => c_l == c_r,
             (Value::String(str_l), Value::String(str_r)) => str_l == str_r,
             (Value::Symbol(sym_l), Value::Symbol(sym_r)) => sym_l == sym_r,
             (Value::Keyword(kwd_l), Value::Keyword(kwd_r)) => kwd_l == kwd_r,
             (Value::Boolean(bool_l), Value::Boolean(bool_r)) => bool_l == bool_r,
             (Value::HashMap(map_l), Value::HashMap(map_r)) => map_l == map_r,
+            (Value::HashMap(_), _) | (_, Value::HashMap(_)) => false,
+            (Value::Set(set_l), Value::Set(set_r)) => set_l == set_r,
+            (Value::Set(_), _) | (_, Value::Set(_)) => false,
+            (Value::Record(record_l), Value::Record(record_r)) => record_l == record_r,
+            (Value::Record(_), _) | (_, Value::Record(_)) => false,
             (Value::Function(func_l), Value::Function(func_r)) => func_l == func_r,
+            (Value::Atom(atom_l), Value::Atom(atom_r)) => Shared::ptr_eq(atom_l, atom_r),
+            #[cfg(feature = "sync")]
+            (Value::Promise(promise_l), Value::Promise(promise_r)) => Shared::ptr_eq(promise_l, promise_r),
+            (Value::Transient(transient_l), Value::Transient(transient_r)) => Shared::ptr_eq(transient_l, transient_r),
+            (Value::DynamicVar(var_l), Value::DynamicVar(var_r)) => Shared::ptr_eq(var_l, var_r),
+            (Value::OutputPort(port_l), Value::OutputPort(port_r)) => port_l == port_r,
+            (Value::Protocol(protocol_l), Value::Protocol(protocol_r)) => Shared::ptr_eq(protocol_l, protocol_r),
+            #[cfg(feature = "sync")]
+            (Value::Channel(channel_l), Value::Channel(channel_r)) => Shared::ptr_eq(channel_l, channel_r),
+            (Value::Tagged(tag_l, value_l), Value::Tagged(tag_r, value_r)) => tag_l == tag_r && value_l == value_r,
             // Special case: Nil is treated as a sequence for everything except equality
             (Value::Nil, Value::Nil) => true,
             (Value::Nil, _) => false,
@@ -101,14 +298,68 @@ impl PartialEq<Value> for Value {
 }
 
 impl Value {
-    pub fn to_seq(self) -> Result<rpds::List<Value>, RuntimeError> {
+    /// Converts a collection into a list of its elements. Maps and records are converted into a
+    /// list of `[key value]` vectors.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_seq(self) -> Result<sync::List<Value>, RuntimeError> {
         match self {
             Value::List(values) => Ok(values),
             Value::Vector(values) => Ok(values.into_iter().cloned().collect()),
-            Value::Nil => Ok(rpds::List::new()),
+            Value::HashMap(pairs) => Ok(pairs.into_iter()
+                .map(|(key, value)| Value::Vector(sync::Vector::from_iter([key.clone().into(), value.clone()])))
+                .collect()),
+            Value::Set(elems) => Ok(elems.into_iter().map(|elem| elem.clone().into()).collect()),
+            Value::Record(record) => Ok(record.entries()
+                .map(|(key, value)| Value::Vector(sync::Vector::from_iter([key.clone().into(), value.clone()])))
+                .collect()),
+            Value::Nil => Ok(sync::List::default()),
             _ => Err(RuntimeError::IncorrectType(TypeError::NotASeq)),
         }
     }
+
+    /// The names that `type_name` gives values that aren't records, which a record can't be named.
+    /// `Object` isn't a type, but `extend-type` uses it for implementations that apply to every type.
+    pub const BUILTIN_TYPE_NAMES: &'static [&'static str] = &[
+        "Integer", "Float", "Char", "String", "Symbol", "Keyword", "Boolean", "List", "Vector", "HashMap",
+        "Set", "Function", "Atom", "Transient", "DynamicVar", "OutputPort", "Protocol", "Promise", "Channel",
+        "Tagged", "Nil", "Object",
+    ];
+
+    /// The name of the value's type, which protocols dispatch on and `extend-type` is given.
+    /// Records give their namespace-qualified name.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Integer(_) => "Integer",
+            Value::Float(_) => "Float",
+            Value::Char(_) => "Char",
+            Value::String(_) => "String",
+            Value::Symbol(_) => "Symbol",
+            Value::Keyword(_) => "Keyword",
+            Value::Boolean(_) => "Boolean",
+            Value::List(_) => "List",
+            Value::Vector(_) => "Vector",
+            Value::HashMap(_) => "HashMap",
+            Value::Set(_) => "Set",
+            Value::Record(record) => record.type_name(),
+            Value::Function(_) => "Function",
+            Value::Atom(_) => "Atom",
+            Value::Transient(_) => "Transient",
+            Value::DynamicVar(_) => "DynamicVar",
+            Value::OutputPort(_) => "OutputPort",
+            Value::Protocol(_) => "Protocol",
+            #[cfg(feature = "sync")]
+            Value::Promise(_) => "Promise",
+            #[cfg(feature = "sync")]
+            Value::Channel(_) => "Channel",
+            Value::Tagged(..) => "Tagged",
+            Value::Nil => "Nil",
+        }
+    }
+
+    /// Only `false` and `nil` are falsy, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Boolean(false) | Value::Nil)
+    }
 }
 
 #[cfg(test)]
@@ -118,16 +369,21 @@ mod tests {
     #[test]
     fn test_nil_equality() {
         assert_eq!(Value::Nil, Value::Nil);
-        assert_ne!(Value::Nil, Value::List(rpds::List::new()));
-        assert_ne!(Value::Vector(rpds::Vector::new()), Value::Nil);
+        assert_ne!(Value::Nil, Value::List(sync::List::default()));
+        assert_ne!(Value::Vector(sync::Vector::default()), Value::Nil);
     }
 
     #[test]
     fn test_to_seq() {
-        assert_eq!(Ok(rpds::List::new()), Value::Nil.to_seq());
+        assert_eq!(Ok(sync::List::default()), Value::Nil.to_seq());
 
         let list_elems = [Value::Integer(1), Value::String("foo".to_string())];
-        assert_eq!(Ok(rpds::List::from_iter(list_elems.clone())), Value::List(rpds::List::from_iter(list_elems.clone())).to_seq());
-        assert_eq!(Ok(rpds::List::from_iter(list_elems.clone())), Value::Vector(rpds::Vector::from_iter(list_elems.clone())).to_seq());
+        assert_eq!(Ok(sync::List::from_iter(list_elems.clone())), Value::List(sync::List::from_iter(list_elems.clone())).to_seq());
+        assert_eq!(Ok(sync::List::from_iter(list_elems.clone())), Value::Vector(sync::Vector::from_iter(list_elems.clone())).to_seq());
+
+        let map = Value::HashMap(sync::HashTrieMap::from_iter([(HashableValue::Keyword(":a".to_string()), Value::Integer(1))]));
+        let expected_pair = Value::Vector(sync::Vector::from_iter([Value::Keyword(":a".to_string()), Value::Integer(1)]));
+        assert_eq!(Ok(sync::List::from_iter([expected_pair.clone()])), map.clone().to_seq());
+        assert_ne!(map, Value::List(sync::List::from_iter([expected_pair])));
     }
 }
\ No newline at end of file
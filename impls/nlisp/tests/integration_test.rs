@@ -1,4 +1,7 @@
-use nlisp::{Env, rep, Result};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use nlisp::{Env, FromValue, Interpreter, IntoValue, Reader, ReadError, rep, rep_form, rep_pprint, Result, Value};
 
 #[test]
 fn test_parse_and_print_strings() -> Result<()> {
@@ -12,9 +15,336 @@ fn test_parse_and_print_strings() -> Result<()> {
 fn test_recursive_functions() -> Result<()> {
     let function_def_src = "(def! fib (fn* (N) (if (= N 0) 1 (if (= N 1) 1 (+ (fib (- N 1)) (fib (- N 2)))))))";
     let env = Env::default();
-    assert_eq!("(fn ...)\n", rep(function_def_src, &env)?);
+    assert_eq!("#<fn fib [N]>\n", rep(function_def_src, &env)?);
     assert_eq!("1\n", rep("(fib 0)", &env)?);
     assert_eq!("1\n", rep("(fib 1)", &env)?);
     assert_eq!("2\n", rep("(fib 2)", &env)?);
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_higher_order_sequence_functions() -> Result<()> {
+    let env = Env::default();
+    assert_eq!("(2 3 4)\n", rep("(map (fn* (x) (+ x 1)) [1 2 3])", &env)?);
+    assert_eq!("(3 4)\n", rep("(filter (fn* (x) (> x 2)) (list 1 2 3 4))", &env)?);
+    assert_eq!("6\n", rep("(reduce (fn* (acc x) (+ acc x)) 0 [1 2 3])", &env)?);
+    assert_eq!("([] [1] [1 2 3])\n", rep("(sort-by count [[1 2 3] [] [1]])", &env)?);
+    assert_eq!("([:a 1])\n", rep("(filter (fn* (entry) true) {:a 1})", &env)?);
+    assert_eq!("()\n", rep("(map (fn* (x) x) nil)", &env)?);
+    assert_eq!("10\n", rep("(apply + 4 [6])", &env)?);
+    Ok(())
+}
+
+#[test]
+fn test_transients() -> Result<()> {
+    let env = Env::default();
+    assert_eq!("[1 2 3]\n", rep("(persistent! (reduce conj! (transient []) (list 1 2 3)))", &env)?);
+    assert_eq!("2\n", rep("(count (conj! (transient [1]) 2))", &env)?);
+    rep("(def! t (transient {}))", &env)?;
+    assert_eq!("{:a 1}\n", rep("(persistent! (assoc! t :a 1))", &env)?);
+    assert!(rep("(assoc! t :b 2)", &env).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_destructuring() -> Result<()> {
+    let env = Env::default();
+    assert_eq!("[1 2 (3 4)]\n", rep("(let* [[a b & rest] [1 2 3 4]] [a b rest])", &env)?);
+    assert_eq!("3\n", rep("(let* [{:keys [x y] :or {y 2}} {:x 1}] (+ x y))", &env)?);
+    assert_eq!("7\n", rep("((fn* [[a b] {:keys [c]}] (+ a (+ b c))) [1 2] {:c 4})", &env)?);
+    assert_eq!("5\n", rep("((fn* [x & {:keys [step]}] (+ x step)) 2 :step 3)", &env)?);
+    assert_eq!("(1 2)\n", rep("(sort (map (fn* [[k v]] v) {:a 1 :b 2}))", &env)?);
+    assert!(rep("(let* [[a] 1] a)", &env).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_multi_arity_functions() -> Result<()> {
+    let env = Env::default();
+    rep("(def! add (fn* add ([] 0) ([x] x) ([x y] (+ x y)) ([x y & more] (add (+ x y) (apply add more)))))", &env)?;
+    assert_eq!("0\n", rep("(add)", &env)?);
+    assert_eq!("3\n", rep("(add 3)", &env)?);
+    assert_eq!("15\n", rep("(add 1 2 3 4 5)", &env)?);
+
+    // The self-name is bound inside the body without needing `def!`
+    assert_eq!("120\n", rep("((fn* fact [n] (if (< n 2) 1 (* n (fact (- n 1))))) 5)", &env)?);
+    // Multiple body expressions are wrapped in an implicit `do`
+    assert_eq!("2\n", rep("((fn* [x] (def! y x) (+ y 1)) 1)", &env)?);
+
+    let err = rep("((fn* f ([x] x) ([x y & more] x)))", &env).unwrap_err();
+    assert_eq!("wrong number of args (0) passed to f, expected one of: [x] [x y & more]", err.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_loop_recur() -> Result<()> {
+    let env = Env::default();
+    // Deep enough that it would overflow the stack without constant-space rebinding
+    assert_eq!("20000\n", rep("(loop [i 0] (if (< i 20000) (recur (+ i 1)) i))", &env)?);
+    assert_eq!("(3 2 1)\n", rep("(loop [[x & xs] [1 2 3] acc (list)] (if x (recur xs (apply list x acc)) acc))", &env)?);
+
+    rep("(def! sum-to (fn* [n acc] (if (= n 0) acc (recur (- n 1) (+ acc n)))))", &env)?;
+    assert_eq!("200010000\n", rep("(sum-to 20000 0)", &env)?);
+
+    let err = rep("(loop [i 0] (+ 1 (recur i)))", &env).unwrap_err();
+    assert_eq!("recur can only be used in tail position", err.to_string());
+    assert!(rep("(recur 1)", &env).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_namespaces() -> Result<()> {
+    let load_dir = std::env::temp_dir().join(format!("nlisp-namespaces-{}", std::process::id()));
+    std::fs::create_dir_all(load_dir.join("my_lib")).unwrap();
+    std::fs::create_dir_all(load_dir.join("other")).unwrap();
+    std::fs::write(load_dir.join("my_lib/util.mal"), "(ns my-lib.util)\n(def! helper (fn* [x] (* x 2)))").unwrap();
+    std::fs::write(load_dir.join("other/lib.mal"), "(ns other.lib (:require [my-lib.util :as u]))\n(def! helper (fn* [x] (u/helper (+ x 1))))").unwrap();
+
+    let env = Env::default();
+    env.add_load_path(load_dir.clone());
+
+    // Both libraries define `helper` without clobbering each other
+    rep("(require '[my-lib.util :as u :refer [helper]] '[other.lib :as o])", &env)?;
+    assert_eq!("4\n", rep("(u/helper 2)", &env)?);
+    assert_eq!("6\n", rep("(o/helper 2)", &env)?);
+    assert_eq!("10\n", rep("(helper 5)", &env)?);
+    assert_eq!("6\n", rep("(other.lib/helper 2)", &env)?);
+
+    // Definitions go into the current namespace
+    rep("(ns app.core (:require [other.lib :refer [helper]]))", &env)?;
+    rep("(def! local 1)", &env)?;
+    assert_eq!("4\n", rep("(helper local)", &env)?);
+    rep("(in-ns 'user)", &env)?;
+    assert!(rep("local", &env).is_err());
+    assert_eq!("1\n", rep("app.core/local", &env)?);
+
+    // `:reload` can go anywhere in a spec, or after the specs to reload all of them
+    std::fs::write(load_dir.join("my_lib/util.mal"), "(ns my-lib.util)\n(def! helper (fn* [x] (* x 3)))").unwrap();
+    rep("(require '[my-lib.util :reload :as u])", &env)?;
+    assert_eq!("6\n", rep("(u/helper 2)", &env)?);
+    std::fs::write(load_dir.join("my_lib/util.mal"), "(ns my-lib.util)\n(def! helper (fn* [x] (* x 4)))").unwrap();
+    rep("(require '[my-lib.util :as u] :reload)", &env)?;
+    assert_eq!("8\n", rep("(u/helper 2)", &env)?);
+
+    let err = rep("(require 'missing.lib)", &env).unwrap_err();
+    assert_eq!("could not find namespace `missing.lib` on the load path, expected a file named `missing/lib.mal`", err.to_string());
+
+    std::fs::remove_dir_all(load_dir).unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_dynamic_binding() -> Result<()> {
+    let env = Env::default();
+    rep("(def! ^:dynamic *indent* 0)", &env)?;
+    rep("(def! show (fn* [s] (str *indent* \":\" s)))", &env)?;
+
+    assert_eq!("\"2:a\"\n", rep("(binding [*indent* 2] (show \"a\"))", &env)?);
+    assert_eq!("\"0:a\"\n", rep("(show \"a\")", &env)?);
+
+    // Bindings nest, and are undone even when the body throws
+    assert_eq!("[4 2]\n", rep("(binding [*indent* 2] [(binding [*indent* 4] *indent*) *indent*])", &env)?);
+    assert_eq!("\"boom\"\n", rep("(try* (binding [*indent* 8] (throw \"boom\")) (catch* e e))", &env)?);
+    assert_eq!("0\n", rep("*indent*", &env)?);
+    Ok(())
+}
+
+#[test]
+fn test_pprint() -> Result<()> {
+    let env = Env::default();
+    rep("(def! data [[:alpha :beta :gamma] [:delta :epsilon :zeta]])", &env)?;
+    assert_eq!("[[:alpha :beta :gamma] [:delta :epsilon :zeta]]\n", rep_pprint("data", &env)?);
+    rep("(def! *print-right-margin* 30)", &env)?;
+    assert_eq!("[[:alpha :beta :gamma]\n [:delta :epsilon :zeta]]\n", rep_pprint("data", &env)?);
+    rep("(def! *print-length* 1)", &env)?;
+    assert_eq!("[[:alpha ...] ...]\n", rep_pprint("data", &env)?);
+    rep("(def! *print-level* 1)", &env)?;
+    assert_eq!("[# ...]\n", rep_pprint("data", &env)?);
+    Ok(())
+}
+
+#[test]
+fn test_print_atoms() -> Result<()> {
+    let env = Env::default();
+    rep("(def! a (atom (atom 1)))", &env)?;
+    assert_eq!("(atom (atom 1))\n", rep("a", &env)?);
+    assert!(rep("(binding [*print-atom-depth* 1] (pr-str a))", &env)?.starts_with("\"(atom #<atom 0x"));
+    assert!(rep("(binding [*print-atoms-opaque* true] (pr-str a))", &env)?.starts_with("\"#<atom 0x"));
+    Ok(())
+}
+
+#[test]
+fn test_print_functions() -> Result<()> {
+    let env = Env::default();
+    assert_eq!("#<builtin +>\n", rep("+", &env)?);
+    assert_eq!("#<fn add [a b]>\n", rep("(def! add (fn* [a b] (+ a b)))", &env)?);
+    assert_eq!("(fn* [a b] (+ a b))\n", rep("(source add)", &env)?);
+    assert_eq!("#<fn add [a b]>\n", rep("(let* [plus add] plus)", &env)?);
+    Ok(())
+}
+
+#[test]
+fn test_reader_dispatch() -> Result<()> {
+    let env = Env::default();
+    assert_eq!("(2 3 4)\n", rep("(map #(+ % 1) '(1 2 3))", &env)?);
+    assert_eq!("(1 (2 3))\n", rep("(#(list %1 %&) 1 2 3)", &env)?);
+    assert_eq!("3\n", rep("(+ 1 #_(throw \"ignored\") 2)", &env)?);
+    assert_eq!("\"nlisp\"\n", rep("#?(:rust2 \"rust2\" :nlisp \"nlisp\")", &env)?);
+    assert_eq!("true\n", rep("#t", &env)?);
+
+    assert_eq!("#inst \"2024-01-31T12:00:00Z\"\n", rep("#inst \"2024-01-31T12:00:00Z\"", &env)?);
+    assert!(rep("#inst \"2024-31-01\"", &env).is_err());
+    assert!(rep("#point [1 2]", &env).is_err());
+
+    rep("(add-reader-tag! 'point (fn* [[x y]] {:x x :y y}))", &env)?;
+    assert_eq!("[1 2]\n", rep("(let* [{:keys [x y]} #point [1 2]] [x y])", &env)?);
+    Ok(())
+}
+
+#[test]
+fn test_streaming_reader() -> Result<()> {
+    let env = Env::default();
+    let mut reader = Reader::new();
+    let mut outputs = vec![];
+    for chunk in ["(def! sq", "uare (fn* [x]\n", " (* x x))) (sq", "uare 12)", " \"done\"\n"] {
+        reader.feed(chunk.as_bytes()).unwrap();
+        loop {
+            match reader.next_expr() {
+                Ok(Some(expr)) => outputs.push(rep_form(expr, &env)?),
+                Ok(None) | Err(ReadError::Incomplete) => break,
+                Err(e) => panic!("unexpected syntax error: {}", e),
+            }
+        }
+    }
+    assert_eq!(vec!["#<fn square [x]>\n", "144\n", "\"done\"\n"], outputs);
+    Ok(())
+}
+
+#[test]
+fn test_edn() -> Result<()> {
+    let env = Env::default();
+    rep(r#"(def! data (edn/read-string "{:name \"nlisp\" :tags #{:lisp} :chars [\\a \\newline] :ratio 0.5}"))"#, &env)?;
+    assert_eq!("[\"nlisp\" [\\a \\newline]]\n", rep("(let* [{:keys [name chars]} data] [name chars])", &env)?);
+    assert_eq!("true\n", rep("(= data (edn/read-string (edn/write-string data)))", &env)?);
+    // The same data can be written as literals in code
+    assert_eq!("true\n", rep(r#"(= data {:name "nlisp" :tags #{:lisp} :chars [\a \newline] :ratio 0.5})"#, &env)?);
+    assert_eq!("nil\n", rep(r#"(edn/read-string "")"#, &env)?);
+
+    assert!(rep(r#"(edn/read-string "[1 ~x]")"#, &env).is_err());
+    assert!(rep("(edn/write-string [1 (atom 2)])", &env).is_err());
+    assert_eq!(
+        "\"invalid EDN at line 2, column 3: `@` is only valid in code, not in EDN data\"\n",
+        rep(r#"(try* (edn/read-string "[1\n  @x]") (catch* e e))"#, &env)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json() -> Result<()> {
+    let env = Env::default();
+    rep(r#"(def! config (json/parse "{\"name\": \"nlisp\", \"ports\": [80, 443], \"debug\": false, \"ratio\": 0.5}" {:keywordize-keys true}))"#, &env)?;
+    assert_eq!("[\"nlisp\" 443 false]\n", rep("(let* [{:keys [name ports debug]} config] [name (last ports) debug])", &env)?);
+    assert_eq!(
+        "\"{\\\"debug\\\":false,\\\"name\\\":\\\"nlisp\\\",\\\"ports\\\":[80,443],\\\"ratio\\\":0.5}\"\n",
+        rep("(json/stringify config {:sort-keys true})", &env)?
+    );
+    assert_eq!("\"{\\n  \\\"a\\\": [\\n    null\\n  ]\\n}\"\n", rep("(json/stringify {:a (list nil)} {:pretty true})", &env)?);
+
+    assert!(rep(r#"(json/parse "{\"a\": 1")"#, &env).is_err());
+    assert!(rep("(json/stringify [(atom 1)])", &env).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_process_builtins() -> Result<()> {
+    let env = Env::default();
+    assert_eq!(
+        "[0 \"hello\\n\" \"\"]\n",
+        rep("(let* [{:keys [exit out err]} (sh \"echo\" \"hello\")] [exit out err])", &env)?
+    );
+    assert_eq!("[3 \"in\" \"oops\\n\"]\n", rep(
+        "(let* [{:keys [exit out err]} (sh \"sh\" \"-c\" \"cat; echo oops >&2; exit 3\" :in \"in\")] [exit out err])",
+        &env,
+    )?);
+    assert_eq!("\"/\\n\"\n", rep("(let* [{:keys [out]} (sh \"pwd\" :dir \"/\")] out)", &env)?);
+    // Bigger than the pipe buffers, so the output has to be read while the input is written
+    env.insert("big".to_string(), Value::String("x".repeat(1 << 20)));
+    assert_eq!("true\n", rep("(let* [{:keys [out]} (sh \"cat\" :in big)] (= out big))", &env)?);
+    assert_eq!("nil\n", rep("(getenv \"NLISP_SURELY_UNSET_VARIABLE\")", &env)?);
+    // Variables that aren't valid UTF-8 are still read rather than panicking
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        std::env::set_var("NLISP_NOT_UTF8", std::ffi::OsString::from_vec(vec![b'a', 0xff]));
+        assert_eq!("\"a\u{fffd}\"\n", rep("(getenv \"NLISP_NOT_UTF8\")", &env)?);
+        assert_eq!("true\n", rep("(= (getenv \"NLISP_NOT_UTF8\") (get (getenv) \"NLISP_NOT_UTF8\"))", &env)?);
+    }
+
+    assert_eq!("\"caught\"\n", rep("(try* (sh \"nlisp-no-such-program\") (catch* e \"caught\"))", &env)?);
+    assert_eq!("\"caught\"\n", rep("(try* (slurp \"/nlisp/no/such/file\") (catch* e \"caught\"))", &env)?);
+    Ok(())
+}
+
+/// A writer that can still be read from after it's handed to an `Env`. It's thread-safe so that
+/// the tests also build with the `sync` feature.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn test_output_ports() -> Result<()> {
+    let env = Env::default();
+    let out = Captured::default();
+    let err = Captured::default();
+    env.set_out(out.clone());
+    env.set_err(err.clone());
+
+    rep("(do (prn \"a\" :b) (println \"c\" 1) (print \"d\") (flush))", &env)?;
+    rep("(binding [*out* *err*] (println \"to stderr\"))", &env)?;
+    assert_eq!("\"a\" :b\nc 1\nd", out.contents());
+    assert_eq!("to stderr\n", err.contents());
+
+    assert_eq!("\"x\\n[1 2]\"\n", rep("(with-out-str (println \"x\") (pr-str 0) (print [1 2]))", &env)?);
+    assert_eq!("\"inner\"\n", rep("(with-out-str (print (with-out-str (print \"inner\"))))", &env)?);
+    assert!(rep("(with-out-str (print 1) (undefined-fn))", &env).is_err());
+    rep("(print \"after\")", &env)?;
+    assert_eq!("\"a\" :b\nc 1\ndafter", out.contents());
+    Ok(())
+}
+
+#[test]
+fn test_embedded_interpreter() -> Result<()> {
+    let interpreter = Interpreter::new();
+    let alerts = Arc::new(Mutex::new(Vec::<String>::new()));
+    let sink = alerts.clone();
+    interpreter.register_fn("alert!", move |_env, args| {
+        for arg in args {
+            sink.lock().unwrap().push(String::from_value(arg)?);
+        }
+        Ok(Value::Nil)
+    });
+
+    interpreter.define("threshold", 100);
+    interpreter.eval("(def! check (fn* [reading] (if (> reading threshold) (alert! (str \"high: \" reading)) :ok)))")?;
+    assert_eq!(Value::Keyword(":ok".to_string()), interpreter.call("check", [50.into_value()])?);
+    interpreter.call("check", [150.into_value()])?;
+    assert_eq!(vec!["high: 150".to_string()], *alerts.lock().unwrap());
+    assert!(interpreter.eval("(alert! 1)").is_err());
+    Ok(())
+}
@@ -94,6 +94,12 @@ fn read_seq(rdr: &mut Reader, end: &str) -> MalRet {
         if token == end {
             break;
         }
+        if token == "#?" {
+            if let Some(form) = read_conditional(rdr)? {
+                seq.push(form)
+            }
+            continue;
+        }
         seq.push(read_form(rdr)?)
     }
     let _ = rdr.next();
@@ -139,18 +145,48 @@ fn read_form(rdr: &mut Reader) -> MalRet {
         "[" => read_seq(rdr, "]"),
         "}" => error("unexpected '}'"),
         "{" => read_seq(rdr, "}"),
+        "#?" => match read_conditional(rdr)? {
+            Some(form) => Ok(form),
+            // Nothing was selected, but a form is needed here, e.g. after '
+            None => error("expected a form, got a reader conditional with no matching feature"),
+        },
         _ => read_atom(rdr),
     }
 }
 
+// Reads a reader conditional like #?(:rust2 a :default b), returning the form for the first
+// feature that is :rust2 or :default, or None if there isn't one
+fn read_conditional(rdr: &mut Reader) -> Result<Option<MalVal>, MalErr> {
+    let _ = rdr.next();
+    let clauses = match read_form(rdr)? {
+        List(l, _) if l.len() % 2 == 0 => l,
+        _ => return Err(ErrString("reader conditional must be a list of feature/form pairs".to_string())),
+    };
+    for clause in clauses.chunks(2) {
+        match &clause[0] {
+            Str(s) if s == "\u{29e}rust2" || s == "\u{29e}default" => return Ok(Some(clause[1].clone())),
+            Str(s) if s.starts_with("\u{29e}") => {}
+            _ => return Err(ErrString("reader conditional features must be keywords".to_string())),
+        }
+    }
+    Ok(None)
+}
+
 pub fn read_str(str: String) -> MalRet {
     let tokens = tokenize(&str);
     //println!("tokens: {:?}", tokens);
-    if tokens.len() == 0 {
-        return error("no input");
-    }
-    read_form(&mut Reader {
+    let mut rdr = Reader {
         pos: 0,
         tokens: tokens,
-    })
+    };
+    // Top-level conditionals with no matching feature read as nothing, like a comment
+    while rdr.peek().ok().as_deref() == Some("#?") {
+        if let Some(form) = read_conditional(&mut rdr)? {
+            return Ok(form);
+        }
+    }
+    if rdr.pos == rdr.tokens.len() {
+        return error("no input");
+    }
+    read_form(&mut rdr)
 }
@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 //use std::collections::HashMap;
 use fnv::FnvHashMap;
 use itertools::Itertools;
@@ -11,6 +12,8 @@ extern crate fnv;
 extern crate itertools;
 extern crate regex;
 
+extern crate ctrlc;
+
 extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
@@ -27,6 +30,11 @@ use crate::env::{env_bind, env_find, env_get, env_new, env_set, env_sets, Env};
 #[macro_use]
 mod core;
 
+// Set by the Ctrl-C handler while evaluating, and checked by eval at
+// every function application and TCO loop iteration. It stays set until
+// the next line is evaluated, so try*/catch* can't swallow the interrupt.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 // read
 fn read(str: &str) -> MalRet {
     reader::read_str(str.to_string())
@@ -132,6 +140,9 @@ fn eval(mut ast: MalVal, mut env: Env) -> MalRet {
     let ret: MalRet;
 
     'tco: loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return error("interrupted");
+        }
         ret = match ast.clone() {
             List(l, _) => {
                 if l.len() == 0 {
@@ -362,6 +373,11 @@ fn main() {
         }
     }
 
+    // Ctrl-C during evaluation aborts back to the prompt, keeping repl_env
+    if let Err(e) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        eprintln!("Could not install Ctrl-C handler: {}", e);
+    }
+
     // main repl loop
     let _ = rep("(println (str \"Mal [\" *host-language* \"]\"))", &repl_env);
     loop {
@@ -371,6 +387,7 @@ fn main() {
                 rl.add_history_entry(&line);
                 rl.save_history(".mal-history").unwrap();
                 if line.len() > 0 {
+                    INTERRUPTED.store(false, Ordering::SeqCst);
                     match rep(&line, &repl_env) {
                         Ok(out) => println!("{}", out),
                         Err(e) => println!("Error: {}", format_error(e)),